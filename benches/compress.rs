@@ -0,0 +1,77 @@
+//! Compression speed, split into growing the tree (counting the symbols
+//! included) and compressing with its codes, for a small and a large
+//! alphabet.
+//!
+//! Run with `cargo bench --bench compress`.
+
+use huffman_coding::{ HuffmanCoding, HuffmanTree };
+
+use std::hint::black_box;
+use std::time::{ Duration, Instant };
+
+// the time `f` takes at best out of a few runs
+fn time(mut f: impl FnMut()) -> Duration {
+  let mut best = Duration::MAX;
+  for _ in 0..5 {
+    let start = Instant::now();
+    f();
+    best = best.min(start.elapsed());
+  }
+
+  return best;
+}
+
+fn report(name: &str, byte_count: usize, tree: Duration, compress: Duration) {
+  let megabytes = byte_count as f64 / 1_000_000.0;
+  println!(
+    "{name:<26} tree {:>9.2} MB/s   compress {:>9.2} MB/s",
+    megabytes / tree.as_secs_f64(),
+    megabytes / compress.as_secs_f64()
+  );
+}
+
+fn bench_bytes(name: &str, bytes: &[u8]) {
+  let tree = HuffmanTree::from_bytes(bytes);
+  let tree_time = time(|| {
+    black_box(HuffmanTree::from_bytes(black_box(bytes)));
+  });
+  let compress_time = time(|| {
+    black_box(HuffmanCoding::compress(black_box(bytes), &tree).unwrap());
+  });
+
+  report(name, bytes.len(), tree_time, compress_time);
+}
+
+fn bench_text(name: &str, text: &str) {
+  let tree = HuffmanTree::new(text);
+  let tree_time = time(|| {
+    black_box(HuffmanTree::new(black_box(text)));
+  });
+  let compress_time = time(|| {
+    black_box(HuffmanCoding::compress_str(black_box(text), &tree).unwrap());
+  });
+
+  report(name, text.len(), tree_time, compress_time);
+}
+
+// a pseudo random number generator, so the data is the same every run
+fn next(seed: &mut u64) -> u32 {
+  *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+  return (*seed >> 33) as u32;
+}
+
+fn main() {
+  let text = include_str!("../src/huffman_coding.rs").repeat(20);
+  bench_bytes("source code as bytes", text.as_bytes());
+  bench_text("source code as chars", &text);
+
+  // a few thousand distinct CJK chars, the lower ones far more often
+  let mut seed: u64 = 1;
+  let cjk: String = (0..300_000)
+    .map(|_| {
+      let rank = 4000 / (1 + next(&mut seed) % 4000);
+      char::from_u32(0x4e00 + rank * 5 + next(&mut seed) % 5).unwrap()
+    })
+    .collect();
+  bench_text("CJK text as chars", &cjk);
+}
@@ -0,0 +1,97 @@
+//! Decoding speed of the lookup table decoder against the linear search
+//! over every code after every bit that it replaced.
+//!
+//! Run with `cargo bench --bench decode`.
+
+use huffman_coding::{ CanonicalCodes, CharCodePair, Header, HuffmanCoding, HuffmanError, HuffmanTree };
+
+use std::hint::black_box;
+use std::time::{ Duration, Instant };
+
+// the decoder from before the lookup tables, with the symbols found by
+// searching all codes after every single bit
+fn decode_linear(b: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+  let (header, header_byte_count) = Header::from_bytes(b)?;
+
+  let mut code_lengths: Vec<(u8, u8)> = Vec::new();
+  for entry in &header.code_lengths {
+    code_lengths.push(entry.to_code_length()?);
+  }
+
+  let char_codes: Vec<CharCodePair<u8>> = CanonicalCodes::from_code_lengths(code_lengths)?;
+  let compressed_bytes = &b[header_byte_count..];
+
+  let mut result: Vec<u8> = Vec::new();
+  let mut current_code: u32 = 0;
+  let mut current_bits: u8 = 0;
+  for bit_index in 0..header.bits {
+    let byte = compressed_bytes[(bit_index / 8) as usize];
+    current_code = current_code << 1 | (byte >> (7 - bit_index % 8) & 1) as u32;
+    current_bits += 1;
+
+    let found = char_codes.iter()
+      .find(|pair| pair.bits == current_bits && pair.code == current_code);
+    if let Some(pair) = found {
+      result.push(pair.value);
+      current_code = 0;
+      current_bits = 0;
+    }
+  }
+
+  return Ok(result);
+}
+
+// the time `f` takes at best out of a few runs
+fn time(mut f: impl FnMut()) -> Duration {
+  let mut best = Duration::MAX;
+  for _ in 0..5 {
+    let start = Instant::now();
+    f();
+    best = best.min(start.elapsed());
+  }
+
+  return best;
+}
+
+fn bench(name: &str, bytes: &[u8]) {
+  let tree = HuffmanTree::from_bytes(bytes);
+  let compressed = HuffmanCoding::compress(bytes, &tree).unwrap();
+  assert_eq!(decode_linear(&compressed).unwrap(), bytes);
+
+  let linear = time(|| {
+    black_box(decode_linear(black_box(&compressed)).unwrap());
+  });
+  let table = time(|| {
+    black_box(HuffmanCoding::decompress(black_box(&compressed)).unwrap());
+  });
+
+  let megabytes = bytes.len() as f64 / 1_000_000.0;
+  println!(
+    "{name:<14} linear search {:>9.2} MB/s   lookup tables {:>9.2} MB/s   {:.1}x faster",
+    megabytes / linear.as_secs_f64(),
+    megabytes / table.as_secs_f64(),
+    linear.as_secs_f64() / table.as_secs_f64()
+  );
+}
+
+fn main() {
+  // text with a typical spread of letters
+  let text = include_str!("../src/huffman_coding.rs").repeat(20);
+  bench("source code", text.as_bytes());
+
+  // every byte value, some far more often than others, for long codes
+  let mut seed: u64 = 1;
+  let skewed: Vec<u8> = (0..1_000_000)
+    .map(|_| {
+      seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+      let n = (seed >> 33) as u32;
+      (n.trailing_zeros() * 8 + n % 8) as u8
+    })
+    .collect();
+  bench("skewed bytes", &skewed);
+
+  // all byte values but one as often, so nearly every code is 8 bits. with
+  // all 256 of them nothing would be saved and the bytes would be stored
+  let uniform: Vec<u8> = (0..1_000_000u32).map(|n| (n.wrapping_mul(2654435761) >> 24) as u8 % 255).collect();
+  bench("uniform bytes", &uniform);
+}
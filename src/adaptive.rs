@@ -0,0 +1,366 @@
+use crate::bits::{ BitWriter, SymbolReader };
+use crate::error::HuffmanError;
+use crate::symbol::Symbol;
+use crate::symbol_index::SymbolIndex;
+
+// A node of an AdaptiveHuffmanTree
+struct AdaptiveNode<T> {
+  // how often the symbols below this node have been seen
+  weight: u64,
+  parent: Option<usize>,
+  // the left and right child of a branch, None for a leaf
+  children: Option<[usize; 2]>,
+  // the symbol of a leaf, None for branches and the NYT leaf
+  value: Option<T>,
+}
+
+// A Huffman tree that changes with every symbol coded with it, following
+// Vitter's algorithm Λ from "Design and Analysis of Dynamic Huffman Codes".
+//
+// Symbols that haven't been seen yet go through the NYT ("not yet
+// transmitted") leaf of weight 0, followed by the symbol itself in
+// T::RAW_BITS bits. After every symbol, its leaf and all the nodes above it
+// get their weight increased by one, and nodes move around so the tree stays
+// a Huffman tree of the weights. Since the encoder and decoder make the same
+// changes after the same symbols, no tree has to be stored at all.
+//
+// The nodes are kept in order of Vitter's implicit numbering, from the root
+// down, so the highest number comes first. Going down that order the
+// weights never increase, siblings are next to each other, and of equal
+// weights the branches come before the leaves.
+pub struct AdaptiveHuffmanTree<T> {
+  nodes: Vec<AdaptiveNode<T>>,
+  // the nodes in order of their implicit number, highest first
+  order: Vec<usize>,
+  // where every node is in `order`
+  positions: Vec<usize>,
+  // the leaf of every symbol that has been seen
+  leaves: SymbolIndex<T>,
+  nyt: usize,
+  // the sides of the path to a leaf, from the leaf up
+  path: Vec<bool>,
+}
+
+impl<T: Symbol> AdaptiveHuffmanTree<T> {
+  // a tree of just the NYT leaf, before any symbol has been seen
+  pub fn new() -> AdaptiveHuffmanTree<T> {
+    return AdaptiveHuffmanTree {
+      nodes: vec![AdaptiveNode { weight: 0, parent: None, children: None, value: None }],
+      order: vec![0],
+      positions: vec![0],
+      leaves: SymbolIndex::new(),
+      nyt: 0,
+      path: Vec::new(),
+    };
+  }
+
+  // write the code of `symbol`, or the code of the NYT leaf and the symbol
+  // itself if it hasn't been seen yet, then update the tree. fails if the
+  // symbol doesn't fit in T::RAW_BITS bits
+  pub fn encode(&mut self, symbol: &T, writer: &mut BitWriter) -> Result<(), HuffmanError> {
+    let leaf = self.leaves.get(symbol);
+    self.write_path(leaf.unwrap_or(self.nyt), writer)?;
+
+    if leaf.is_none() {
+      let value = symbol.to_u32();
+      if (value as u64) >> T::RAW_BITS != 0 {
+        return Err(HuffmanError::InvalidSymbol(value));
+      }
+
+      writer.write(value, T::RAW_BITS)?;
+    }
+
+    self.update(symbol.clone());
+    return Ok(());
+  }
+
+  // write the code of the leaf `node`, the side of every node on the path
+  // from the root down to it
+  fn write_path(&mut self, node: usize, writer: &mut BitWriter) -> Result<(), HuffmanError> {
+    self.path.clear();
+    let mut current = node;
+    while let Some(parent) = self.nodes[current].parent {
+      self.path.push(self.side(current) == 1);
+      current = parent;
+    }
+
+    // up to 32 bits at a time from the root down, since codes can be longer
+    // than that
+    for bits in self.path.rchunks(32) {
+      let code = bits.iter().rev().fold(0u32, |code, bit| code << 1 | *bit as u32);
+      writer.write(code, bits.len() as u8)?;
+    }
+
+    return Ok(());
+  }
+
+  // the root of the tree
+  fn root(&self) -> usize {
+    return self.order[0];
+  }
+
+  // the child on the given `side` of the branch `node`, 0 for the left and
+  // 1 for the right, or None if `node` is a leaf
+  fn child(&self, node: usize, side: usize) -> Option<usize> {
+    return self.nodes[node].children.map(|children| children[side]);
+  }
+
+  // which side of its parent `node` is on, 0 for the left and 1 for the right
+  fn side(&self, node: usize) -> usize {
+    return match self.nodes[node].parent.and_then(|parent| self.nodes[parent].children) {
+      Some(children) if children[1] == node => 1,
+      _ => 0,
+    };
+  }
+
+  // add one more of `symbol` to the tree
+  fn update(&mut self, symbol: T) {
+    let (branch, leaf_to_increment) = match self.leaves.get(&symbol) {
+      Some(leaf) => {
+        // the leaf takes the place of the highest numbered leaf of its
+        // weight, so it can become heavier without breaking the order
+        let leader = self.leader(leaf);
+        if leader != leaf {
+          self.swap(leaf, leader);
+        }
+
+        if self.nodes[leaf].parent == self.nodes[self.nyt].parent {
+          // its parent is just as heavy, so the leaf would slide past it.
+          // increment the parent first and the leaf last
+          (self.nodes[leaf].parent, Some(leaf))
+        } else {
+          (Some(leaf), None)
+        }
+      },
+      None => {
+        // the NYT leaf becomes a branch with a new NYT leaf on the left and
+        // the leaf of the symbol on the right
+        let branch = self.nyt;
+        let leaf = self.add_node(branch, Some(symbol.clone()));
+        let nyt = self.add_node(branch, None);
+        self.nodes[branch].children = Some([nyt, leaf]);
+        self.leaves.insert(symbol, leaf);
+        self.nyt = nyt;
+
+        (Some(branch), Some(leaf))
+      },
+    };
+
+    let mut node = branch;
+    while let Some(current) = node {
+      node = self.slide_and_increment(current);
+    }
+
+    if let Some(leaf) = leaf_to_increment {
+      self.slide_and_increment(leaf);
+    }
+  }
+
+  // add a node of weight 0 below `parent`, at the end of the order
+  fn add_node(&mut self, parent: usize, value: Option<T>) -> usize {
+    let node = self.nodes.len();
+    self.nodes.push(AdaptiveNode { weight: 0, parent: Some(parent), children: None, value });
+    self.positions.push(self.order.len());
+    self.order.push(node);
+    return node;
+  }
+
+  // the highest numbered node of the same weight and kind as `node`
+  fn leader(&self, node: usize) -> usize {
+    let is_leaf = self.nodes[node].children.is_none();
+    let weight = self.nodes[node].weight;
+
+    let mut position = self.positions[node];
+    while position > 0 {
+      let other = &self.nodes[self.order[position - 1]];
+      if other.children.is_none() != is_leaf || other.weight != weight {
+        break;
+      }
+
+      position -= 1;
+    }
+
+    return self.order[position];
+  }
+
+  // increase the weight of `node`, which is the leader of its block, after
+  // sliding it past the nodes it's about to outweigh: the branches of its
+  // weight for a leaf, or the leaves of one more than its weight for a
+  // branch. returns the node that has to be incremented next
+  fn slide_and_increment(&mut self, node: usize) -> Option<usize> {
+    let is_leaf = self.nodes[node].children.is_none();
+    let weight = self.nodes[node].weight;
+    let former_parent = self.nodes[node].parent;
+
+    let position = self.positions[node];
+    let mut new_position = position;
+    while new_position > 0 {
+      let other = &self.nodes[self.order[new_position - 1]];
+      let in_block = if is_leaf {
+        other.children.is_some() && other.weight == weight
+      } else {
+        other.children.is_none() && other.weight == weight + 1
+      };
+
+      if !in_block {
+        break;
+      }
+
+      new_position -= 1;
+    }
+
+    if new_position < position {
+      self.slide(position, new_position);
+    }
+
+    self.nodes[node].weight += 1;
+
+    // a leaf slid past nodes of its old weight, so its new parent got
+    // heavier. a branch slid past nodes of its new weight, so the one that
+    // took its place left its former parent just as heavy as it is now
+    if is_leaf {
+      return self.nodes[node].parent;
+    } else {
+      return former_parent;
+    }
+  }
+
+  // move the node at `from` up to `to` in the order, and every node from
+  // `to` on down by one. every position keeps its place in the tree, so the
+  // nodes take over the places of the ones they moved to
+  fn slide(&mut self, from: usize, to: usize) {
+    let places: Vec<(Option<usize>, usize)> = self.order[to..=from].iter()
+      .map(|node| (self.nodes[*node].parent, self.side(*node)))
+      .collect();
+
+    self.order[to..=from].rotate_right(1);
+
+    for (i, place) in places.into_iter().enumerate() {
+      self.put(self.order[to + i], to + i, place);
+    }
+  }
+
+  // swap the places of the nodes `a` and `b` in the tree and the order
+  fn swap(&mut self, a: usize, b: usize) {
+    let place_a = (self.nodes[a].parent, self.side(a));
+    let place_b = (self.nodes[b].parent, self.side(b));
+    let position_a = self.positions[a];
+    let position_b = self.positions[b];
+
+    self.order[position_a] = b;
+    self.order[position_b] = a;
+    self.put(b, position_a, place_a);
+    self.put(a, position_b, place_b);
+  }
+
+  // put `node` at `position` in the order, and below the parent and on the
+  // side of the `place` in the tree
+  fn put(&mut self, node: usize, position: usize, place: (Option<usize>, usize)) {
+    let (parent, side) = place;
+    self.positions[node] = position;
+    self.nodes[node].parent = parent;
+    if let Some(parent) = parent {
+      if let Some(children) = &mut self.nodes[parent].children {
+        children[side] = node;
+      }
+    }
+  }
+}
+
+// Reads symbols back with an AdaptiveHuffmanTree, making the same changes
+// to it as the encoder made. The bits can come in any number of pieces.
+pub struct AdaptiveReader<T> {
+  tree: AdaptiveHuffmanTree<T>,
+  // how far down the tree the bits of the current symbol went
+  node: usize,
+  // the bits read of the current symbol, and of a new one written as it is
+  symbol_bits: u32,
+  raw_bits: u8,
+  raw_value: u32,
+}
+
+impl<T: Symbol> AdaptiveReader<T> {
+  pub fn new() -> AdaptiveReader<T> {
+    return AdaptiveReader {
+      tree: AdaptiveHuffmanTree::new(),
+      node: 0,
+      symbol_bits: 0,
+      raw_bits: 0,
+      raw_value: 0,
+    };
+  }
+
+  // go on with the current symbol with the next `bit`, adding it to
+  // `result` once it's whole
+  fn read_bit(&mut self, bit: usize, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    self.symbol_bits += 1;
+
+    if self.node == self.tree.nyt {
+      // a new symbol, written as it is
+      self.raw_value = self.raw_value << 1 | bit as u32;
+      self.raw_bits += 1;
+      if self.raw_bits == T::RAW_BITS {
+        let symbol = T::from_u32(self.raw_value)?;
+        return self.add_symbol(symbol, result);
+      }
+
+      return Ok(());
+    }
+
+    self.node = match self.tree.child(self.node, bit) {
+      Some(child) => child,
+      None => return Err(HuffmanError::InvalidCode),
+    };
+
+    if let Some(symbol) = &self.tree.nodes[self.node].value {
+      let symbol = symbol.clone();
+      return self.add_symbol(symbol, result);
+    }
+
+    return Ok(());
+  }
+
+  // add the whole `symbol` to `result` and the tree, and start on the next
+  fn add_symbol(&mut self, symbol: T, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    // a new symbol must not be one that has been seen already
+    if self.node == self.tree.nyt && self.tree.leaves.get(&symbol).is_some() {
+      return Err(HuffmanError::InvalidCode);
+    }
+
+    self.tree.update(symbol.clone());
+    result.push(symbol);
+
+    self.node = self.tree.root();
+    self.symbol_bits = 0;
+    self.raw_bits = 0;
+    self.raw_value = 0;
+    return Ok(());
+  }
+}
+
+impl<T: Symbol> SymbolReader<T> for AdaptiveReader<T> {
+  fn read_bytes(&mut self, bytes: &[u8], bits: u64, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    let mut bits_left = bits;
+    for byte in bytes {
+      for i in (0..8).rev() {
+        if bits_left == 0 {
+          return Ok(());
+        }
+
+        self.read_bit((byte >> i & 1) as usize, result)?;
+        bits_left -= 1;
+      }
+    }
+
+    return Ok(());
+  }
+
+  fn finish(&mut self, _result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    // the bits ended in the middle of a symbol
+    if self.symbol_bits != 0 {
+      return Err(HuffmanError::InvalidCode);
+    }
+
+    return Ok(());
+  }
+}
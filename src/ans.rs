@@ -0,0 +1,584 @@
+use crate::bits::{ BitBuffer, BitWriter, SymbolReader };
+use crate::char_frequency::CharFrequencyPair;
+use crate::error::HuffmanError;
+use crate::symbol::Symbol;
+use crate::symbol_index::SymbolIndex;
+
+use std::cmp::Reverse;
+use std::iter::repeat_n;
+use std::marker::PhantomData;
+
+// the number of bits of the total of the frequencies, unless there are too
+// many symbols for it
+const DEFAULT_TABLE_LOG: u8 = 12;
+
+// the fewest bits the total of the frequencies can have, below which the
+// symbols can't be spread over a tANS table
+const MIN_TABLE_LOG: u8 = 5;
+
+// the most bits the total of the frequencies can have
+const MAX_TABLE_LOG: u8 = 20;
+
+// the number of states of interleaved rANS, which take turns coding the
+// symbols
+const RANS_STATES: usize = 4;
+
+// the lowest value a rANS state can have between symbols. it's always below
+// 256 times that, so bytes move in and out of it whole
+const RANS_LOW: u64 = 1 << 23;
+
+// the frequencies of the `frequencies`, from the lowest symbol to the
+// highest, quantized so they add up to a power of two. every symbol keeps a
+// frequency of at least 1. fails if there are too many symbols for that
+pub fn quantize_frequencies<T: Symbol>(
+  frequencies: &[CharFrequencyPair<T>]
+) -> Result<Vec<CharFrequencyPair<T>>, HuffmanError> {
+  let mut result: Vec<CharFrequencyPair<T>> = frequencies.to_vec();
+  result.sort_by_key(|pair| pair.value.to_u32());
+  if result.is_empty() {
+    return Ok(result);
+  }
+
+  // at least twice as many shares as there are symbols
+  let symbol_bits = (u64::BITS - (result.len() as u64 - 1).leading_zeros()) as u8;
+  let table_log = DEFAULT_TABLE_LOG.max(symbol_bits + 1);
+  if table_log > MAX_TABLE_LOG {
+    return Err(HuffmanError::TooManySymbols);
+  }
+
+  let size: u64 = 1 << table_log;
+  let total: u128 = result.iter().map(|pair| pair.count as u128).sum();
+  for pair in &mut result {
+    let count = (pair.count as u128 * size as u128 + total / 2) / total;
+    pair.count = (count as u64).max(1);
+  }
+
+  // what's left over from rounding goes to the most common symbols, who
+  // feel it the least
+  let mut by_count: Vec<usize> = (0..result.len()).collect();
+  by_count.sort_by_key(|i| Reverse(result[*i].count));
+
+  let sum: u64 = result.iter().map(|pair| pair.count).sum();
+  if sum < size {
+    result[by_count[0]].count += size - sum;
+  } else {
+    // rounding adds at most 1 for every symbol, and there are at least
+    // twice as many shares as symbols, so there's enough above 1 to give up
+    let mut excess = sum - size;
+    for i in by_count {
+      let taken = excess.min(result[i].count - 1);
+      result[i].count -= taken;
+      excess -= taken;
+    }
+  }
+
+  return Ok(result);
+}
+
+// The quantized frequencies of the symbols, and where the share of every one
+// of them starts
+struct AnsModel<T> {
+  symbols: Vec<T>,
+  frequencies: Vec<u32>,
+  starts: Vec<u32>,
+  // the number of bits of the total of the frequencies
+  table_log: u8,
+}
+
+impl<T: Symbol> AnsModel<T> {
+  // the model of the `frequencies`, which must be quantized like
+  // quantize_frequencies quantizes them. fails if a frequency is 0 or they
+  // don't add up to a power of two of the sizes the tables can have
+  fn new(frequencies: Vec<CharFrequencyPair<T>>) -> Result<AnsModel<T>, HuffmanError> {
+    let mut model = AnsModel {
+      symbols: Vec::new(),
+      frequencies: Vec::new(),
+      starts: Vec::new(),
+      table_log: 0,
+    };
+
+    let mut total: u64 = 0;
+    for pair in frequencies {
+      if pair.count == 0 || pair.count > 1 << MAX_TABLE_LOG {
+        return Err(HuffmanError::BadHeader("invalid frequency"));
+      }
+
+      model.symbols.push(pair.value);
+      model.frequencies.push(pair.count as u32);
+      model.starts.push(total as u32);
+      total += pair.count;
+    }
+
+    if model.symbols.is_empty() {
+      return Ok(model);
+    }
+
+    model.table_log = total.trailing_zeros() as u8;
+    if !total.is_power_of_two() || model.table_log < MIN_TABLE_LOG || model.table_log > MAX_TABLE_LOG {
+      return Err(HuffmanError::BadHeader("frequencies don't add up to a table size"));
+    }
+
+    return Ok(model);
+  }
+
+  // the position of every symbol of the model in a tANS table, each as its
+  // index. the symbols are spread out over the table, so the states of
+  // every symbol are far apart
+  fn spread(&self) -> Vec<u32> {
+    if self.symbols.is_empty() {
+      return Vec::new();
+    }
+
+    let size: usize = 1 << self.table_log;
+    let mask = size - 1;
+    // odd, so every position is visited exactly once
+    let step = (size >> 1) + (size >> 3) + 3;
+
+    let mut spread: Vec<u32> = vec![0; size];
+    let mut position: usize = 0;
+    for (i, frequency) in self.frequencies.iter().enumerate() {
+      for _ in 0..*frequency {
+        spread[position] = i as u32;
+        position = (position + step) & mask;
+      }
+    }
+
+    return spread;
+  }
+
+  // the index of `symbol`. fails if it has no frequency
+  fn index_of(index: &SymbolIndex<T>, symbol: &T) -> Result<usize, HuffmanError> {
+    return match index.get(symbol) {
+      Some(i) => Ok(i),
+      None => Err(HuffmanError::SymbolNotInTree(symbol.to_u32())),
+    };
+  }
+}
+
+// Codes symbols with table-based asymmetric numeral systems (tANS). A single
+// state between the table size and twice that takes in every symbol, after
+// giving up as many of its lowest bits as the symbol's share makes room for.
+pub struct TansEncoder<T> {
+  model: AnsModel<T>,
+  index: SymbolIndex<T>,
+  // the state after every symbol, in the order of the model's starts and
+  // then of the symbol's positions in the table
+  states: Vec<u32>,
+}
+
+impl<T: Symbol> TansEncoder<T> {
+  // code symbols by the `frequencies`, quantized like quantize_frequencies
+  // quantizes them
+  pub fn new(frequencies: Vec<CharFrequencyPair<T>>) -> Result<TansEncoder<T>, HuffmanError> {
+    let model = AnsModel::new(frequencies)?;
+    let size: u32 = 1 << model.table_log;
+
+    let mut states: Vec<u32> = vec![0; size as usize];
+    let mut next: Vec<u32> = model.starts.clone();
+    for (position, i) in model.spread().into_iter().enumerate() {
+      states[next[i as usize] as usize] = size + position as u32;
+      next[i as usize] += 1;
+    }
+
+    return Ok(TansEncoder {
+      index: SymbolIndex::from_symbols(model.symbols.iter()),
+      model,
+      states,
+    });
+  }
+
+  // code the `symbols`. returns the bytes and the number of bits of them.
+  // fails if a symbol has no frequency
+  pub fn encode(&self, symbols: &[T]) -> Result<(Vec<u8>, u64), HuffmanError> {
+    let mut writer = BitWriter::new();
+    if symbols.is_empty() {
+      return Ok((Vec::new(), 0));
+    }
+
+    // the symbols are read back in the opposite order, so they're coded
+    // from the last one and the bits are turned around at the end
+    let table_log = self.model.table_log;
+    let size: u32 = 1 << table_log;
+    let mut state = size;
+    for symbol in symbols.iter().rev() {
+      let i = AnsModel::index_of(&self.index, symbol)?;
+      let frequency = self.model.frequencies[i];
+
+      // give up bits until the state is within the share of the symbol
+      let most_bits = table_log - frequency.ilog2() as u8;
+      let bits = if state >= frequency << most_bits { most_bits } else { most_bits - 1 };
+      write_reversed(&mut writer, state & ((1 << bits) - 1), bits)?;
+
+      let position = self.model.starts[i] + (state >> bits) - frequency;
+      state = self.states[position as usize];
+    }
+
+    write_reversed(&mut writer, state - size, table_log)?;
+
+    let bits = writer.bits();
+    return Ok((writer.finish_reversed(), bits));
+  }
+}
+
+// Codes symbols with range asymmetric numeral systems (rANS), with
+// RANS_STATES states that take turns, so the symbols of one don't have to
+// wait for the one before it while decoding.
+pub struct RansEncoder<T> {
+  model: AnsModel<T>,
+  index: SymbolIndex<T>,
+}
+
+impl<T: Symbol> RansEncoder<T> {
+  // code symbols by the `frequencies`, quantized like quantize_frequencies
+  // quantizes them
+  pub fn new(frequencies: Vec<CharFrequencyPair<T>>) -> Result<RansEncoder<T>, HuffmanError> {
+    let model = AnsModel::new(frequencies)?;
+    return Ok(RansEncoder { index: SymbolIndex::from_symbols(model.symbols.iter()), model });
+  }
+
+  // code the `symbols`, the one at `i` with the state at `i % RANS_STATES`.
+  // returns the bytes and the number of bits of them. fails if a symbol has
+  // no frequency
+  pub fn encode(&self, symbols: &[T]) -> Result<(Vec<u8>, u64), HuffmanError> {
+    let mut writer = BitWriter::new();
+    if symbols.is_empty() {
+      return Ok((Vec::new(), 0));
+    }
+
+    // coded from the last symbol, like with tANS
+    let table_log = self.model.table_log;
+    let mut states: [u64; RANS_STATES] = [RANS_LOW; RANS_STATES];
+    for (i, symbol) in symbols.iter().enumerate().rev() {
+      let index = AnsModel::index_of(&self.index, symbol)?;
+      let frequency = self.model.frequencies[index] as u64;
+      let state = &mut states[i % RANS_STATES];
+
+      // give up bytes until the state stays below 256 times RANS_LOW after
+      // taking in the symbol
+      let max_state = (RANS_LOW >> table_log << 8) * frequency;
+      while *state >= max_state {
+        write_reversed(&mut writer, (*state & 0xff) as u32, 8)?;
+        *state >>= 8;
+      }
+
+      *state = ((*state / frequency) << table_log) + *state % frequency + self.model.starts[index] as u64;
+    }
+
+    for state in states.iter().rev() {
+      write_reversed(&mut writer, *state as u32, 32)?;
+    }
+
+    let bits = writer.bits();
+    return Ok((writer.finish_reversed(), bits));
+  }
+}
+
+// The part of reading ANS coded symbols back that differs between tANS and
+// rANS
+pub trait AnsDecoder<T> {
+  // the number of states at the start of the bits
+  fn state_count(&self) -> usize;
+
+  // the most bits a single state or symbol takes up
+  fn max_bits(&self) -> u8;
+
+  // read the next state from the `buffer`
+  fn read_state(&mut self, buffer: &mut BitBuffer) -> Result<(), HuffmanError>;
+
+  // add the next symbol to `result` and read the bits the state gave up for
+  // it from the `buffer`
+  fn decode(&mut self, buffer: &mut BitBuffer, result: &mut Vec<T>) -> Result<(), HuffmanError>;
+
+  // whether the states are back where the encoder started them, as they
+  // are after the last symbol
+  fn is_done(&self) -> bool;
+
+  // the most symbols `bits` bits can hold
+  fn max_symbols(&self, bits: u64) -> u64;
+}
+
+// One entry of the decoding table of tANS, for a state
+#[derive(Clone, Copy)]
+struct TansEntry {
+  // the index of the symbol of the state
+  symbol: u32,
+  // the number of bits to read into the next state
+  bits: u8,
+  // the next state, before the bits are added to it
+  base: u32,
+}
+
+// Reads tANS coded symbols back
+pub struct TansDecoder<T> {
+  symbols: Vec<T>,
+  table: Vec<TansEntry>,
+  table_log: u8,
+  // the state, less the table size
+  state: u32,
+}
+
+impl<T: Symbol> TansDecoder<T> {
+  // read symbols coded by the `frequencies` of the header. fails if they
+  // aren't valid symbols or frequencies
+  pub fn new(frequencies: &[CharFrequencyPair<u32>]) -> Result<TansDecoder<T>, HuffmanError> {
+    let model = AnsModel::<T>::new(symbol_frequencies(frequencies)?)?;
+    let size: u32 = 1 << model.table_log;
+
+    let mut next: Vec<u32> = model.frequencies.clone();
+    let mut table: Vec<TansEntry> = Vec::new();
+    for i in model.spread() {
+      let state = next[i as usize];
+      next[i as usize] += 1;
+
+      let bits = model.table_log - state.ilog2() as u8;
+      table.push(TansEntry { symbol: i, bits, base: (state << bits) - size });
+    }
+
+    return Ok(TansDecoder { symbols: model.symbols, table, table_log: model.table_log, state: 0 });
+  }
+}
+
+impl<T: Symbol> AnsDecoder<T> for TansDecoder<T> {
+  fn state_count(&self) -> usize {
+    return 1;
+  }
+
+  fn max_bits(&self) -> u8 {
+    return self.table_log;
+  }
+
+  fn read_state(&mut self, buffer: &mut BitBuffer) -> Result<(), HuffmanError> {
+    self.state = buffer.take(self.table_log)?;
+    return Ok(());
+  }
+
+  fn decode(&mut self, buffer: &mut BitBuffer, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    let entry = match self.table.get(self.state as usize) {
+      Some(entry) => *entry,
+      None => return Err(HuffmanError::InvalidCode),
+    };
+
+    result.push(self.symbols[entry.symbol as usize].clone());
+    self.state = entry.base + buffer.take(entry.bits)?;
+    return Ok(());
+  }
+
+  fn is_done(&self) -> bool {
+    return self.state == 0;
+  }
+
+  fn max_symbols(&self, bits: u64) -> u64 {
+    if self.symbols.is_empty() {
+      return 0;
+    }
+
+    // a symbol takes up no bits only when its state is below the one it was
+    // read from, so there are never more of those in a row than there are
+    // entries of them
+    let free_entries = self.table.iter().filter(|entry| entry.bits == 0).count() as u64;
+    return bits.saturating_add(1).saturating_mul(free_entries + 1);
+  }
+}
+
+// Reads interleaved rANS coded symbols back
+pub struct RansDecoder<T> {
+  model: AnsModel<T>,
+  // the index of the symbol of every share of the total
+  slots: Vec<u32>,
+  states: [u64; RANS_STATES],
+  // the state of the next symbol, and the number of states read at the
+  // start
+  next: usize,
+}
+
+impl<T: Symbol> RansDecoder<T> {
+  // read symbols coded by the `frequencies` of the header. fails if they
+  // aren't valid symbols or frequencies
+  pub fn new(frequencies: &[CharFrequencyPair<u32>]) -> Result<RansDecoder<T>, HuffmanError> {
+    let model = AnsModel::<T>::new(symbol_frequencies(frequencies)?)?;
+
+    let mut slots: Vec<u32> = Vec::new();
+    for (i, frequency) in model.frequencies.iter().enumerate() {
+      slots.extend(repeat_n(i as u32, *frequency as usize));
+    }
+
+    return Ok(RansDecoder { model, slots, states: [RANS_LOW; RANS_STATES], next: 0 });
+  }
+}
+
+impl<T: Symbol> AnsDecoder<T> for RansDecoder<T> {
+  fn state_count(&self) -> usize {
+    return RANS_STATES;
+  }
+
+  fn max_bits(&self) -> u8 {
+    return 32;
+  }
+
+  fn read_state(&mut self, buffer: &mut BitBuffer) -> Result<(), HuffmanError> {
+    let state = buffer.take(32)? as u64;
+    if !(RANS_LOW..RANS_LOW << 8).contains(&state) {
+      return Err(HuffmanError::InvalidCode);
+    }
+
+    self.states[self.next] = state;
+    self.next = (self.next + 1) % RANS_STATES;
+    return Ok(());
+  }
+
+  fn decode(&mut self, buffer: &mut BitBuffer, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    let table_log = self.model.table_log;
+    let state = &mut self.states[self.next];
+    let slot = *state & ((1 << table_log) - 1);
+    let i = match self.slots.get(slot as usize) {
+      Some(i) => *i as usize,
+      None => return Err(HuffmanError::InvalidCode),
+    };
+    result.push(self.model.symbols[i].clone());
+
+    *state = self.model.frequencies[i] as u64 * (*state >> table_log) + slot - self.model.starts[i] as u64;
+    while *state < RANS_LOW {
+      *state = *state << 8 | buffer.take(8)? as u64;
+    }
+
+    self.next = (self.next + 1) % RANS_STATES;
+    return Ok(());
+  }
+
+  fn is_done(&self) -> bool {
+    return self.states.iter().all(|state| *state == RANS_LOW);
+  }
+
+  fn max_symbols(&self, bits: u64) -> u64 {
+    let most = match self.model.frequencies.iter().max() {
+      Some(most) => *most as u64,
+      None => return 0,
+    };
+
+    // a symbol takes a state down by the table size less its frequency for
+    // every time the table size fits in the state, which is at least 7/8 of
+    // the state over the table size for states of at least RANS_LOW. the
+    // states start up to 8 bits above RANS_LOW and end at it
+    let size = 1u64 << self.model.table_log;
+    let share = 1.0 - (size - most) as f64 / size as f64 * 7.0 / 8.0;
+    let state_bits = (RANS_STATES * 8) as f64;
+    return ((bits as f64 + state_bits) / -share.log2()) as u64 + RANS_STATES as u64;
+  }
+}
+
+// Reads the bits of a TansEncoder or RansEncoder back into symbols with the
+// matching decoder. The bits can come in any number of pieces.
+pub struct AnsReader<T, D> {
+  decoder: D,
+  // the number of states that haven't been read yet
+  states_left: usize,
+  // the number of symbols that haven't been read yet
+  symbols_left: u64,
+  buffer: BitBuffer,
+  phantom: PhantomData<T>,
+}
+
+impl<T: Symbol, D: AnsDecoder<T>> AnsReader<T, D> {
+  // read `symbol_count` symbols with the `decoder` from `bits` bits.
+  // fails if the bits can't hold that many symbols
+  pub fn new(decoder: D, symbol_count: u64, bits: u64) -> Result<AnsReader<T, D>, HuffmanError> {
+    if symbol_count > decoder.max_symbols(bits) {
+      return Err(HuffmanError::BadHeader("more symbols than the bits can hold"));
+    }
+
+    return Ok(AnsReader {
+      // there are no bits at all without symbols
+      states_left: if symbol_count > 0 { decoder.state_count() } else { 0 },
+      decoder,
+      symbols_left: symbol_count,
+      buffer: BitBuffer::new(),
+      phantom: PhantomData,
+    });
+  }
+
+  // read a state, or symbols until the bits of one are read. fails if
+  // there are bits after the last symbol
+  fn step(
+    decoder: &mut D,
+    states_left: &mut usize,
+    symbols_left: &mut u64,
+    buffer: &mut BitBuffer,
+    result: &mut Vec<T>
+  ) -> Result<(), HuffmanError> {
+    if *states_left > 0 {
+      *states_left -= 1;
+      return decoder.read_state(buffer);
+    }
+
+    let bits_before = buffer.len();
+    while buffer.len() == bits_before {
+      if *symbols_left == 0 {
+        return Err(HuffmanError::InvalidCode);
+      }
+
+      decoder.decode(buffer, result)?;
+      *symbols_left -= 1;
+    }
+
+    return Ok(());
+  }
+}
+
+impl<T: Symbol, D: AnsDecoder<T>> SymbolReader<T> for AnsReader<T, D> {
+  fn read_bytes(&mut self, bytes: &[u8], bits: u64, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    // with at least the bits of a state or symbol, there's enough for the
+    // next one
+    let decoder = &mut self.decoder;
+    let states_left = &mut self.states_left;
+    let symbols_left = &mut self.symbols_left;
+    return self.buffer.read_bytes(bytes, bits, decoder.max_bits(), |buffer| {
+      return Self::step(decoder, states_left, symbols_left, buffer, result);
+    });
+  }
+
+  fn finish(&mut self, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    while self.states_left > 0 {
+      self.states_left -= 1;
+      self.decoder.read_state(&mut self.buffer)?;
+    }
+
+    while self.symbols_left > 0 {
+      self.decoder.decode(&mut self.buffer, result)?;
+      self.symbols_left -= 1;
+    }
+
+    // the states end up where they started only when the bits are intact
+    if !self.buffer.is_empty() || !self.decoder.is_done() {
+      return Err(HuffmanError::InvalidCode);
+    }
+
+    return Ok(());
+  }
+}
+
+// the frequencies of the header as the symbols of type `T`. fails if a
+// symbol isn't valid, or there's only one, which takes up no bits at all
+fn symbol_frequencies<T: Symbol>(
+  frequencies: &[CharFrequencyPair<u32>]
+) -> Result<Vec<CharFrequencyPair<T>>, HuffmanError> {
+  if frequencies.len() == 1 {
+    return Err(HuffmanError::BadHeader("codec with a single symbol"));
+  }
+
+  let mut result: Vec<CharFrequencyPair<T>> = Vec::new();
+  for pair in frequencies {
+    result.push(CharFrequencyPair { value: T::from_u32(pair.value)?, count: pair.count });
+  }
+
+  return Ok(result);
+}
+
+// write the lowest `bits` bits of `value`, lowest of them first, so they
+// come out highest first once all the bits are turned around
+fn write_reversed(writer: &mut BitWriter, value: u32, bits: u8) -> Result<(), HuffmanError> {
+  if bits == 0 {
+    return Ok(());
+  }
+
+  return writer.write(value.reverse_bits() >> (32 - bits as u32), bits);
+}
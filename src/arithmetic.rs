@@ -0,0 +1,327 @@
+use crate::bits::{ BitWriter, SymbolReader };
+use crate::char_frequency::CharFrequencyPair;
+use crate::error::HuffmanError;
+use crate::symbol::Symbol;
+use crate::symbol_index::SymbolIndex;
+
+// the highest value of the 32 bit range
+const TOP: u64 = (1 << 32) - 1;
+
+// the first value of the upper half of the range
+const HALF: u64 = 1 << 31;
+
+// the first value of the second quarter of the range
+const QUARTER: u64 = 1 << 30;
+
+// the most the frequencies can add up to. the range is always wider than a
+// quarter, so every symbol keeps a share of it
+const MAX_TOTAL: u64 = 1 << 24;
+
+// the frequencies of the `frequencies`, from the lowest symbol to the
+// highest, scaled down so they add up to no more than MAX_TOTAL. every
+// symbol keeps a frequency of at least 1. fails if there are too many
+// symbols for that
+pub fn scale_frequencies<T: Symbol>(
+  frequencies: &[CharFrequencyPair<T>]
+) -> Result<Vec<CharFrequencyPair<T>>, HuffmanError> {
+  let symbol_count = frequencies.len() as u64;
+  if symbol_count >= MAX_TOTAL {
+    return Err(HuffmanError::TooManySymbols);
+  }
+
+  let total: u128 = frequencies.iter().map(|pair| pair.count as u128).sum();
+  let mut result: Vec<CharFrequencyPair<T>> = frequencies.to_vec();
+  if total > MAX_TOTAL as u128 {
+    // the shares are rounded down, which leaves room for the 1 the rarest
+    // symbols are rounded up to
+    for pair in &mut result {
+      let count = pair.count as u128 * (MAX_TOTAL - symbol_count) as u128 / total;
+      pair.count = (count as u64).max(1);
+    }
+  }
+
+  result.sort_by_key(|pair| pair.value.to_u32());
+  return Ok(result);
+}
+
+// The symbols of the frequencies and where the share of every one of them
+// starts
+struct ArithmeticModel<T> {
+  symbols: Vec<T>,
+  // the frequencies of the symbols before every symbol added up, and the
+  // total of all of them at the end
+  starts: Vec<u64>,
+}
+
+impl<T: Symbol> ArithmeticModel<T> {
+  // the model of the `frequencies`, which must be scaled like
+  // scale_frequencies scales them. fails if a frequency is 0 or they add up
+  // to more than MAX_TOTAL
+  fn new(frequencies: Vec<CharFrequencyPair<T>>) -> Result<ArithmeticModel<T>, HuffmanError> {
+    let mut symbols: Vec<T> = Vec::new();
+    let mut starts: Vec<u64> = vec![0];
+    for pair in frequencies {
+      if pair.count == 0 {
+        return Err(HuffmanError::BadHeader("symbol with a frequency of 0"));
+      }
+
+      let total = starts[starts.len() - 1].saturating_add(pair.count);
+      if total > MAX_TOTAL {
+        return Err(HuffmanError::BadHeader("frequencies add up to too much"));
+      }
+
+      symbols.push(pair.value);
+      starts.push(total);
+    }
+
+    return Ok(ArithmeticModel { symbols, starts });
+  }
+
+  fn total(&self) -> u64 {
+    return self.starts[self.starts.len() - 1];
+  }
+
+  // the most symbols `bits` bits can hold. every symbol narrows the range
+  // down to its share of it and 1 more, which for the most common symbol
+  // still takes up a part of a bit, and the reader looks 64 bits ahead
+  fn max_symbols(&self, bits: u64) -> u64 {
+    let most = match self.starts.windows(2).map(|pair| pair[1] - pair[0]).max() {
+      Some(most) => most,
+      None => return 0,
+    };
+
+    let share = most as f64 / self.total() as f64 + 1.0 / QUARTER as f64;
+    return ((bits as f64 + 64.0) / -share.log2()) as u64 + 1;
+  }
+}
+
+// The low and high end of the range of an arithmetic coder, both inclusive
+struct Range {
+  low: u64,
+  high: u64,
+}
+
+impl Range {
+  fn new() -> Range {
+    return Range { low: 0, high: TOP };
+  }
+
+  // narrow the range down to the share of the symbol at `i` of the `model`
+  fn narrow<T: Symbol>(&mut self, model: &ArithmeticModel<T>, i: usize) {
+    let width = self.high - self.low + 1;
+    self.high = self.low + width * model.starts[i + 1] / model.total() - 1;
+    self.low += width * model.starts[i] / model.total();
+  }
+
+  // whether the range is narrow enough that its next bit is known, or it
+  // sits around the middle, so it has to be widened before the next symbol
+  fn needs_shift(&self) -> bool {
+    return self.high < HALF || self.low >= HALF || (self.low >= QUARTER && self.high < HALF + QUARTER);
+  }
+
+  // widen the range to twice its width by moving its half or middle out.
+  // returns how far the range was moved down before that
+  fn shift(&mut self) -> u64 {
+    let offset = if self.high < HALF {
+      0
+    } else if self.low >= HALF {
+      HALF
+    } else {
+      QUARTER
+    };
+
+    self.low = (self.low - offset) << 1;
+    self.high = (self.high - offset) << 1 | 1;
+    return offset;
+  }
+}
+
+// Codes symbols with arithmetic coding, as a single number within the range
+// the shares of the symbols narrow down to.
+pub struct ArithmeticEncoder<T> {
+  model: ArithmeticModel<T>,
+  index: SymbolIndex<T>,
+  range: Range,
+  // the number of bits that come out opposite of the next bit, for a range
+  // that sat around the middle
+  pending_bits: u64,
+}
+
+impl<T: Symbol> ArithmeticEncoder<T> {
+  // code symbols by the `frequencies`, scaled like scale_frequencies scales
+  // them
+  pub fn new(frequencies: Vec<CharFrequencyPair<T>>) -> Result<ArithmeticEncoder<T>, HuffmanError> {
+    let model = ArithmeticModel::new(frequencies)?;
+    return Ok(ArithmeticEncoder {
+      index: SymbolIndex::from_symbols(model.symbols.iter()),
+      model,
+      range: Range::new(),
+      pending_bits: 0,
+    });
+  }
+
+  // write the bits the range of `symbol` settles. fails if it has no
+  // frequency
+  pub fn encode(&mut self, symbol: &T, writer: &mut BitWriter) -> Result<(), HuffmanError> {
+    let i = match self.index.get(symbol) {
+      Some(i) => i,
+      None => return Err(HuffmanError::SymbolNotInTree(symbol.to_u32())),
+    };
+
+    self.range.narrow(&self.model, i);
+    while self.range.needs_shift() {
+      if self.range.high < HALF {
+        self.write_bit(0, writer)?;
+      } else if self.range.low >= HALF {
+        self.write_bit(1, writer)?;
+      } else {
+        self.pending_bits += 1;
+      }
+
+      self.range.shift();
+    }
+
+    return Ok(());
+  }
+
+  // write the last bits, enough to pick a number within the range
+  pub fn finish(mut self, writer: &mut BitWriter) -> Result<(), HuffmanError> {
+    self.pending_bits += 1;
+    let bit = if self.range.low < QUARTER { 0 } else { 1 };
+    return self.write_bit(bit, writer);
+  }
+
+  // write `bit`, then the pending bits as the opposite of it
+  fn write_bit(&mut self, bit: u32, writer: &mut BitWriter) -> Result<(), HuffmanError> {
+    writer.write(bit, 1)?;
+
+    let pending = if bit == 0 { u32::MAX } else { 0 };
+    while self.pending_bits > 0 {
+      let bits = self.pending_bits.min(32);
+      writer.write(pending, bits as u8)?;
+      self.pending_bits -= bits;
+    }
+
+    return Ok(());
+  }
+}
+
+// Reads symbols back from the bits of an ArithmeticEncoder. The bits can
+// come in any number of pieces.
+pub struct ArithmeticReader<T> {
+  model: ArithmeticModel<T>,
+  range: Range,
+  // the 32 bits of the number that are within reach of the range
+  value: u64,
+  // how many of the first 32 bits are in `value` yet
+  value_bits: u8,
+  // the number of symbols that haven't been read yet
+  symbols_left: u64,
+}
+
+impl<T: Symbol> ArithmeticReader<T> {
+  // read `symbol_count` symbols coded by the `frequencies` of the header
+  // in `bits` bits. fails if they aren't valid symbols or frequencies, or
+  // the bits can't hold that many symbols
+  pub fn new(
+    frequencies: &[CharFrequencyPair<u32>],
+    symbol_count: u64,
+    bits: u64
+  ) -> Result<ArithmeticReader<T>, HuffmanError> {
+    let mut symbol_frequencies: Vec<CharFrequencyPair<T>> = Vec::new();
+    for pair in frequencies {
+      symbol_frequencies.push(CharFrequencyPair { value: T::from_u32(pair.value)?, count: pair.count });
+    }
+
+    if symbol_count > 0 && symbol_frequencies.is_empty() {
+      return Err(HuffmanError::BadHeader("symbols without frequencies"));
+    }
+
+    // the range never narrows with a single symbol, so it takes up no bits
+    // and nothing bounds how many of it there are
+    if symbol_frequencies.len() == 1 {
+      return Err(HuffmanError::BadHeader("codec with a single symbol"));
+    }
+
+    let model = ArithmeticModel::new(symbol_frequencies)?;
+    if symbol_count > model.max_symbols(bits) {
+      return Err(HuffmanError::BadHeader("more symbols than the bits can hold"));
+    }
+
+    return Ok(ArithmeticReader {
+      model,
+      range: Range::new(),
+      value: 0,
+      value_bits: 0,
+      symbols_left: symbol_count,
+    });
+  }
+
+  // take in the next `bit`, then read every symbol the range settles
+  fn read_bit(&mut self, bit: u8, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    if self.symbols_left == 0 {
+      return Ok(());
+    }
+
+    if self.value_bits < 32 {
+      self.value = self.value << 1 | bit as u64;
+      self.value_bits += 1;
+      if self.value_bits < 32 {
+        return Ok(());
+      }
+    } else {
+      // the number within the range moves along with it
+      let offset = self.range.shift();
+      self.value = (self.value - offset) << 1 | bit as u64;
+    }
+
+    while self.symbols_left > 0 && !self.range.needs_shift() {
+      // the bits only ever point outside the range when they're damaged
+      if self.value < self.range.low || self.value > self.range.high {
+        return Err(HuffmanError::InvalidCode);
+      }
+
+      let width = self.range.high - self.range.low + 1;
+      let share = ((self.value - self.range.low + 1) * self.model.total() - 1) / width;
+      let i = self.model.starts.partition_point(|start| *start <= share) - 1;
+
+      self.range.narrow(&self.model, i);
+      result.push(self.model.symbols[i].clone());
+      self.symbols_left -= 1;
+    }
+
+    return Ok(());
+  }
+}
+
+impl<T: Symbol> SymbolReader<T> for ArithmeticReader<T> {
+  fn read_bytes(&mut self, bytes: &[u8], bits: u64, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    let mut bits_left = bits;
+    for byte in bytes {
+      for i in (0..8).rev() {
+        if bits_left == 0 {
+          return Ok(());
+        }
+
+        self.read_bit(byte >> i & 1, result)?;
+        bits_left -= 1;
+      }
+    }
+
+    return Ok(());
+  }
+
+  fn finish(&mut self, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    // the encoder leaves out the 0 bits after the last ones it wrote, and
+    // the reader looks 32 bits ahead of it
+    for _ in 0..64 {
+      if self.symbols_left == 0 {
+        return Ok(());
+      }
+
+      self.read_bit(0, result)?;
+    }
+
+    return Err(HuffmanError::InvalidCode);
+  }
+}
@@ -0,0 +1,351 @@
+use crate::char_code::{ CharCodePair, MAX_CODE_BITS };
+use crate::error::HuffmanError;
+use crate::symbol::Symbol;
+
+// Writes codes into bytes, first bit of the code first, starting at the
+// highest bit of every byte. The full bytes can be taken out at any time,
+// so long data doesn't have to be held all at once.
+pub struct BitWriter {
+  // the full bytes that haven't been taken out yet
+  bytes: Vec<u8>,
+  // the bits that don't make up a whole byte yet, the last of them at the
+  // bottom
+  bit_buffer: u64,
+  buffered_bits: u8,
+  // the number of bits written so far
+  bits: u64,
+}
+
+impl BitWriter {
+  pub fn new() -> BitWriter {
+    return BitWriter {
+      bytes: Vec::new(),
+      bit_buffer: 0,
+      buffered_bits: 0,
+      bits: 0,
+    };
+  }
+
+  // write the lowest `bits` bits of `code`, highest of them first
+  pub fn write(&mut self, code: u32, bits: u8) -> Result<(), HuffmanError> {
+    if bits == 0 {
+      return Ok(());
+    }
+
+    self.bits = match self.bits.checked_add(bits as u64) {
+      Some(n) => n,
+      None => return Err(HuffmanError::LengthOverflow),
+    };
+
+    // fewer than 8 bits are buffered, so there's room for any code
+    let code = code & (u32::MAX >> (32 - bits as u32));
+    self.bit_buffer = self.bit_buffer << bits | code as u64;
+    self.buffered_bits += bits;
+
+    while self.buffered_bits >= 8 {
+      self.buffered_bits -= 8;
+      self.bytes.push((self.bit_buffer >> self.buffered_bits) as u8);
+    }
+
+    return Ok(());
+  }
+
+  // the number of bits written so far
+  pub fn bits(&self) -> u64 {
+    return self.bits;
+  }
+
+  // take out the full bytes written since the last time
+  pub fn take_bytes(&mut self) -> Vec<u8> {
+    return std::mem::take(&mut self.bytes);
+  }
+
+  // take out the rest of the bytes, with the last byte filled up with 0 bits
+  // if it's only partly written
+  pub fn finish(mut self) -> Vec<u8> {
+    if self.buffered_bits != 0 {
+      self.bytes.push((self.bit_buffer << (8 - self.buffered_bits)) as u8);
+    }
+
+    return self.bytes;
+  }
+
+  // take out all the bits written, last one first, with the last byte filled
+  // up with 0 bits. none of the bytes may have been taken out before
+  pub fn finish_reversed(self) -> Vec<u8> {
+    let padding = (self.bits.div_ceil(8) * 8 - self.bits) as u32;
+    let reversed: Vec<u8> = self.finish().iter().rev().map(|byte| byte.reverse_bits()).collect();
+    if padding == 0 {
+      return reversed;
+    }
+
+    // the 0 bits that filled up the last byte came first, move them to the
+    // end again
+    let mut result: Vec<u8> = Vec::with_capacity(reversed.len());
+    for (i, byte) in reversed.iter().enumerate() {
+      let next = reversed.get(i + 1).copied().unwrap_or(0);
+      result.push(byte << padding | next >> (8 - padding));
+    }
+
+    return result;
+  }
+}
+
+// Turns compressed bits back into symbols. The bits can come in any number
+// of pieces
+pub trait SymbolReader<T> {
+  // decode the first `bits` bits of `bytes` into `result`. the bits of a
+  // symbol that isn't whole yet are kept for the next bytes
+  fn read_bytes(&mut self, bytes: &[u8], bits: u64, result: &mut Vec<T>) -> Result<(), HuffmanError>;
+
+  // decode the bits that are left into `result`. fails if they don't make
+  // up whole symbols
+  fn finish(&mut self, result: &mut Vec<T>) -> Result<(), HuffmanError>;
+}
+
+// the number of bits the first table of a CodeTable looks up at once
+const ROOT_TABLE_BITS: u8 = 9;
+
+// the most bits any other table of a CodeTable looks up at once
+const SUB_TABLE_BITS: u8 = 8;
+
+// An entry of the lookup tables of a CodeTable, for the bits that index it
+#[derive(Clone, Copy)]
+enum TableEntry {
+  // no code starts with these bits
+  Invalid,
+  // the symbol at `index`, whose code is `bits` long counting from the bits
+  // of this table on
+  Symbol { index: u32, bits: u8 },
+  // the code goes on, look up the next `bits` bits in the table at `start`
+  Table { start: u32, bits: u8 },
+}
+
+// The bits a reader has taken in but not decoded yet
+pub struct BitBuffer {
+  // the first of the bits at the top
+  bits: u64,
+  count: u8,
+}
+
+impl BitBuffer {
+  pub fn new() -> BitBuffer {
+    return BitBuffer { bits: 0, count: 0 };
+  }
+
+  // take in the first `bits` bits of `bytes`, and call `decode` whenever at
+  // least `min_bits` of them are buffered, which should take out at least
+  // one bit
+  pub fn read_bytes(
+    &mut self,
+    bytes: &[u8],
+    bits: u64,
+    min_bits: u8,
+    mut decode: impl FnMut(&mut BitBuffer) -> Result<(), HuffmanError>
+  ) -> Result<(), HuffmanError> {
+    let mut bits_left = bits;
+    for byte in bytes {
+      if bits_left == 0 {
+        break;
+      }
+
+      // only part of the last byte may be in use, the other bits are 0
+      let bits_in_byte = bits_left.min(8) as u8;
+      let byte = *byte & (0xff00u16 >> bits_in_byte) as u8;
+      bits_left -= bits_in_byte as u64;
+
+      // fewer than min_bits are left after decoding, so there's room
+      self.bits |= (byte as u64) << (56 - self.count);
+      self.count += bits_in_byte;
+
+      while self.count >= min_bits {
+        decode(self)?;
+      }
+    }
+
+    return Ok(());
+  }
+
+  // whether there are no bits left
+  pub fn is_empty(&self) -> bool {
+    return self.count == 0;
+  }
+
+  // the number of bits that are left
+  pub fn len(&self) -> u8 {
+    return self.count;
+  }
+
+  // take out the next `bits` bits, up to 32 of them, the first of them as
+  // the highest. fails if fewer are left
+  pub fn take(&mut self, bits: u8) -> Result<u32, HuffmanError> {
+    if bits > self.count {
+      return Err(HuffmanError::InvalidCode);
+    }
+
+    if bits == 0 {
+      return Ok(0);
+    }
+
+    let value = (self.bits >> (64 - bits as u32)) as u32;
+    self.bits <<= bits;
+    self.count -= bits;
+    return Ok(value);
+  }
+}
+
+// Lookup tables that read codes back into symbols, like zlib's inflate.
+//
+// The first table is indexed by the next ROOT_TABLE_BITS bits, so codes up
+// to that long are found with a single lookup. Longer codes continue in
+// smaller tables of the codes that start with the same bits.
+pub struct CodeTable<T> {
+  symbols: Vec<T>,
+  // all tables, one after the other, starting with the first one
+  table: Vec<TableEntry>,
+  root_bits: u8,
+  // the length of the longest code
+  max_bits: u8,
+}
+
+impl<T: Symbol> CodeTable<T> {
+  // build the tables for the codes of `char_codes`. fails if a code is
+  // longer than MAX_CODE_BITS or doesn't fit in its length
+  pub fn new(char_codes: Vec<CharCodePair<T>>) -> Result<CodeTable<T>, HuffmanError> {
+    let mut symbols: Vec<T> = Vec::new();
+    let mut codes: Vec<(u32, u8, u32)> = Vec::new();
+    for pair in char_codes {
+      if pair.bits == 0 || pair.bits > MAX_CODE_BITS || (pair.code as u64) >> pair.bits != 0 {
+        return Err(HuffmanError::BadHeader("invalid code"));
+      }
+
+      codes.push((pair.code, pair.bits, symbols.len() as u32));
+      symbols.push(pair.value);
+    }
+
+    let max_bits = codes.iter().map(|code| code.1).max().unwrap_or(0);
+    let root_bits = max_bits.clamp(1, ROOT_TABLE_BITS);
+
+    let mut result = CodeTable {
+      symbols,
+      table: Vec::new(),
+      root_bits,
+      max_bits,
+    };
+
+    result.build_table(codes, root_bits);
+    return Ok(result);
+  }
+
+  // the length of the longest code
+  pub fn max_bits(&self) -> u8 {
+    return self.max_bits;
+  }
+
+  // look up the symbol of the code at the top of the `buffer` and drop its
+  // bits
+  pub fn decode(&self, buffer: &mut BitBuffer) -> Result<T, HuffmanError> {
+    let mut start: usize = 0;
+    let mut table_bits = self.root_bits;
+    loop {
+      let index = (buffer.bits >> (64 - table_bits as u32)) as usize;
+      match self.table[start + index] {
+        TableEntry::Symbol { index, bits } => {
+          // past the end of the bits, only 0 bits were looked up
+          if bits > buffer.count {
+            return Err(HuffmanError::InvalidCode);
+          }
+
+          buffer.bits <<= bits;
+          buffer.count -= bits;
+          return Ok(self.symbols[index as usize].clone());
+        },
+        TableEntry::Table { start: next_start, bits } => {
+          if table_bits > buffer.count {
+            return Err(HuffmanError::InvalidCode);
+          }
+
+          buffer.bits <<= table_bits;
+          buffer.count -= table_bits;
+          start = next_start as usize;
+          table_bits = bits;
+        },
+        TableEntry::Invalid => return Err(HuffmanError::InvalidCode),
+      }
+    }
+  }
+  // add a table indexed by `table_bits` bits for the `codes`, each a code,
+  // its length and the index of its symbol. codes longer than `table_bits`
+  // go on in tables of their own. returns where the table starts
+  fn build_table(&mut self, codes: Vec<(u32, u8, u32)>, table_bits: u8) -> u32 {
+    let start = self.table.len();
+    self.table.resize(start + (1 << table_bits), TableEntry::Invalid);
+
+    // the codes that are longer than this table, by the bits they start with
+    let mut longer_codes: Vec<Vec<(u32, u8, u32)>> = vec![Vec::new(); 1 << table_bits];
+    for (code, bits, index) in codes {
+      if bits <= table_bits {
+        // every entry that starts with the code
+        let first = (code as usize) << (table_bits - bits);
+        let count = 1 << (table_bits - bits);
+        for entry in &mut self.table[start + first..start + first + count] {
+          *entry = TableEntry::Symbol { index, bits };
+        }
+      } else {
+        let rest_bits = bits - table_bits;
+        let rest = code & (u32::MAX >> (32 - rest_bits as u32));
+        longer_codes[(code >> rest_bits) as usize].push((rest, rest_bits, index));
+      }
+    }
+
+    for (prefix, codes) in longer_codes.into_iter().enumerate() {
+      if codes.is_empty() {
+        continue;
+      }
+
+      // just big enough for the codes, so rare long codes don't blow up
+      // the size of the tables
+      let longest = codes.iter().map(|code| code.1).max().unwrap();
+      let needed = (usize::BITS - (codes.len() - 1).leading_zeros()) as u8;
+      let bits = longest.min(needed.clamp(1, SUB_TABLE_BITS));
+
+      let table_start = self.build_table(codes, bits);
+      self.table[start + prefix] = TableEntry::Table { start: table_start, bits };
+    }
+
+    return start as u32;
+  }
+}
+
+// Reads codes back into symbols with the lookup tables of a CodeTable. The
+// bits can come in any number of pieces.
+pub struct CodeReader<T> {
+  table: CodeTable<T>,
+  buffer: BitBuffer,
+}
+
+impl<T: Symbol> CodeReader<T> {
+  // read the codes of `char_codes`. fails like CodeTable::new
+  pub fn new(char_codes: Vec<CharCodePair<T>>) -> Result<CodeReader<T>, HuffmanError> {
+    return Ok(CodeReader { table: CodeTable::new(char_codes)?, buffer: BitBuffer::new() });
+  }
+}
+
+impl<T: Symbol> SymbolReader<T> for CodeReader<T> {
+  fn read_bytes(&mut self, bytes: &[u8], bits: u64, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    // with at least max_bits, there's enough for any code
+    let table = &self.table;
+    return self.buffer.read_bytes(bytes, bits, table.max_bits(), |buffer| {
+      result.push(table.decode(buffer)?);
+      return Ok(());
+    });
+  }
+
+  fn finish(&mut self, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    while !self.buffer.is_empty() {
+      result.push(self.table.decode(&mut self.buffer)?);
+    }
+
+    return Ok(());
+  }
+}
@@ -0,0 +1,37 @@
+use crate::char_code::MAX_CODE_BITS;
+use crate::checksum::ChecksumKind;
+
+/// The number of symbols in a block, unless stated otherwise
+pub const DEFAULT_BLOCK_SIZE: usize = 128 * 1024;
+
+/// The most bytes a block that goes through the Burrows-Wheeler transform
+/// can have, like the largest blocks of bzip2
+pub const BWT_BLOCK_SIZE: usize = 900_000;
+
+/// How `HuffmanCoding::encode_blocks` splits data into blocks, each with
+/// its own tree.
+///
+/// That keeps the codes short for data where some symbols are common in one
+/// part and rare in another, like source code followed by base64.
+pub struct BlockOptions {
+  /// The number of symbols in every block but the last one
+  pub block_size: usize,
+  /// Let a block use the codes of the block before it instead of its own
+  /// when that takes up fewer bytes
+  pub reuse_trees: bool,
+  /// The longest code in bits the tree of a block may have
+  pub max_code_length: u8,
+  /// The algorithm of the checksum of the original data, if any
+  pub checksum: Option<ChecksumKind>,
+}
+
+impl Default for BlockOptions {
+  fn default() -> BlockOptions {
+    return BlockOptions {
+      block_size: DEFAULT_BLOCK_SIZE,
+      reuse_trees: true,
+      max_code_length: MAX_CODE_BITS,
+      checksum: Some(ChecksumKind::Crc32),
+    };
+  }
+}
@@ -0,0 +1,87 @@
+use crate::char_code::{ CharCodePair, MAX_CODE_BITS };
+use crate::error::HuffmanError;
+use crate::merge_sort::MergeSort;
+use crate::nary_tree::{ digit_bits, MAX_ARITY, MIN_ARITY };
+use crate::symbol::Symbol;
+
+/// Canonical Huffman codes, where the codes follow from just their lengths.
+///
+/// The symbols are put in order of code length, then of the symbol itself,
+/// and each one gets the next free code of its length. So only the length of
+/// every symbol's code has to be stored to get the exact same codes back.
+pub struct CanonicalCodes;
+
+impl CanonicalCodes {
+  /// Give every symbol the canonical code of the given length. Fails if a
+  /// length is 0, longer than `MAX_CODE_BITS`, or if the lengths are too
+  /// short for every symbol to get its own code.
+  pub fn from_code_lengths<T: Symbol>(
+    code_lengths: Vec<(T, u8)>
+  ) -> Result<Vec<CharCodePair<T>>, HuffmanError> {
+    return Self::from_digit_lengths(code_lengths, 2);
+  }
+
+  /// Give every symbol the canonical code of the given number of digits,
+  /// for the codes of an NaryHuffmanTree of the given `arity`. Every digit
+  /// of the codes is written in `digit_bits(arity)` bits.
+  ///
+  /// Fails if a length is 0, the code would be longer than `MAX_CODE_BITS`
+  /// bits, or if the lengths are too short for every symbol to get its own
+  /// code. Fails with `HuffmanError::InvalidArity` if `arity` is below 2 or
+  /// above 256.
+  pub fn from_digit_lengths<T: Symbol>(
+    digit_lengths: Vec<(T, u8)>,
+    arity: u16
+  ) -> Result<Vec<CharCodePair<T>>, HuffmanError> {
+    if !(MIN_ARITY..=MAX_ARITY).contains(&arity) {
+      return Err(HuffmanError::InvalidArity(arity));
+    }
+
+    let digit_lengths = MergeSort::run(digit_lengths, |a, b| {
+      a.1 < b.1 || (a.1 == b.1 && a.0 <= b.0)
+    });
+
+    let digit_bits = digit_bits(arity);
+    let mut result: Vec<CharCodePair<T>> = Vec::new();
+    let mut code: u64 = 0;
+    let mut previous_digits: u8 = 0;
+    for (symbol, digits) in digit_lengths {
+      if digits == 0 || digits as u32 * digit_bits as u32 > MAX_CODE_BITS as u32 {
+        return Err(HuffmanError::BadHeader("invalid code length"));
+      }
+
+      // move on to the next free code, adding digits if this one is longer.
+      // it fits in 32 bits, so the number of codes of its length fits too
+      code *= (arity as u64).pow((digits - previous_digits) as u32);
+      if code >= (arity as u64).pow(digits as u32) {
+        // ran out of codes of this length
+        return Err(HuffmanError::BadHeader("code lengths don't form a prefix code"));
+      }
+
+      // every digit in its own bits, the first digit first
+      let mut bit_code: u64 = 0;
+      for i in (0..digits as u32).rev() {
+        let digit = code / (arity as u64).pow(i) % arity as u64;
+        bit_code = bit_code << digit_bits | digit;
+      }
+
+      result.push(CharCodePair::new(symbol, digits * digit_bits, bit_code as u32));
+
+      code += 1;
+      previous_digits = digits;
+    }
+
+    return Ok(result);
+  }
+
+  /// Swap the codes of `pairs` for canonical codes of the same lengths
+  pub fn from_char_code_pairs<T: Symbol>(
+    pairs: Vec<CharCodePair<T>>
+  ) -> Result<Vec<CharCodePair<T>>, HuffmanError> {
+    let code_lengths = pairs.into_iter()
+      .map(|pair| (pair.value, pair.bits))
+      .collect();
+
+    return Self::from_code_lengths(code_lengths);
+  }
+}
@@ -1,12 +1,19 @@
+/// The most bits a code can have, because `CharCodePair.code` is a `u32`
+pub const MAX_CODE_BITS: u8 = 32;
+
+/// A symbol (a char unless stated otherwise) and the Huffman code that
+/// represents it.
+///
+/// Only the lowest `bits` bits of `code` are used, most significant first.
 #[derive(Debug)]
-pub struct CharCodePair {
-  pub value: char,
+pub struct CharCodePair<T = char> {
+  pub value: T,
   pub bits: u8,
   pub code: u32,
 }
 
-impl CharCodePair {
-  pub fn new(value: char, bits: u8, code: u32) -> CharCodePair {
+impl<T> CharCodePair<T> {
+  pub fn new(value: T, bits: u8, code: u32) -> CharCodePair<T> {
     return CharCodePair {
       value,
       bits,
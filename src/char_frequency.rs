@@ -1,6 +1,7 @@
-// A pair that contains a char and it's frequency
-#[derive(Clone, Copy)]
-pub struct CharFrequencyPair {
-  pub value: char,
+/// A pair that contains a symbol (a char unless stated otherwise) and it's
+/// frequency
+#[derive(Debug, Clone, Copy)]
+pub struct CharFrequencyPair<T = char> {
+  pub value: T,
   pub count: u64,
 }
@@ -0,0 +1,245 @@
+use crate::error::HuffmanError;
+
+// the CRC-32 polynomial of zlib, PNG and gzip, with its bits reversed
+const CRC32_POLYNOMIAL: u32 = 0xedb8_8320;
+
+// the CRC-32 of every possible byte, so bytes are added in one lookup
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+  let mut table = [0u32; 256];
+  let mut byte = 0;
+  while byte < 256 {
+    let mut crc = byte as u32;
+    let mut bit = 0;
+    while bit < 8 {
+      if crc & 1 == 1 {
+        crc = (crc >> 1) ^ CRC32_POLYNOMIAL;
+      } else {
+        crc >>= 1;
+      }
+
+      bit += 1;
+    }
+
+    table[byte] = crc;
+    byte += 1;
+  }
+
+  return table;
+}
+
+/// The CRC-32 checksum (as in zlib, PNG and gzip) of bytes that may come in
+/// several pieces
+pub struct Crc32 {
+  crc: u32,
+}
+
+impl Crc32 {
+  /// Start a checksum of no bytes yet
+  pub fn new() -> Crc32 {
+    return Crc32 { crc: u32::MAX };
+  }
+
+  /// The checksum of all of `b` at once
+  pub fn checksum(b: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(b);
+    return crc.finish();
+  }
+
+  /// Add the bytes `b` to the checksum
+  pub fn update(&mut self, b: &[u8]) {
+    for byte in b {
+      let index = (self.crc ^ *byte as u32) & 0xff;
+      self.crc = (self.crc >> 8) ^ CRC32_TABLE[index as usize];
+    }
+  }
+
+  /// The checksum of all bytes added so far
+  pub fn finish(&self) -> u32 {
+    return !self.crc;
+  }
+}
+
+impl Default for Crc32 {
+  fn default() -> Crc32 {
+    return Crc32::new();
+  }
+}
+
+// the primes of xxHash32
+const XXH_PRIME_1: u32 = 0x9e37_79b1;
+const XXH_PRIME_2: u32 = 0x85eb_ca77;
+const XXH_PRIME_3: u32 = 0xc2b2_ae3d;
+const XXH_PRIME_4: u32 = 0x27d4_eb2f;
+const XXH_PRIME_5: u32 = 0x1656_67b1;
+
+/// The xxHash32 checksum (with a seed of 0) of bytes that may come in
+/// several pieces. Much faster than CRC-32 on large data
+pub struct XxHash32 {
+  // the four accumulators of the 16 byte stripes
+  accumulators: [u32; 4],
+  // the bytes of a stripe that isn't complete yet
+  buffer: [u8; 16],
+  buffered: usize,
+  total_length: u64,
+}
+
+impl XxHash32 {
+  /// Start a checksum of no bytes yet
+  pub fn new() -> XxHash32 {
+    return XxHash32 {
+      accumulators: [
+        XXH_PRIME_1.wrapping_add(XXH_PRIME_2),
+        XXH_PRIME_2,
+        0,
+        0u32.wrapping_sub(XXH_PRIME_1),
+      ],
+      buffer: [0; 16],
+      buffered: 0,
+      total_length: 0,
+    };
+  }
+
+  /// The checksum of all of `b` at once
+  pub fn checksum(b: &[u8]) -> u32 {
+    let mut hash = XxHash32::new();
+    hash.update(b);
+    return hash.finish();
+  }
+
+  /// Add the bytes `b` to the checksum
+  pub fn update(&mut self, mut b: &[u8]) {
+    self.total_length += b.len() as u64;
+
+    // fill up the stripe that was left over from last time
+    if self.buffered > 0 {
+      let taken = b.len().min(16 - self.buffered);
+      self.buffer[self.buffered..self.buffered + taken].copy_from_slice(&b[..taken]);
+      self.buffered += taken;
+      b = &b[taken..];
+
+      if self.buffered < 16 {
+        return;
+      }
+
+      let stripe = self.buffer;
+      self.add_stripe(&stripe);
+      self.buffered = 0;
+    }
+
+    while b.len() >= 16 {
+      self.add_stripe(&b[..16]);
+      b = &b[16..];
+    }
+
+    self.buffer[..b.len()].copy_from_slice(b);
+    self.buffered = b.len();
+  }
+
+  /// The checksum of all bytes added so far
+  pub fn finish(&self) -> u32 {
+    let mut hash = if self.total_length >= 16 {
+      let [a, b, c, d] = self.accumulators;
+      a.rotate_left(1)
+        .wrapping_add(b.rotate_left(7))
+        .wrapping_add(c.rotate_left(12))
+        .wrapping_add(d.rotate_left(18))
+    } else {
+      XXH_PRIME_5
+    };
+
+    // only the lowest 32 bits of the length are part of the hash
+    hash = hash.wrapping_add(self.total_length as u32);
+
+    let mut rest = &self.buffer[..self.buffered];
+    while rest.len() >= 4 {
+      let lane = u32::from_le_bytes(rest[..4].try_into().unwrap());
+      hash = hash.wrapping_add(lane.wrapping_mul(XXH_PRIME_3));
+      hash = hash.rotate_left(17).wrapping_mul(XXH_PRIME_4);
+      rest = &rest[4..];
+    }
+
+    for byte in rest {
+      hash = hash.wrapping_add((*byte as u32).wrapping_mul(XXH_PRIME_5));
+      hash = hash.rotate_left(11).wrapping_mul(XXH_PRIME_1);
+    }
+
+    // mix the bits so every input bit affects every output bit
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(XXH_PRIME_2);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(XXH_PRIME_3);
+    hash ^= hash >> 16;
+
+    return hash;
+  }
+
+  // mix a whole 16 byte stripe into the accumulators
+  fn add_stripe(&mut self, stripe: &[u8]) {
+    for (i, accumulator) in self.accumulators.iter_mut().enumerate() {
+      let lane = u32::from_le_bytes(stripe[i * 4..i * 4 + 4].try_into().unwrap());
+      *accumulator = accumulator
+        .wrapping_add(lane.wrapping_mul(XXH_PRIME_2))
+        .rotate_left(13)
+        .wrapping_mul(XXH_PRIME_1);
+    }
+  }
+}
+
+impl Default for XxHash32 {
+  fn default() -> XxHash32 {
+    return XxHash32::new();
+  }
+}
+
+/// The algorithms the checksum of the original data can be taken with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumKind {
+  Crc32 = 1,
+  XxHash32 = 2,
+}
+
+impl ChecksumKind {
+  /// get the ChecksumKind of the given number, as stored in the header
+  pub fn from_u8(kind: u8) -> Result<ChecksumKind, HuffmanError> {
+    return match kind {
+      1 => Ok(ChecksumKind::Crc32),
+      2 => Ok(ChecksumKind::XxHash32),
+      _ => Err(HuffmanError::BadHeader("unknown checksum algorithm")),
+    };
+  }
+}
+
+/// A checksum of the original data with any ChecksumKind
+pub enum Checksum {
+  Crc32(Crc32),
+  XxHash32(XxHash32),
+}
+
+impl Checksum {
+  /// Start a checksum of no bytes yet with the algorithm `kind`
+  pub fn new(kind: ChecksumKind) -> Checksum {
+    return match kind {
+      ChecksumKind::Crc32 => Checksum::Crc32(Crc32::new()),
+      ChecksumKind::XxHash32 => Checksum::XxHash32(XxHash32::new()),
+    };
+  }
+
+  /// Add the bytes `b` to the checksum
+  pub fn update(&mut self, b: &[u8]) {
+    match self {
+      Checksum::Crc32(crc) => crc.update(b),
+      Checksum::XxHash32(hash) => hash.update(b),
+    }
+  }
+
+  /// The checksum of all bytes added so far
+  pub fn finish(&self) -> u32 {
+    return match self {
+      Checksum::Crc32(crc) => crc.finish(),
+      Checksum::XxHash32(hash) => hash.finish(),
+    };
+  }
+}
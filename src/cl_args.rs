@@ -1,7 +1,7 @@
-use clap::Parser;
+use clap::{ Parser, ValueEnum };
 use std::path::PathBuf;
 
-// Simple program to compress and decompress text with Huffman coding
+// Simple program to compress and decompress files with Huffman coding
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
 pub struct ClArgs {
@@ -20,4 +20,105 @@ pub struct ClArgs {
   /// Output file of compression/decompression
   #[arg(short, long)]
   pub output: PathBuf,
+
+  /// Longest code in bits the compression may use, up to 32
+  #[arg(short = 'l', long)]
+  pub max_code_length: Option<u8>,
+
+  /// Split the file into blocks of this many bytes, each with its own tree
+  #[arg(short, long)]
+  pub block_size: Option<usize>,
+
+  /// Compress in a single pass with adaptive Huffman coding, without storing
+  /// a tree
+  #[arg(short, long, default_value_t = false, conflicts_with_all = ["max_code_length", "block_size"])]
+  pub adaptive: bool,
+
+  /// Code every byte with a table for the byte before it, which suits text
+  #[arg(long, default_value_t = false, conflicts_with_all = ["block_size", "adaptive"])]
+  pub contexts: bool,
+
+  /// Code the file as UTF-8 text a word at a time, with a dictionary of its
+  /// words. Reads the whole file into memory
+  #[arg(
+    short,
+    long,
+    default_value_t = false,
+    conflicts_with_all = ["max_code_length", "block_size", "adaptive", "contexts"]
+  )]
+  pub words: bool,
+
+  /// Codec that codes the bytes by their frequencies. Anything but huffman
+  /// reads the whole file into memory
+  #[arg(
+    long,
+    value_enum,
+    conflicts_with_all = ["max_code_length", "block_size", "adaptive", "contexts", "words"]
+  )]
+  pub codec: Option<CodecArg>,
+
+  /// How the code lengths are picked. Anything but huffman reads the whole
+  /// file into memory
+  #[arg(
+    long,
+    value_enum,
+    conflicts_with_all = ["max_code_length", "block_size", "adaptive", "contexts", "words", "codec"]
+  )]
+  pub code: Option<CodeArg>,
+
+  /// Number of different digits of the codes, from 2 to 256, like 3 for
+  /// ternary or 256 for codes of whole bytes. Reads the whole file into
+  /// memory
+  #[arg(
+    long,
+    conflicts_with_all = ["max_code_length", "block_size", "adaptive", "contexts", "words", "codec", "code"]
+  )]
+  pub arity: Option<u16>,
+
+  /// Run the bytes through the Burrows-Wheeler transform, move-to-front and
+  /// zero-run coding before Huffman coding them, like bzip2, a block of
+  /// 900 000 bytes at a time
+  #[arg(
+    short,
+    long,
+    default_value_t = false,
+    conflicts_with_all = ["max_code_length", "block_size", "adaptive", "contexts", "words", "codec", "code", "arity"]
+  )]
+  pub transform: bool,
+
+  /// Print how close the codes of every way to pick them come to the
+  /// entropy of the file
+  #[arg(long, default_value_t = false, requires = "compress")]
+  pub report: bool,
+
+  /// Checksum of the original data to store with the compressed data
+  #[arg(long, value_enum, default_value_t = ChecksumArg::Crc32)]
+  pub checksum: ChecksumArg,
+}
+
+// The checksum algorithms that can be picked on the command line
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ChecksumArg {
+  Crc32,
+  Xxhash32,
+  None,
+}
+
+// The codecs that can be picked on the command line
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CodecArg {
+  Huffman,
+  Arithmetic,
+  Tans,
+  Rans,
+  /// Whichever codec makes the file smallest
+  Best,
+}
+
+// The ways to pick code lengths that can be picked on the command line
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CodeArg {
+  Huffman,
+  ShannonFano,
+  Shannon,
 }
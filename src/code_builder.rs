@@ -0,0 +1,210 @@
+use crate::canonical::CanonicalCodes;
+use crate::char_code::{ CharCodePair, MAX_CODE_BITS };
+use crate::char_frequency::CharFrequencyPair;
+use crate::error::HuffmanError;
+use crate::huffman_coding::HuffmanCoding;
+use crate::huffman_tree::HuffmanTree;
+use crate::merge_sort::MergeSort;
+use crate::symbol::Symbol;
+use crate::symbol_index::SymbolIndex;
+
+use std::fmt;
+
+/// The ways a prefix code can be built from the frequencies of the symbols,
+/// to compare them with each other.
+///
+/// All of them give every symbol a code length, and the symbols get the
+/// canonical codes of those lengths, so `HuffmanCoding::encode_with_codes`
+/// codes with any of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeBuilder {
+  /// The codes of a HuffmanTree, which average the fewest bits any prefix
+  /// code can
+  Huffman,
+  /// Shannon-Fano coding, which splits the symbols from the most common to
+  /// the least in two halves of about the same total frequency, gives the
+  /// first half a 0 bit and the second a 1 bit, and goes on splitting both
+  /// halves
+  ShannonFano,
+  /// Shannon coding, which gives every symbol of probability `p` a code of
+  /// `ceil(-log2(p))` bits
+  Shannon,
+}
+
+impl CodeBuilder {
+  /// Every way to build codes there is
+  pub const ALL: [CodeBuilder; 3] = [CodeBuilder::Huffman, CodeBuilder::ShannonFano, CodeBuilder::Shannon];
+
+  /// Build the canonical codes of the symbols of the `frequencies`. A single
+  /// symbol gets a 1 bit code, like in a HuffmanTree.
+  ///
+  /// Fails with `HuffmanError::CodeTooLong` if a code would be longer than
+  /// `MAX_CODE_BITS`, which a symbol with a count of 0 would be for Shannon
+  /// coding.
+  pub fn build<T: Symbol>(
+    &self,
+    frequencies: &[CharFrequencyPair<T>]
+  ) -> Result<Vec<CharCodePair<T>>, HuffmanError> {
+    let code_lengths = match self {
+      CodeBuilder::Huffman => {
+        let tree = HuffmanTree::from_frequencies(frequencies.to_vec());
+        return HuffmanCoding::get_canonical_codes(&tree);
+      },
+      CodeBuilder::ShannonFano => shannon_fano_code_lengths(frequencies)?,
+      CodeBuilder::Shannon => shannon_code_lengths(frequencies)?,
+    };
+
+    return CanonicalCodes::from_code_lengths(code_lengths);
+  }
+}
+
+impl fmt::Display for CodeBuilder {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    return match self {
+      CodeBuilder::Huffman => write!(f, "Huffman"),
+      CodeBuilder::ShannonFano => write!(f, "Shannon-Fano"),
+      CodeBuilder::Shannon => write!(f, "Shannon"),
+    };
+  }
+}
+
+/// How close the codes of some symbols come to their entropy
+#[derive(Debug, Clone, Copy)]
+pub struct CodeReport {
+  /// The entropy of the frequencies, the fewest bits any code could spend
+  /// on a symbol on average
+  pub entropy: f64,
+  /// The bits the codes spend on a symbol on average
+  pub average_bits: f64,
+}
+
+impl CodeReport {
+  /// Compare the codes of `char_codes` for the symbols of the `frequencies`
+  /// with their entropy. Fails if one of the symbols has no code.
+  pub fn new<T: Symbol>(
+    frequencies: &[CharFrequencyPair<T>],
+    char_codes: &[CharCodePair<T>]
+  ) -> Result<CodeReport, HuffmanError> {
+    let index = SymbolIndex::from_symbols(char_codes.iter().map(|pair| &pair.value));
+    let total: u64 = frequencies.iter().map(|pair| pair.count).sum();
+
+    let mut entropy: f64 = 0.0;
+    let mut total_bits: f64 = 0.0;
+    for pair in frequencies {
+      let bits = HuffmanCoding::get_char_code_pair(&pair.value, char_codes, &index)?.bits;
+      total_bits += pair.count as f64 * bits as f64;
+
+      if pair.count > 0 {
+        let probability = pair.count as f64 / total as f64;
+        entropy -= probability * probability.log2();
+      }
+    }
+
+    let average_bits = if total > 0 { total_bits / total as f64 } else { 0.0 };
+    return Ok(CodeReport { entropy, average_bits });
+  }
+
+  /// The bits the codes spend on a symbol on average above the entropy
+  pub fn redundancy(&self) -> f64 {
+    return self.average_bits - self.entropy;
+  }
+
+  /// The entropy as a share of the bits the codes spend, 1 for codes that
+  /// can't be beaten
+  pub fn efficiency(&self) -> f64 {
+    if self.average_bits == 0.0 {
+      return 1.0;
+    }
+
+    return self.entropy / self.average_bits;
+  }
+}
+
+impl fmt::Display for CodeReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    return write!(
+      f,
+      "{:.4} bits per symbol, entropy {:.4}, {:.2}% efficient",
+      self.average_bits,
+      self.entropy,
+      self.efficiency() * 100.0
+    );
+  }
+}
+
+// the code length of every symbol of the `frequencies` with Shannon-Fano
+// coding. fails if a code would be longer than MAX_CODE_BITS
+fn shannon_fano_code_lengths<T: Symbol>(
+  frequencies: &[CharFrequencyPair<T>]
+) -> Result<Vec<(T, u8)>, HuffmanError> {
+  if frequencies.is_empty() {
+    return Ok(Vec::new());
+  }
+
+  let frequencies = MergeSort::run(frequencies.to_vec(), |a, b| {
+    a.count >= b.count
+  });
+
+  // the counts of all the symbols before every symbol added up, so the
+  // total of any run of them is a subtraction away
+  let mut totals: Vec<u64> = vec![0];
+  for pair in &frequencies {
+    totals.push(totals[totals.len() - 1] + pair.count);
+  }
+
+  let mut lengths: Vec<u8> = vec![1; frequencies.len()];
+  // the runs of symbols that are still to be split, and the length of
+  // their codes so far. a deep tree would be too much for recursion
+  let mut runs: Vec<(usize, usize, u8)> = vec![(0, frequencies.len(), 0)];
+  while let Some((start, end, bits)) = runs.pop() {
+    if end - start == 1 {
+      lengths[start] = bits.max(1);
+      continue;
+    }
+
+    if bits == MAX_CODE_BITS {
+      return Err(HuffmanError::CodeTooLong);
+    }
+
+    // the first split where the first half holds at least half of the run,
+    // or the one before it if that's closer to half
+    let total = totals[end] - totals[start];
+    let twice_first_half = |split: usize| (totals[split] - totals[start]) * 2;
+    let mut split = start + 1 + totals[start + 1..end].partition_point(|t| (t - totals[start]) * 2 < total);
+    if split == end {
+      split = end - 1;
+    } else if split > start + 1 && total - twice_first_half(split - 1) <= twice_first_half(split) - total {
+      split -= 1;
+    }
+
+    runs.push((start, split, bits + 1));
+    runs.push((split, end, bits + 1));
+  }
+
+  return Ok(frequencies.into_iter().map(|pair| pair.value).zip(lengths).collect());
+}
+
+// the code length of every symbol of the `frequencies` with Shannon coding,
+// the fewest bits `l` where `count * 2^l` is at least the total. fails if a
+// code would be longer than MAX_CODE_BITS
+fn shannon_code_lengths<T: Symbol>(
+  frequencies: &[CharFrequencyPair<T>]
+) -> Result<Vec<(T, u8)>, HuffmanError> {
+  let total: u64 = frequencies.iter().map(|pair| pair.count).sum();
+
+  let mut result: Vec<(T, u8)> = Vec::new();
+  for pair in frequencies {
+    if pair.count == 0 {
+      return Err(HuffmanError::CodeTooLong);
+    }
+
+    let bits = match total.div_ceil(pair.count).checked_next_power_of_two() {
+      Some(power) if power.trailing_zeros() <= MAX_CODE_BITS as u32 => power.trailing_zeros(),
+      _ => return Err(HuffmanError::CodeTooLong),
+    };
+
+    result.push((pair.value.clone(), (bits as u8).max(1)));
+  }
+
+  return Ok(result);
+}
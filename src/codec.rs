@@ -0,0 +1,92 @@
+use crate::ans::{ AnsReader, RansDecoder, TansDecoder };
+use crate::arithmetic::ArithmeticReader;
+use crate::bits::SymbolReader;
+use crate::error::HuffmanError;
+use crate::header::Header;
+use crate::symbol::Symbol;
+
+/// The ways the symbols can be coded by their frequencies, stated by the
+/// codec byte of the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+  /// Canonical Huffman codes, a whole number of bits for every symbol
+  Huffman = 0,
+  /// Arithmetic coding, which codes the whole data as a single number and
+  /// can spend a fraction of a bit on a symbol
+  Arithmetic = 1,
+  /// Table-based asymmetric numeral systems, about as small as arithmetic
+  /// coding and decoded with a table lookup for every symbol like Huffman
+  /// coding
+  Tans = 2,
+  /// Range asymmetric numeral systems with four interleaved states
+  Rans = 3,
+}
+
+impl Codec {
+  /// Every codec there is
+  pub const ALL: [Codec; 4] = [Codec::Huffman, Codec::Arithmetic, Codec::Tans, Codec::Rans];
+
+  /// get the Codec of the given number, as stored in the header
+  pub fn from_u8(codec: u8) -> Result<Codec, HuffmanError> {
+    return match codec {
+      0 => Ok(Codec::Huffman),
+      1 => Ok(Codec::Arithmetic),
+      2 => Ok(Codec::Tans),
+      3 => Ok(Codec::Rans),
+      _ => Err(HuffmanError::BadHeader("unknown codec")),
+    };
+  }
+}
+
+// Reads back the symbols of a header with FLAG_CODEC, with the reader of its
+// codec
+pub(crate) enum CodecReader<T> {
+  Arithmetic(ArithmeticReader<T>),
+  Tans(AnsReader<T, TansDecoder<T>>),
+  Rans(AnsReader<T, RansDecoder<T>>),
+}
+
+impl<T: Symbol> CodecReader<T> {
+  // the reader of the codec of the `header`. fails if its frequencies aren't
+  // valid for it, its bits can't hold its symbols, or it's coded with
+  // Huffman codes
+  pub(crate) fn new(header: &Header) -> Result<CodecReader<T>, HuffmanError> {
+    let frequencies = &header.frequencies;
+    return match header.codec {
+      Codec::Arithmetic => Ok(CodecReader::Arithmetic(ArithmeticReader::new(
+        frequencies,
+        header.symbol_count,
+        header.bits
+      )?)),
+      Codec::Tans => Ok(CodecReader::Tans(AnsReader::new(
+        TansDecoder::new(frequencies)?,
+        header.symbol_count,
+        header.bits
+      )?)),
+      Codec::Rans => Ok(CodecReader::Rans(AnsReader::new(
+        RansDecoder::new(frequencies)?,
+        header.symbol_count,
+        header.bits
+      )?)),
+      Codec::Huffman => Err(HuffmanError::BadHeader("Huffman codes have no frequencies")),
+    };
+  }
+}
+
+impl<T: Symbol> SymbolReader<T> for CodecReader<T> {
+  fn read_bytes(&mut self, bytes: &[u8], bits: u64, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    return match self {
+      CodecReader::Arithmetic(reader) => reader.read_bytes(bytes, bits, result),
+      CodecReader::Tans(reader) => reader.read_bytes(bytes, bits, result),
+      CodecReader::Rans(reader) => reader.read_bytes(bytes, bits, result),
+    };
+  }
+
+  fn finish(&mut self, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    return match self {
+      CodecReader::Arithmetic(reader) => reader.finish(result),
+      CodecReader::Tans(reader) => reader.finish(result),
+      CodecReader::Rans(reader) => reader.finish(result),
+    };
+  }
+}
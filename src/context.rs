@@ -0,0 +1,295 @@
+use crate::bits::{ BitBuffer, BitWriter, CodeTable, SymbolReader };
+use crate::char_code::CharCodePair;
+use crate::char_frequency::CharFrequencyPair;
+use crate::checksum::ChecksumKind;
+use crate::error::HuffmanError;
+use crate::header::{ write_contexts, ContextCodeLengths, Header };
+use crate::huffman_coding::HuffmanCoding;
+use crate::huffman_tree::HuffmanTree;
+use crate::metadata::CodeLengthEntry;
+use crate::symbol::Symbol;
+use crate::symbol_index::SymbolIndex;
+
+// How often every symbol comes right after every other symbol
+pub struct ContextFrequencies<T> {
+  // the first symbol, the only one that comes after none
+  pub first: Option<T>,
+  // every symbol that's followed by another, and the frequencies of the
+  // symbols that follow it
+  pub contexts: Vec<(T, Vec<CharFrequencyPair<T>>)>,
+}
+
+impl<T: Symbol> ContextFrequencies<T> {
+  // count the symbols that follow every symbol of `symbols`
+  pub fn count(symbols: &[T]) -> ContextFrequencies<T> {
+    let mut contexts: Vec<(T, Vec<CharFrequencyPair<T>>, SymbolIndex<T>)> = Vec::new();
+    let mut index: SymbolIndex<T> = SymbolIndex::new();
+    for pair in symbols.windows(2) {
+      let i = match index.get(&pair[0]) {
+        Some(i) => i,
+        None => {
+          index.insert(pair[0].clone(), contexts.len());
+          contexts.push((pair[0].clone(), Vec::new(), SymbolIndex::new()));
+          contexts.len() - 1
+        },
+      };
+
+      let (_, frequencies, frequency_index) = &mut contexts[i];
+      add_frequency(frequencies, frequency_index, &pair[1], 1);
+    }
+
+    return ContextFrequencies {
+      first: symbols.first().cloned(),
+      contexts: contexts.into_iter().map(|(value, frequencies, _)| (value, frequencies)).collect(),
+    };
+  }
+}
+
+// The codes of an order-1 context model, where every symbol is coded with
+// the table of the symbol before it.
+//
+// A context only gets a table of its own when that table and the codes of
+// the symbols after it take up fewer bits than those symbols would with
+// the codes of a tree of all the symbols. The rare contexts that don't pay
+// off share the fallback table instead, which also codes the first symbol.
+pub struct ContextCodes<T> {
+  // the fallback table first, then the table of every context that has one
+  tables: Vec<Vec<CharCodePair<T>>>,
+  // where the code of every symbol is in each of the tables
+  indexes: Vec<SymbolIndex<T>>,
+  // which of the tables codes the symbols after every context that has one
+  context_index: SymbolIndex<T>,
+  // the symbols of the contexts that have a table, in the order of `tables`
+  contexts: Vec<T>,
+  // the number of bits the symbols of the frequencies take up
+  bits: u64,
+}
+
+impl<T: Symbol> ContextCodes<T> {
+  // pick the contexts of the `frequencies` that get a table of their own and
+  // grow the trees of all the tables, none of them with codes longer than
+  // `max_code_length`. fails if the maximum code length is too short
+  pub fn from_frequencies(
+    frequencies: ContextFrequencies<T>,
+    max_code_length: u8
+  ) -> Result<ContextCodes<T>, HuffmanError> {
+    // the codes every context is compared against, as if there were none
+    let mut all: Vec<CharFrequencyPair<T>> = Vec::new();
+    let mut all_index: SymbolIndex<T> = SymbolIndex::new();
+    if let Some(first) = &frequencies.first {
+      add_frequency(&mut all, &mut all_index, first, 1);
+    }
+
+    for (_, context_frequencies) in &frequencies.contexts {
+      for pair in context_frequencies {
+        add_frequency(&mut all, &mut all_index, &pair.value, pair.count);
+      }
+    }
+
+    let all_codes = codes_of(all, max_code_length)?;
+
+    let mut fallback: Vec<CharFrequencyPair<T>> = Vec::new();
+    let mut fallback_index: SymbolIndex<T> = SymbolIndex::new();
+    if let Some(first) = &frequencies.first {
+      add_frequency(&mut fallback, &mut fallback_index, first, 1);
+    }
+
+    let mut result = ContextCodes {
+      tables: vec![Vec::new()],
+      indexes: vec![SymbolIndex::new()],
+      context_index: SymbolIndex::new(),
+      contexts: Vec::new(),
+      bits: 0,
+    };
+
+    for (value, context_frequencies) in frequencies.contexts {
+      let char_codes = codes_of(context_frequencies.clone(), max_code_length)?;
+      let own_bits = count_bits(&context_frequencies, &char_codes)?;
+      let shared_bits = count_bits(&context_frequencies, &all_codes)?;
+
+      // the table is compared by what it adds to the header
+      let mut table_bytes: Vec<u8> = Vec::new();
+      write_contexts(&mut table_bytes, &[ContextCodeLengths {
+        value: value.to_u32(),
+        code_lengths: char_codes.iter().map(CodeLengthEntry::new).collect(),
+      }]);
+
+      if (table_bytes.len() as u64 * 8).saturating_add(own_bits) < shared_bits {
+        result.add_table(Some(value), char_codes);
+        result.bits = add_bits(result.bits, own_bits)?;
+      } else {
+        for pair in &context_frequencies {
+          add_frequency(&mut fallback, &mut fallback_index, &pair.value, pair.count);
+        }
+      }
+    }
+
+    let fallback_codes = codes_of(fallback.clone(), max_code_length)?;
+    result.bits = add_bits(result.bits, count_bits(&fallback, &fallback_codes)?)?;
+    result.add_table(None, fallback_codes);
+
+    return Ok(result);
+  }
+
+  // write the code of `symbol` with the table of the symbol `previous` to
+  // it. fails if the table has no code for it
+  pub fn encode(
+    &self,
+    symbol: &T,
+    previous: Option<&T>,
+    writer: &mut BitWriter
+  ) -> Result<(), HuffmanError> {
+    let table = previous.and_then(|previous| self.context_index.get(previous)).unwrap_or(0);
+    let pair = HuffmanCoding::get_char_code_pair(symbol, &self.tables[table], &self.indexes[table])?;
+    return writer.write(pair.code, pair.bits);
+  }
+
+  // the header of the symbols the codes were made for, with a checksum of
+  // the given algorithm or none at all
+  pub fn header(&self, checksum: Option<ChecksumKind>) -> Header {
+    let contexts = self.contexts.iter().zip(&self.tables[1..])
+      .map(|(value, char_codes)| ContextCodeLengths {
+        value: value.to_u32(),
+        code_lengths: char_codes.iter().map(CodeLengthEntry::new).collect(),
+      })
+      .collect();
+
+    let code_lengths = self.tables[0].iter().map(CodeLengthEntry::new).collect();
+    return Header {
+      contexts: Some(contexts),
+      ..Header::new(T::KIND, checksum, code_lengths, self.bits)
+    };
+  }
+
+  // add the `char_codes` as the table of the context `value`, or as the
+  // fallback table when that's None
+  fn add_table(&mut self, value: Option<T>, char_codes: Vec<CharCodePair<T>>) {
+    let index = SymbolIndex::from_symbols(char_codes.iter().map(|pair| &pair.value));
+    match value {
+      Some(value) => {
+        self.context_index.insert(value.clone(), self.tables.len());
+        self.contexts.push(value);
+        self.tables.push(char_codes);
+        self.indexes.push(index);
+      },
+      None => {
+        self.tables[0] = char_codes;
+        self.indexes[0] = index;
+      },
+    }
+  }
+}
+
+// Reads symbols back with the tables of a header with FLAG_CONTEXTS,
+// switching to the table of every symbol after reading it. The bits can
+// come in any number of pieces.
+pub struct ContextReader<T> {
+  // the fallback table first, then the table of every context that has one
+  tables: Vec<CodeTable<T>>,
+  // which of the tables reads the symbols after every context that has one
+  context_index: SymbolIndex<T>,
+  // the table of the next symbol
+  table: usize,
+  // the length of the longest code of any table
+  max_bits: u8,
+  buffer: BitBuffer,
+}
+
+impl<T: Symbol> ContextReader<T> {
+  // build the tables of the fallback `code_lengths` and the `contexts`.
+  // fails if they don't make up valid codes
+  pub fn new(
+    code_lengths: &[CodeLengthEntry],
+    contexts: &[ContextCodeLengths]
+  ) -> Result<ContextReader<T>, HuffmanError> {
+    let char_codes = HuffmanCoding::code_length_entries_to_char_code_pairs(code_lengths)?;
+    let mut tables: Vec<CodeTable<T>> = vec![CodeTable::new(char_codes)?];
+    let mut context_index: SymbolIndex<T> = SymbolIndex::new();
+    for context in contexts {
+      context_index.insert(T::from_u32(context.value)?, tables.len());
+
+      let char_codes = HuffmanCoding::code_length_entries_to_char_code_pairs(&context.code_lengths)?;
+      tables.push(CodeTable::new(char_codes)?);
+    }
+
+    return Ok(ContextReader {
+      max_bits: tables.iter().map(CodeTable::max_bits).max().unwrap_or(0),
+      tables,
+      context_index,
+      table: 0,
+      buffer: BitBuffer::new(),
+    });
+  }
+}
+
+impl<T: Symbol> SymbolReader<T> for ContextReader<T> {
+  fn read_bytes(&mut self, bytes: &[u8], bits: u64, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    // with at least the longest code of any table, there's enough for the
+    // code of the next one
+    let tables = &self.tables;
+    let context_index = &self.context_index;
+    let table = &mut self.table;
+    return self.buffer.read_bytes(bytes, bits, self.max_bits, |buffer| {
+      let symbol = tables[*table].decode(buffer)?;
+      *table = context_index.get(&symbol).unwrap_or(0);
+      result.push(symbol);
+      return Ok(());
+    });
+  }
+
+  fn finish(&mut self, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    while !self.buffer.is_empty() {
+      let symbol = self.tables[self.table].decode(&mut self.buffer)?;
+      self.table = self.context_index.get(&symbol).unwrap_or(0);
+      result.push(symbol);
+    }
+
+    return Ok(());
+  }
+}
+
+// add `count` more of `value` to the `frequencies`, where the `index` says
+// where every symbol is
+fn add_frequency<T: Symbol>(
+  frequencies: &mut Vec<CharFrequencyPair<T>>,
+  index: &mut SymbolIndex<T>,
+  value: &T,
+  count: u64
+) {
+  match index.get(value) {
+    Some(i) => frequencies[i].count += count,
+    None => {
+      index.insert(value.clone(), frequencies.len());
+      frequencies.push(CharFrequencyPair { value: value.clone(), count });
+    },
+  }
+}
+
+// the canonical codes of a tree grown from the `frequencies`
+fn codes_of<T: Symbol>(
+  frequencies: Vec<CharFrequencyPair<T>>,
+  max_code_length: u8
+) -> Result<Vec<CharCodePair<T>>, HuffmanError> {
+  let tree = HuffmanTree::from_frequencies_with_max_code_length(frequencies, max_code_length)?;
+  return HuffmanCoding::get_canonical_codes(&tree);
+}
+
+// the number of bits the symbols of the `frequencies` take up with the
+// `char_codes`, which have a code for every one of them
+fn count_bits<T: Symbol>(
+  frequencies: &[CharFrequencyPair<T>],
+  char_codes: &[CharCodePair<T>]
+) -> Result<u64, HuffmanError> {
+  return match HuffmanCoding::count_bits(frequencies, char_codes) {
+    Some(bits) => Ok(bits),
+    None => Err(HuffmanError::LengthOverflow),
+  };
+}
+
+// `a` and `b` added up, if that fits in a u64
+fn add_bits(a: u64, b: u64) -> Result<u64, HuffmanError> {
+  return match a.checked_add(b) {
+    Some(bits) => Ok(bits),
+    None => Err(HuffmanError::LengthOverflow),
+  };
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong while compressing or decompressing
+#[derive(Debug)]
+pub enum HuffmanError {
+  /// The data doesn't start with the signature of compressed data, nor is it
+  /// compressed data from before format versions
+  UnknownFormat,
+  /// The compressed data is of a format version this crate can't read
+  UnsupportedVersion(u8),
+  /// The compressed data uses extended feature flags this crate doesn't
+  /// know, which are the ones given
+  UnsupportedFeatures(u8),
+  /// The CRC-32 of the header doesn't match, so the header is damaged
+  HeaderChecksumMismatch,
+  /// The checksum of the decompressed data doesn't match the one stored with
+  /// it, so the compressed data is damaged
+  ChecksumMismatch,
+  /// The compressed data ended before everything it announced was read
+  TruncatedInput,
+  /// The metadata at the start of the compressed data is malformed
+  BadHeader(&'static str),
+  /// The compressed bits don't match any code of the dictionary
+  InvalidCode,
+  /// A dictionary entry holds a value that isn't a valid `char`
+  InvalidChar(u32),
+  /// A dictionary entry holds a value that doesn't stand for a symbol
+  InvalidSymbol(u32),
+  /// The symbol to compress, serialized like in the dictionary, has no code
+  /// in the given HuffmanTree
+  SymbolNotInTree(u32),
+  /// The HuffmanTree has a code longer than `MAX_CODE_BITS`, which can't be
+  /// written to the dictionary. Grow it with a maximum code length instead
+  CodeTooLong,
+  /// The maximum code length is 0, longer than `MAX_CODE_BITS` or too short
+  /// to give every symbol its own code
+  InvalidCodeLengthLimit(u8),
+  /// The input of a HuffmanEncoder changed between reading it to grow the
+  /// tree and reading it again to compress it
+  InputChanged,
+  /// Data can't be split into blocks of 0 symbols
+  InvalidBlockSize,
+  /// The compressed data is too long to count its bits in a `u64`
+  LengthOverflow,
+  /// Data that was stored without codes doesn't hold whole symbols
+  InvalidStoredData,
+  /// There are too many different symbols for a codec to give every one of
+  /// them a share of the frequencies it works with
+  TooManySymbols,
+  /// Codes can't have fewer than 2 or more than 256 different digits
+  InvalidArity(u16),
+  /// The symbols of data that went through the Burrows-Wheeler transform
+  /// don't turn back into its blocks
+  InvalidTransform,
+  /// Reading or writing failed
+  Io(io::Error),
+}
+
+impl fmt::Display for HuffmanError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    return match self {
+      HuffmanError::UnknownFormat => write!(f, "not Huffman compressed data"),
+      HuffmanError::UnsupportedVersion(version) => write!(f, "unsupported format version {version}"),
+      HuffmanError::UnsupportedFeatures(flags) => write!(f, "unsupported extended feature flags {flags:#010b}"),
+      HuffmanError::HeaderChecksumMismatch => write!(f, "header checksum mismatch, the header is damaged"),
+      HuffmanError::ChecksumMismatch => write!(f, "checksum mismatch, the compressed data is damaged"),
+      HuffmanError::TruncatedInput => write!(f, "compressed data is truncated"),
+      HuffmanError::BadHeader(reason) => write!(f, "bad metadata: {reason}"),
+      HuffmanError::InvalidCode => write!(f, "compressed data contains an invalid code"),
+      HuffmanError::InvalidChar(value) => write!(f, "{value:#x} is not a valid char"),
+      HuffmanError::InvalidSymbol(value) => write!(f, "{value:#x} is not a valid symbol"),
+      HuffmanError::SymbolNotInTree(value) => write!(f, "symbol {value:#x} has no code in the Huffman tree"),
+      HuffmanError::CodeTooLong => write!(f, "the Huffman tree has a code longer than 32 bits"),
+      HuffmanError::InvalidCodeLengthLimit(bits) => write!(f, "can't limit codes to {bits} bits"),
+      HuffmanError::InputChanged => write!(f, "input changed while it was being compressed"),
+      HuffmanError::InvalidBlockSize => write!(f, "blocks must hold at least one symbol"),
+      HuffmanError::LengthOverflow => write!(f, "length of compressed bits is too long for the algorithm to handle"),
+      HuffmanError::InvalidStoredData => write!(f, "stored data doesn't hold whole symbols"),
+      HuffmanError::TooManySymbols => write!(f, "too many different symbols for the codec"),
+      HuffmanError::InvalidArity(arity) => write!(f, "can't build codes of {arity} different digits"),
+      HuffmanError::InvalidTransform => write!(f, "transformed data doesn't turn back into its blocks"),
+      HuffmanError::Io(e) => write!(f, "{e}"),
+    };
+  }
+}
+
+impl std::error::Error for HuffmanError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    return match self {
+      HuffmanError::Io(e) => Some(e),
+      _ => None,
+    };
+  }
+}
+
+impl From<io::Error> for HuffmanError {
+  fn from(e: io::Error) -> HuffmanError {
+    return HuffmanError::Io(e);
+  }
+}
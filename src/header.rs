@@ -0,0 +1,802 @@
+use crate::block::BWT_BLOCK_SIZE;
+use crate::char_frequency::CharFrequencyPair;
+use crate::checksum::{ ChecksumKind, Crc32 };
+use crate::codec::Codec;
+use crate::error::HuffmanError;
+use crate::metadata::{ CodeLengthEntry, SymbolKind };
+use crate::varint::Varint;
+
+/// The signature every compressed file starts with. The first byte has its
+/// highest bit set so text tools that strip it are caught.
+///
+/// Compressed data from before format versions starts with the key of a
+/// START_METADATA entry instead, which is two 0 bytes.
+pub const SIGNATURE: [u8; 4] = [0x89, b'H', b'U', b'F'];
+
+/// The format version that follows the signature
+pub const FORMAT_VERSION: u8 = 2;
+
+/// The feature flag for a checksum of the original data after the
+/// compressed bits
+pub const FLAG_CHECKSUM: u8 = 0b0000_0001;
+
+/// The feature flag for data split into blocks, each with its own
+/// BlockHeader, instead of one table of code lengths for all of it
+pub const FLAG_BLOCKS: u8 = 0b0000_0010;
+
+/// The feature flag for data that's stored as it is, because coding it
+/// wouldn't make it any smaller
+pub const FLAG_STORED: u8 = 0b0000_0100;
+
+/// The feature flag for data coded with adaptive Huffman coding, where the
+/// tree changes with every symbol instead of being stored
+pub const FLAG_ADAPTIVE: u8 = 0b0000_1000;
+
+/// The feature flag for data coded with a table of codes for every symbol
+/// that comes before it, an order-1 context model
+pub const FLAG_CONTEXTS: u8 = 0b0001_0000;
+
+/// The feature flag for text coded a word at a time, with a dictionary of
+/// the words and the chars of the other ones spelled out
+pub const FLAG_WORDS: u8 = 0b0010_0000;
+
+/// The feature flag for data coded with another Codec than Huffman coding,
+/// which a codec byte says
+pub const FLAG_CODEC: u8 = 0b0100_0000;
+
+/// The feature flag for a byte of extended feature flags, for the features
+/// that don't fit in the feature flags byte
+pub const FLAG_EXTENDED: u8 = 0b1000_0000;
+
+/// The extended feature flag for codes with more than two digits, of an
+/// NaryHuffmanTree, which an arity byte says
+pub const EXTENDED_FLAG_ARITY: u8 = 0b0000_0001;
+
+/// The extended feature flag for blocks that went through the
+/// Burrows-Wheeler transform, move-to-front and zero-run coding before
+/// they were coded, which only comes with FLAG_BLOCKS
+pub const EXTENDED_FLAG_TRANSFORM: u8 = 0b0000_0010;
+
+// the extended feature flags this version knows about. every bit of the
+// feature flags byte is taken, so new features go here, where the unknown
+// ones are refused
+const KNOWN_EXTENDED_FLAGS: u8 = EXTENDED_FLAG_ARITY | EXTENDED_FLAG_TRANSFORM;
+
+// the feature flags that say how the data follows the header, of which
+// there can be only one
+const LAYOUT_FLAGS: [u8; 5] = [FLAG_BLOCKS, FLAG_STORED, FLAG_ADAPTIVE, FLAG_CONTEXTS, FLAG_WORDS];
+
+/// The byte that ends the blocks of data with FLAG_BLOCKS
+pub const END_OF_BLOCKS: u8 = 0;
+
+// the first byte of a BlockHeader with its own code lengths
+const BLOCK_NEW_CODES: u8 = 1;
+
+// the first byte of a BlockHeader that uses the codes of the block before it
+const BLOCK_PREVIOUS_CODES: u8 = 2;
+
+// the first byte of a BlockHeader whose symbols are stored as they are
+const BLOCK_STORED: u8 = 3;
+
+// the first byte of a BlockHeader with its own code lengths, whose bytes
+// went through the Burrows-Wheeler transform
+const BLOCK_TRANSFORMED: u8 = 4;
+
+/// The compact header at the start of compressed data in format version 2.
+///
+/// In order, it holds:
+/// - the 4 byte SIGNATURE
+/// - the format version byte
+/// - the feature flags byte
+/// - the SymbolKind byte
+/// - with FLAG_EXTENDED, the extended feature flags byte
+/// - with FLAG_CHECKSUM, the ChecksumKind byte
+/// - with FLAG_CODEC, the Codec byte
+/// - with EXTENDED_FLAG_ARITY, the arity of the codes minus one
+/// - the number of symbols, as a varint
+/// - every symbol serialized as a `u32`, from lowest to highest, each as the
+///   varint of how much higher it is than the one before it
+/// - the code length of every symbol, in the same order, as varints
+/// - the number of compressed bits that follow the header, as a varint
+/// - the CRC-32 of everything above, as 4 bytes, most significant first
+///
+/// With FLAG_BLOCKS, the number of symbols, the symbols, their code lengths
+/// and the number of bits are left out. The header is followed by blocks
+/// instead, each a BlockHeader and its compressed bits, and then the
+/// END_OF_BLOCKS byte. With EXTENDED_FLAG_TRANSFORM as well, every block
+/// that isn't stored went through the Burrows-Wheeler transform on its own.
+///
+/// With FLAG_STORED, the number of symbols, the symbols and their code
+/// lengths are left out, and the bits that follow the header are the
+/// original data as `Symbol::write_stored` writes it. It can't be combined
+/// with FLAG_BLOCKS, where every block can be stored on its own instead.
+///
+/// With FLAG_ADAPTIVE, the number of symbols, the symbols, their code
+/// lengths and the number of bits are left out too. The header is followed
+/// by pieces of the compressed bits instead, each the number of its bits as
+/// a varint and the bits filled up to a whole byte with 0 bits, and then a
+/// 0 varint. Every piece holds whole symbols, coded with the adaptive tree
+/// as it is after the pieces before it.
+///
+/// With FLAG_CONTEXTS, the code lengths are followed by the tables of the
+/// contexts that have their own: the number of them as a varint, then for
+/// every context from lowest to highest, the varint of how much higher its
+/// symbol is than the one before it and the code lengths of the symbols
+/// that follow it, written like the ones above. Every symbol is coded with
+/// the table of the symbol before it, or with the code lengths above when
+/// that has no table of its own or it's the first symbol.
+///
+/// With FLAG_WORDS, which is only for chars, the symbols of the code lengths
+/// stand for tokens of the text: 0 for a token that's spelled out and any
+/// other for the word of the dictionary at one less. The code lengths are
+/// followed by the dictionary: the number of words as a varint, then every
+/// word as the varints of how many of its first bytes are the same as the
+/// word before it and how many bytes follow, and those bytes of UTF-8. Then
+/// come the code lengths of the chars of spelled out tokens, written like
+/// the ones above, where every char is its value plus one and 0 ends the
+/// token.
+///
+/// With FLAG_CODEC, which can't be combined with any of the flags above,
+/// the code lengths are replaced by the frequencies the Codec codes the
+/// symbols by, written like them, followed by the number of symbols as a
+/// varint.
+///
+/// With EXTENDED_FLAG_ARITY, which can't be combined with any of the flags
+/// above either, the code lengths count digits of the arity instead of
+/// bits, and every digit of a code is written in as many bits as the
+/// highest digit takes up.
+///
+/// With FLAG_CHECKSUM, the compressed bits are followed by the checksum of
+/// the original data as 4 bytes, most significant first.
+#[derive(Debug)]
+pub struct Header {
+  pub kind: SymbolKind,
+  pub checksum: Option<ChecksumKind>,
+  pub blocks: bool,
+  pub stored: bool,
+  pub adaptive: bool,
+  pub code_lengths: Vec<CodeLengthEntry>,
+  /// With FLAG_CONTEXTS, the tables of the contexts that have their own
+  pub contexts: Option<Vec<ContextCodeLengths>>,
+  /// With FLAG_WORDS, the dictionary and the codes of spelled out tokens
+  pub words: Option<WordDictionary>,
+  /// How the symbols are coded, with FLAG_CODEC when it isn't
+  /// `Codec::Huffman`
+  pub codec: Codec,
+  /// With FLAG_CODEC, the frequencies of the symbols in place of the code
+  /// lengths
+  pub frequencies: Vec<CharFrequencyPair<u32>>,
+  /// With FLAG_CODEC, the number of symbols that are coded
+  pub symbol_count: u64,
+  /// The number of digits of the codes, with EXTENDED_FLAG_ARITY when it
+  /// isn't 2
+  pub arity: u16,
+  /// With EXTENDED_FLAG_TRANSFORM, whether the blocks went through the
+  /// Burrows-Wheeler transform
+  pub transform: bool,
+  pub bits: u64,
+}
+
+/// The code lengths of the symbols that follow the symbol `value`, in a
+/// Header with FLAG_CONTEXTS
+#[derive(Debug)]
+pub struct ContextCodeLengths {
+  pub value: u32,
+  pub code_lengths: Vec<CodeLengthEntry>,
+}
+
+/// The words of a Header with FLAG_WORDS, and the code lengths of the chars
+/// of the tokens that aren't among them
+#[derive(Debug)]
+pub struct WordDictionary {
+  /// Every word of the dictionary, where the one at `i` has the symbol
+  /// `i + 1`
+  pub words: Vec<String>,
+  /// The code lengths of the chars of spelled out tokens, every char as its
+  /// value plus one, and of the 0 that ends a token
+  pub spelling_code_lengths: Vec<CodeLengthEntry>,
+}
+
+impl Header {
+  /// A header of `bits` of data of the given `kind`, Huffman coded with the
+  /// given `code_lengths` and without any of the other features. Those can
+  /// be set on top of it with `Header { blocks: true, ..Header::new(...) }`
+  pub fn new(
+    kind: SymbolKind,
+    checksum: Option<ChecksumKind>,
+    code_lengths: Vec<CodeLengthEntry>,
+    bits: u64
+  ) -> Header {
+    return Header {
+      kind,
+      checksum,
+      blocks: false,
+      stored: false,
+      adaptive: false,
+      code_lengths,
+      contexts: None,
+      words: None,
+      codec: Codec::Huffman,
+      frequencies: Vec::new(),
+      symbol_count: 0,
+      arity: 2,
+      transform: false,
+      bits,
+    };
+  }
+
+  /// Write this header as bytes
+  pub fn as_bytes(&self) -> Vec<u8> {
+    let mut result: Vec<u8> = SIGNATURE.to_vec();
+    result.push(FORMAT_VERSION);
+    result.push(self.flags());
+    result.push(self.kind as u8);
+    if self.extended_flags() != 0 {
+      result.push(self.extended_flags());
+    }
+
+    if let Some(checksum) = self.checksum {
+      result.push(checksum as u8);
+    }
+
+    if self.codec != Codec::Huffman {
+      result.push(self.codec as u8);
+    }
+
+    if self.arity != 2 {
+      result.push((self.arity - 1) as u8);
+    }
+
+    if !self.blocks && !self.adaptive {
+      if self.codec != Codec::Huffman {
+        write_frequencies(&mut result, &self.frequencies);
+        Varint::write(&mut result, self.symbol_count);
+      } else if !self.stored {
+        write_code_lengths(&mut result, &self.code_lengths);
+      }
+
+      if let Some(contexts) = &self.contexts {
+        write_contexts(&mut result, contexts);
+      }
+
+      if let Some(words) = &self.words {
+        write_words(&mut result, words);
+      }
+
+      Varint::write(&mut result, self.bits);
+    }
+
+    let crc = Crc32::checksum(&result);
+    result.extend_from_slice(&crc.to_be_bytes());
+
+    return result;
+  }
+
+  /// The feature flags of this header
+  pub fn flags(&self) -> u8 {
+    let mut flags: u8 = 0;
+    if self.checksum.is_some() {
+      flags |= FLAG_CHECKSUM;
+    }
+
+    if self.blocks {
+      flags |= FLAG_BLOCKS;
+    }
+
+    if self.stored {
+      flags |= FLAG_STORED;
+    }
+
+    if self.adaptive {
+      flags |= FLAG_ADAPTIVE;
+    }
+
+    if self.contexts.is_some() {
+      flags |= FLAG_CONTEXTS;
+    }
+
+    if self.words.is_some() {
+      flags |= FLAG_WORDS;
+    }
+
+    if self.codec != Codec::Huffman {
+      flags |= FLAG_CODEC;
+    }
+
+    if self.extended_flags() != 0 {
+      flags |= FLAG_EXTENDED;
+    }
+
+    return flags;
+  }
+
+  /// The extended feature flags of this header, which are only written
+  /// when there are any
+  pub fn extended_flags(&self) -> u8 {
+    let mut flags: u8 = 0;
+    if self.arity != 2 {
+      flags |= EXTENDED_FLAG_ARITY;
+    }
+
+    if self.transform {
+      flags |= EXTENDED_FLAG_TRANSFORM;
+    }
+
+    return flags;
+  }
+
+  /// Check if `b` starts with the SIGNATURE of a header
+  pub fn is_header(b: &[u8]) -> bool {
+    return b.starts_with(&SIGNATURE);
+  }
+
+  /// Read a header from the start of `b`, as written by `as_bytes`. Also
+  /// returns the number of bytes the header takes up.
+  ///
+  /// Fails if `b` doesn't start with the SIGNATURE, is of another format
+  /// version, uses unknown extended feature flags or the CRC-32 doesn't
+  /// match.
+  pub fn from_bytes(b: &[u8]) -> Result<(Header, usize), HuffmanError> {
+    if !Self::is_header(b) {
+      return Err(HuffmanError::UnknownFormat);
+    }
+
+    let (version, flags, kind) = match b.get(4..7) {
+      Some(bytes) => (bytes[0], bytes[1], bytes[2]),
+      None => return Err(HuffmanError::TruncatedInput),
+    };
+
+    if version != FORMAT_VERSION {
+      return Err(HuffmanError::UnsupportedVersion(version));
+    }
+
+    let mut position: usize = 7;
+    let extended_flags = if flags & FLAG_EXTENDED != 0 {
+      match b.get(position) {
+        Some(extended_flags) => {
+          position += 1;
+          *extended_flags
+        },
+        None => return Err(HuffmanError::TruncatedInput),
+      }
+    } else {
+      0
+    };
+
+    if extended_flags & !KNOWN_EXTENDED_FLAGS != 0 {
+      return Err(HuffmanError::UnsupportedFeatures(extended_flags & !KNOWN_EXTENDED_FLAGS));
+    }
+
+    let kind = SymbolKind::from_u8(kind)?;
+
+    let checksum = if flags & FLAG_CHECKSUM != 0 {
+      match b.get(position) {
+        Some(checksum) => {
+          position += 1;
+          Some(ChecksumKind::from_u8(*checksum)?)
+        },
+        None => return Err(HuffmanError::TruncatedInput),
+      }
+    } else {
+      None
+    };
+
+    let codec = if flags & FLAG_CODEC != 0 {
+      match b.get(position) {
+        Some(codec) => {
+          position += 1;
+          Codec::from_u8(*codec)?
+        },
+        None => return Err(HuffmanError::TruncatedInput),
+      }
+    } else {
+      Codec::Huffman
+    };
+
+    // stored as one less, so the byte fits every arity up to 256
+    let arity = if extended_flags & EXTENDED_FLAG_ARITY != 0 {
+      match b.get(position) {
+        Some(0) => return Err(HuffmanError::BadHeader("codes of a single digit")),
+        Some(arity) => {
+          position += 1;
+          *arity as u16 + 1
+        },
+        None => return Err(HuffmanError::TruncatedInput),
+      }
+    } else {
+      2
+    };
+
+    // a codec or arity only codes the symbols of the layout without any of
+    // them, and not both at once
+    let layout_flag_count = LAYOUT_FLAGS.iter().filter(|flag| flags & **flag != 0).count();
+    let coding_flag_count = (codec != Codec::Huffman) as usize
+      + (extended_flags & EXTENDED_FLAG_ARITY != 0) as usize;
+    if layout_flag_count > 1 || (coding_flag_count > 0 && layout_flag_count + coding_flag_count > 1) {
+      return Err(HuffmanError::BadHeader("conflicting feature flags"));
+    }
+
+    let blocks = flags & FLAG_BLOCKS != 0;
+    let stored = flags & FLAG_STORED != 0;
+    let adaptive = flags & FLAG_ADAPTIVE != 0;
+    if flags & FLAG_WORDS != 0 && kind != SymbolKind::Chars {
+      return Err(HuffmanError::BadHeader("only chars can be coded as words"));
+    }
+
+    let transform = extended_flags & EXTENDED_FLAG_TRANSFORM != 0;
+    if transform && !blocks {
+      return Err(HuffmanError::BadHeader("only blocks can be transformed"));
+    }
+
+    let mut frequencies: Vec<CharFrequencyPair<u32>> = Vec::new();
+    let mut symbol_count: u64 = 0;
+    let (code_lengths, contexts, words, bits) = if blocks || adaptive {
+      (Vec::new(), None, None, 0)
+    } else if codec != Codec::Huffman {
+      frequencies = read_frequencies(b, &mut position)?;
+      symbol_count = Varint::read(b, &mut position)?;
+      (Vec::new(), None, None, Varint::read(b, &mut position)?)
+    } else if stored {
+      (Vec::new(), None, None, Varint::read(b, &mut position)?)
+    } else {
+      let code_lengths = read_code_lengths(b, &mut position)?;
+      let contexts = if flags & FLAG_CONTEXTS != 0 {
+        Some(read_contexts(b, &mut position)?)
+      } else {
+        None
+      };
+
+      let words = if flags & FLAG_WORDS != 0 {
+        Some(read_words(b, &mut position)?)
+      } else {
+        None
+      };
+
+      (code_lengths, contexts, words, Varint::read(b, &mut position)?)
+    };
+
+    check_crc(b, position)?;
+
+    let header = Header {
+      kind,
+      checksum,
+      blocks,
+      stored,
+      adaptive,
+      code_lengths,
+      contexts,
+      words,
+      codec,
+      frequencies,
+      symbol_count,
+      arity,
+      transform,
+      bits,
+    };
+    return Ok((header, position + 4));
+  }
+}
+
+/// The header of a block of data with FLAG_BLOCKS.
+///
+/// In order, it holds:
+/// - a byte that says if the block has its own code lengths, uses the ones
+///   of the block before it, is stored as it is or went through the
+///   Burrows-Wheeler transform
+/// - with its own code lengths, the number of symbols, the symbols and the
+///   code lengths like in the Header
+/// - when it went through the transform, the BlockTransform: the length of
+///   the block and its primary index, as varints
+/// - the number of compressed bits of the block, as a varint
+/// - the CRC-32 of everything above, as 4 bytes, most significant first
+///
+/// The compressed bits of the block follow right after it, filled up to a
+/// whole byte with 0 bits. A stored block has no codes, its bits are the
+/// symbols of the block as `Symbol::write_stored` writes them.
+///
+/// A block that went through the transform always has its own code
+/// lengths, of the symbols that came out of it. Those turn back into the
+/// symbols of the block as `Symbol::write_stored` writes them.
+#[derive(Debug)]
+pub struct BlockHeader {
+  /// The code lengths of the block, or `None` when it uses the codes of the
+  /// block before it or is stored
+  pub code_lengths: Option<Vec<CodeLengthEntry>>,
+  /// Whether the symbols of the block are stored as they are
+  pub stored: bool,
+  /// How the block went through the Burrows-Wheeler transform, if it did
+  pub transform: Option<BlockTransform>,
+  pub bits: u64,
+}
+
+/// How a block went through the Burrows-Wheeler transform, which sorts the
+/// rotations of its bytes and keeps the last byte of every one
+#[derive(Debug, Clone, Copy)]
+pub struct BlockTransform {
+  /// The number of bytes of the block, from 1 up to BWT_BLOCK_SIZE
+  pub length: u64,
+  /// The row of the sorted rotations where the block ends, from 1 up to
+  /// the length
+  pub primary_index: u64,
+}
+
+impl BlockHeader {
+  /// Write this block header as bytes
+  pub fn as_bytes(&self) -> Vec<u8> {
+    let mut result: Vec<u8> = Vec::new();
+    match (&self.code_lengths, &self.transform) {
+      _ if self.stored => result.push(BLOCK_STORED),
+      (Some(code_lengths), Some(transform)) => {
+        result.push(BLOCK_TRANSFORMED);
+        write_code_lengths(&mut result, code_lengths);
+        Varint::write(&mut result, transform.length);
+        Varint::write(&mut result, transform.primary_index);
+      },
+      (Some(code_lengths), None) => {
+        result.push(BLOCK_NEW_CODES);
+        write_code_lengths(&mut result, code_lengths);
+      },
+      (None, _) => result.push(BLOCK_PREVIOUS_CODES),
+    }
+
+    Varint::write(&mut result, self.bits);
+
+    let crc = Crc32::checksum(&result);
+    result.extend_from_slice(&crc.to_be_bytes());
+
+    return result;
+  }
+
+  /// Read a block header from the start of `b`, as written by `as_bytes`,
+  /// or `None` if `b` starts with the END_OF_BLOCKS byte instead. Also
+  /// returns the number of bytes read.
+  ///
+  /// Fails if `b` starts with neither, the length or primary index of a
+  /// transformed block is out of range or the CRC-32 doesn't match.
+  pub fn from_bytes(b: &[u8]) -> Result<(Option<BlockHeader>, usize), HuffmanError> {
+    let mut position: usize = 1;
+    let kind = match b.first() {
+      Some(&END_OF_BLOCKS) => return Ok((None, 1)),
+      Some(kind) => *kind,
+      None => return Err(HuffmanError::TruncatedInput),
+    };
+
+    let code_lengths = match kind {
+      BLOCK_NEW_CODES | BLOCK_TRANSFORMED => Some(read_code_lengths(b, &mut position)?),
+      BLOCK_PREVIOUS_CODES | BLOCK_STORED => None,
+      _ => return Err(HuffmanError::BadHeader("unknown kind of block")),
+    };
+
+    let transform = if kind == BLOCK_TRANSFORMED {
+      Some(read_block_transform(b, &mut position)?)
+    } else {
+      None
+    };
+
+    let bits = Varint::read(b, &mut position)?;
+
+    check_crc(b, position)?;
+
+    let block_header = BlockHeader { code_lengths, stored: kind == BLOCK_STORED, transform, bits };
+    return Ok((Some(block_header), position + 4));
+  }
+}
+
+// write the number of symbols, every symbol from lowest to highest as how
+// much higher it is than the one before it, and their code lengths
+fn write_code_lengths(result: &mut Vec<u8>, code_lengths: &[CodeLengthEntry]) {
+  let mut entries: Vec<(u32, u64)> = code_lengths.iter()
+    .map(|entry| (entry.value, entry.bits as u64))
+    .collect();
+  entries.sort_by_key(|entry| entry.0);
+  write_symbols_with_numbers(result, &entries);
+}
+
+// read the code lengths at `*position` in `b`, as written by
+// write_code_lengths, and move `position` past them
+fn read_code_lengths(
+  b: &[u8],
+  position: &mut usize
+) -> Result<Vec<CodeLengthEntry>, HuffmanError> {
+  let mut code_lengths: Vec<CodeLengthEntry> = Vec::new();
+  for (value, bits) in read_symbols_with_numbers(b, position)? {
+    match u8::try_from(bits) {
+      Ok(bits) => code_lengths.push(CodeLengthEntry { value, bits }),
+      Err(_) => return Err(HuffmanError::BadHeader("invalid code length")),
+    }
+  }
+
+  return Ok(code_lengths);
+}
+
+// write the `frequencies` like write_code_lengths writes code lengths
+fn write_frequencies(result: &mut Vec<u8>, frequencies: &[CharFrequencyPair<u32>]) {
+  let mut entries: Vec<(u32, u64)> = frequencies.iter().map(|pair| (pair.value, pair.count)).collect();
+  entries.sort_by_key(|entry| entry.0);
+  write_symbols_with_numbers(result, &entries);
+}
+
+// read the frequencies at `*position` in `b`, as written by
+// write_frequencies, and move `position` past them
+fn read_frequencies(
+  b: &[u8],
+  position: &mut usize
+) -> Result<Vec<CharFrequencyPair<u32>>, HuffmanError> {
+  let entries = read_symbols_with_numbers(b, position)?;
+  return Ok(entries.into_iter().map(|(value, count)| CharFrequencyPair { value, count }).collect());
+}
+
+// write the number of `entries`, the symbol of every entry as how much
+// higher it is than the one before it, then the number of every entry. the
+// entries must be in order of their symbols
+fn write_symbols_with_numbers(result: &mut Vec<u8>, entries: &[(u32, u64)]) {
+  Varint::write(result, entries.len() as u64);
+
+  let mut previous_value: u32 = 0;
+  for (value, _) in entries {
+    Varint::write(result, (value - previous_value) as u64);
+    previous_value = *value;
+  }
+
+  for (_, number) in entries {
+    Varint::write(result, *number);
+  }
+}
+
+// read the symbols and numbers at `*position` in `b`, as written by
+// write_symbols_with_numbers, and move `position` past them
+fn read_symbols_with_numbers(
+  b: &[u8],
+  position: &mut usize
+) -> Result<Vec<(u32, u64)>, HuffmanError> {
+  let symbol_count = Varint::read(b, position)?;
+
+  let mut values: Vec<u32> = Vec::new();
+  for i in 0..symbol_count {
+    let delta = Varint::read(b, position)?;
+    if i > 0 && delta == 0 {
+      return Err(HuffmanError::BadHeader("symbols are not in order"));
+    }
+
+    let previous_value = values.last().copied().unwrap_or(0) as u64;
+    match previous_value.checked_add(delta).map(u32::try_from) {
+      Some(Ok(value)) => values.push(value),
+      _ => return Err(HuffmanError::BadHeader("symbol doesn't fit in a u32")),
+    }
+  }
+
+  let mut entries: Vec<(u32, u64)> = Vec::new();
+  for value in values {
+    entries.push((value, Varint::read(b, position)?));
+  }
+
+  return Ok(entries);
+}
+
+// write the number of `contexts`, then every context from lowest to highest
+// as how much higher it is than the one before it, and its code lengths
+pub(crate) fn write_contexts(result: &mut Vec<u8>, contexts: &[ContextCodeLengths]) {
+  let mut contexts: Vec<&ContextCodeLengths> = contexts.iter().collect();
+  contexts.sort_by_key(|context| context.value);
+
+  Varint::write(result, contexts.len() as u64);
+
+  let mut previous_value: u32 = 0;
+  for context in contexts {
+    Varint::write(result, (context.value - previous_value) as u64);
+    write_code_lengths(result, &context.code_lengths);
+    previous_value = context.value;
+  }
+}
+
+// read the contexts at `*position` in `b`, as written by write_contexts,
+// and move `position` past them
+fn read_contexts(
+  b: &[u8],
+  position: &mut usize
+) -> Result<Vec<ContextCodeLengths>, HuffmanError> {
+  let context_count = Varint::read(b, position)?;
+
+  let mut contexts: Vec<ContextCodeLengths> = Vec::new();
+  for i in 0..context_count {
+    let delta = Varint::read(b, position)?;
+    if i > 0 && delta == 0 {
+      return Err(HuffmanError::BadHeader("contexts are not in order"));
+    }
+
+    let previous_value = contexts.last().map(|context| context.value).unwrap_or(0) as u64;
+    let value = match previous_value.checked_add(delta).map(u32::try_from) {
+      Some(Ok(value)) => value,
+      _ => return Err(HuffmanError::BadHeader("context doesn't fit in a u32")),
+    };
+
+    let code_lengths = read_code_lengths(b, position)?;
+    contexts.push(ContextCodeLengths { value, code_lengths });
+  }
+
+  return Ok(contexts);
+}
+
+// write the number of words of the `dictionary`, then every word as the
+// number of bytes it shares with the start of the word before it and the
+// rest of its bytes, then the code lengths of spelled out tokens
+fn write_words(result: &mut Vec<u8>, dictionary: &WordDictionary) {
+  Varint::write(result, dictionary.words.len() as u64);
+
+  let mut previous_word: &str = "";
+  for word in &dictionary.words {
+    let shared = word.bytes().zip(previous_word.bytes()).take_while(|(a, b)| a == b).count();
+    Varint::write(result, shared as u64);
+    Varint::write(result, (word.len() - shared) as u64);
+    result.extend_from_slice(&word.as_bytes()[shared..]);
+    previous_word = word;
+  }
+
+  write_code_lengths(result, &dictionary.spelling_code_lengths);
+}
+
+// read the words at `*position` in `b`, as written by write_words, and move
+// `position` past them
+fn read_words(b: &[u8], position: &mut usize) -> Result<WordDictionary, HuffmanError> {
+  let word_count = Varint::read(b, position)?;
+
+  let mut words: Vec<String> = Vec::new();
+  for _ in 0..word_count {
+    let shared = Varint::read(b, position)?;
+    let rest = Varint::read(b, position)?;
+
+    let previous_word = words.last().map(|word| word.as_bytes()).unwrap_or(&[]);
+    if shared > previous_word.len() as u64 {
+      return Err(HuffmanError::BadHeader("word shares more than the word before it"));
+    }
+
+    let rest = match position.checked_add(rest as usize).and_then(|end| b.get(*position..end)) {
+      Some(rest) => rest,
+      None => return Err(HuffmanError::TruncatedInput),
+    };
+    *position += rest.len();
+
+    let mut word = previous_word[..shared as usize].to_vec();
+    word.extend_from_slice(rest);
+    match String::from_utf8(word) {
+      Ok(word) => words.push(word),
+      Err(_) => return Err(HuffmanError::BadHeader("word isn't UTF-8")),
+    }
+  }
+
+  let spelling_code_lengths = read_code_lengths(b, position)?;
+  return Ok(WordDictionary { words, spelling_code_lengths });
+}
+
+// read the length and primary index of a transformed block at `*position`
+// in `b`, and move `position` past them. fails if the block is empty or
+// longer than BWT_BLOCK_SIZE, which bounds what turning it back takes, or
+// the primary index isn't one of its rows
+fn read_block_transform(b: &[u8], position: &mut usize) -> Result<BlockTransform, HuffmanError> {
+  let length = Varint::read(b, position)?;
+  if length == 0 || length > BWT_BLOCK_SIZE as u64 {
+    return Err(HuffmanError::BadHeader("invalid length of a transformed block"));
+  }
+
+  let primary_index = Varint::read(b, position)?;
+  if primary_index == 0 || primary_index > length {
+    return Err(HuffmanError::BadHeader("primary index outside its block"));
+  }
+
+  return Ok(BlockTransform { length, primary_index });
+}
+
+// check that the CRC-32 at `position` in `b` is the one of everything
+// before it
+fn check_crc(b: &[u8], position: usize) -> Result<(), HuffmanError> {
+  let crc = match b.get(position..position + 4) {
+    Some(crc) => u32::from_be_bytes(crc.try_into().unwrap()),
+    None => return Err(HuffmanError::TruncatedInput),
+  };
+
+  if crc != Crc32::checksum(&b[..position]) {
+    return Err(HuffmanError::HeaderChecksumMismatch);
+  }
+
+  return Ok(());
+}
@@ -1,179 +1,1004 @@
-use crate::char_code::CharCodePair;
+use crate::adaptive::{ AdaptiveHuffmanTree, AdaptiveReader };
+use crate::ans::{ quantize_frequencies, RansEncoder, TansEncoder };
+use crate::arithmetic::{ scale_frequencies, ArithmeticEncoder };
+use crate::bits::{ BitWriter, CodeReader, SymbolReader };
+use crate::block::{ BlockOptions, BWT_BLOCK_SIZE };
+use crate::canonical::CanonicalCodes;
+use crate::char_code::{ CharCodePair, MAX_CODE_BITS };
+use crate::char_frequency::CharFrequencyPair;
+use crate::checksum::{ Checksum, ChecksumKind };
+use crate::codec::{ Codec, CodecReader };
+use crate::context::{ ContextCodes, ContextFrequencies, ContextReader };
+use crate::error::HuffmanError;
+use crate::header::{ BlockHeader, BlockTransform, Header, END_OF_BLOCKS };
 use crate::huffman_tree::{ HuffmanTree, HuffmanTreeNode };
-use crate::metadata::MetadataKeyValuePair;
+use crate::metadata::{ CodeLengthEntry, MetadataKeyValuePair, SymbolKind };
+use crate::nary_tree::NaryHuffmanTree;
+use crate::symbol::Symbol;
+use crate::symbol_index::SymbolIndex;
+use crate::transform::{ transform_block, undo_transform_block, TransformedSymbol };
+use crate::varint::Varint;
+use crate::words::{ tokenize, WordCodes, WordReader };
 
+/// Compression and decompression of data with the codes of a HuffmanTree
 pub struct HuffmanCoding;
 
 impl HuffmanCoding {
-  pub fn compress(s: &str, tree: &HuffmanTree) -> Vec<u8> {
-    let mut char_codes: Vec<CharCodePair> = Vec::new();
-    Self::get_char_code_pairs_from_tree(
-      &tree.trunk, // start with the trunk node
-      0, // initial call so current_code is empty (0)
-      0, // initial call so current_code_bits is none (0)
-      &mut char_codes // the Vec where char_codes will be placed
-    );
-
-    // the final compressed bytes of the text
-    let mut bytes: Vec<u8> = Vec::new();
-    // byte currently being written
-    let mut current_byte: u8 = 0;
-    // index inside the current byte. (bit index in whole string)
-    let mut current_byte_index: u64 = 0;
-    for ch in s.chars() {
-      let pair = Self::get_char_code_pair(ch, &char_codes);
-      // rotate bits left by `8 - bits` to turn something like
-      // 0b00111111
-      // (assuming `pair.bits` is 6)
-      // into
-      // 0b11111100
-      let rotate_amount = 8 - pair.bits;
-      let mut char_code = pair.code.rotate_left(rotate_amount.into());
-      for _ in 0..pair.bits {
-        if char_code & 0b1000_0000 == 0b1000_0000 {
-          // current bit of char_code is 1, write 1 to current bit of byte
-          current_byte |= 0b0000_0001;
-        } else {
-          // current bit of char_code is 0, write 0 to current bit of byte
-          current_byte &= 0b1111_1110;
-        }
+  /// Compress the given bytes `b` with the codes of the given `tree`, which
+  /// should be grown with `HuffmanTree::from_bytes`.
+  ///
+  /// The returned bytes start with the metadata describing the codes,
+  /// followed by the compressed bits of `b` and the CRC-32 of `b`. When
+  /// that would take up more space than `b` itself, like for short or
+  /// random data, `b` is stored as it is instead. Fails if `b` contains a
+  /// byte that has no code in `tree`.
+  pub fn compress(b: &[u8], tree: &HuffmanTree<u8>) -> Result<Vec<u8>, HuffmanError> {
+    return Self::encode(b.iter().copied(), tree);
+  }
 
-        // rotate the bits to prepare for next one
-        char_code = char_code.rotate_left(1);
+  /// Compress the given &str `s` with the codes of the given `tree`, which
+  /// should be grown with `HuffmanTree::new`.
+  ///
+  /// Works like `HuffmanCoding::compress`, but the alphabet is the chars of
+  /// `s` rather than its bytes.
+  pub fn compress_str(s: &str, tree: &HuffmanTree<char>) -> Result<Vec<u8>, HuffmanError> {
+    return Self::encode(s.chars(), tree);
+  }
 
-        // check if we just wrote to the last bit of the byte
-        if current_byte_index % 8 == 7 { 
-          bytes.push(current_byte);
-          current_byte = 0;
-        } else {
-          // only rotate the current byte if we didn't write to the last bit
-          current_byte = current_byte.rotate_left(1);
-        }
+  /// Compress the given `symbols` of any Symbol type with the codes of the
+  /// given `tree`, which should be grown with `HuffmanTree::from_symbols`.
+  ///
+  /// `HuffmanCoding::decode` with the same Symbol type reverses this.
+  pub fn encode<T: Symbol>(
+    symbols: impl IntoIterator<Item = T>,
+    tree: &HuffmanTree<T>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    return Self::encode_with_checksum(symbols, tree, Some(ChecksumKind::Crc32));
+  }
 
-        // move to next bit in byte
-        current_byte_index = match current_byte_index.checked_add(1) {
-          Some(n) => n,
-          None => panic!("Length of compressed bits will be too long for the algorithm to handle"),
-        }
+  /// Compress the given `symbols` like `HuffmanCoding::encode`, but store
+  /// the checksum of the original data with the given algorithm, or none at
+  /// all when `checksum` is `None`.
+  ///
+  /// The checksum is taken of the bytes `decompress` returns, so of the
+  /// UTF-8 bytes of text. The symbols are stored as they are when coding
+  /// them wouldn't make them any smaller.
+  pub fn encode_with_checksum<T: Symbol>(
+    symbols: impl IntoIterator<Item = T>,
+    tree: &HuffmanTree<T>,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    return Self::encode_with_codes(symbols, Self::get_canonical_codes(tree)?, checksum);
+  }
+
+  /// Compress the given `symbols` of any Symbol type with the given
+  /// `char_codes`, like the ones `CodeBuilder::build` builds, with the
+  /// checksum of the original data of the given algorithm or none at all.
+  ///
+  /// Only the code lengths are stored, so the codes are swapped for the
+  /// canonical codes of the same lengths first. Otherwise this works like
+  /// `HuffmanCoding::encode_with_checksum`. Fails if a symbol has no code,
+  /// or the code lengths don't make up a prefix code.
+  pub fn encode_with_codes<T: Symbol>(
+    symbols: impl IntoIterator<Item = T>,
+    char_codes: Vec<CharCodePair<T>>,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    let char_codes = CanonicalCodes::from_char_code_pairs(char_codes)?;
+    let code_lengths = char_codes.iter().map(CodeLengthEntry::new).collect();
+    return Self::encode_with_canonical_codes(symbols, char_codes, code_lengths, 2, checksum);
+  }
+
+  /// Compress the given bytes `b` with the codes of an NaryHuffmanTree of
+  /// the given `arity` grown from them, with the CRC-32 of `b`.
+  ///
+  /// `HuffmanCoding::decompress` reverses this like any other compressed
+  /// bytes. Fails like `HuffmanCoding::encode_nary`.
+  pub fn compress_nary(b: &[u8], arity: u16) -> Result<Vec<u8>, HuffmanError> {
+    let tree = NaryHuffmanTree::from_bytes(b, arity)?;
+    return Self::encode_nary(b.iter().copied(), &tree, Some(ChecksumKind::Crc32));
+  }
+
+  /// Compress the given `symbols` of any Symbol type with the codes of the
+  /// given `tree`, with the checksum of the original data of the given
+  /// algorithm or none at all.
+  ///
+  /// The header holds the arity of the tree and the number of digits of
+  /// every code, and every digit is written in `tree.digit_bits()` bits, so
+  /// the codes of a tree of 256 are whole bytes. Otherwise this works like
+  /// `HuffmanCoding::encode_with_checksum`. Fails if a symbol has no code,
+  /// or with `HuffmanError::CodeTooLong` if a code would take up more than
+  /// `MAX_CODE_BITS` bits.
+  pub fn encode_nary<T: Symbol>(
+    symbols: impl IntoIterator<Item = T>,
+    tree: &NaryHuffmanTree<T>,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    let digit_lengths = tree.digit_lengths()?;
+    let code_lengths = digit_lengths.iter()
+      .map(|(value, digits)| CodeLengthEntry { value: value.to_u32(), bits: *digits })
+      .collect();
+
+    let char_codes = CanonicalCodes::from_digit_lengths(digit_lengths, tree.arity)?;
+    return Self::encode_with_canonical_codes(symbols, char_codes, code_lengths, tree.arity, checksum);
+  }
+
+  // compress the `symbols` with the canonical `char_codes` of the
+  // `code_lengths`, which count digits of the `arity`, then finish them
+  // like every other encoding
+  fn encode_with_canonical_codes<T: Symbol>(
+    symbols: impl IntoIterator<Item = T>,
+    char_codes: Vec<CharCodePair<T>>,
+    code_lengths: Vec<CodeLengthEntry>,
+    arity: u16,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    // kept around in case they're stored as they are
+    let symbols: Vec<T> = symbols.into_iter().collect();
+    let writer = Self::write_codes(&symbols, &char_codes)?;
+
+    // the total number of bits is stored in the header to let the
+    // decompression algorithm know exactly the number of bits it should care
+    // about, ignoring any extra bits left in the last byte so it wont think
+    // those extra bits are part of the message.
+    let header = Header {
+      arity,
+      ..Header::new(T::KIND, checksum, code_lengths, writer.bits())
+    };
+
+    let mut result: Vec<u8> = header.as_bytes();
+    result.append(&mut writer.finish());
+
+    return Self::finish_encoding(&symbols, result, checksum);
+  }
+
+  /// Compress the given bytes `b` in blocks, each with its own tree that's
+  /// grown from just the bytes of the block.
+  ///
+  /// `HuffmanCoding::decompress` reverses this like any other compressed
+  /// bytes. Fails if the block size is 0.
+  pub fn compress_blocks(b: &[u8], options: &BlockOptions) -> Result<Vec<u8>, HuffmanError> {
+    return Self::encode_blocks(b.to_vec(), options);
+  }
+
+  /// Compress the given `symbols` of any Symbol type in blocks, each with
+  /// its own tree that's grown from just the symbols of the block.
+  ///
+  /// With `options.reuse_trees`, a block uses the codes of the block before
+  /// it instead when that takes up fewer bytes. A block that coding
+  /// wouldn't make any smaller is stored as it is. `HuffmanCoding::decode` with
+  /// the same Symbol type reverses this. Fails if the block size is 0 or
+  /// the maximum code length is too short.
+  pub fn encode_blocks<T: Symbol>(
+    symbols: impl IntoIterator<Item = T>,
+    options: &BlockOptions
+  ) -> Result<Vec<u8>, HuffmanError> {
+    if options.block_size == 0 {
+      return Err(HuffmanError::InvalidBlockSize);
+    }
+
+    let header = Header {
+      blocks: true,
+      ..Header::new(T::KIND, options.checksum, Vec::new(), 0)
+    };
+
+    let mut result: Vec<u8> = header.as_bytes();
+    let mut data_checksum = options.checksum.map(Checksum::new);
+    let mut previous_codes: Option<Vec<CharCodePair<T>>> = None;
+
+    let mut symbols = symbols.into_iter().peekable();
+    while symbols.peek().is_some() {
+      let block: Vec<T> = symbols.by_ref().take(options.block_size).collect();
+      if let Some(data_checksum) = &mut data_checksum {
+        T::update_checksum_with_all(&block, data_checksum);
       }
+
+      result.append(&mut Self::encode_block(&block, &mut previous_codes, options)?);
     }
 
-    // push the last current byte to make sure it went through.
-    // rotate amount 7 - ... because the else statement above already performed
-    // one rotation whenever the last bit wasn't written to
-    let rotate_amount = 7 - (current_byte_index % 8);
-    current_byte = current_byte.rotate_left(rotate_amount as u32);
-    bytes.push(current_byte);
+    result.push(END_OF_BLOCKS);
+
+    if let Some(data_checksum) = data_checksum {
+      result.extend_from_slice(&data_checksum.finish().to_be_bytes());
+    }
+
+    return Ok(result);
+  }
 
-    let mut result: Vec<u8> = Vec::new();
+  /// Compress the given bytes `b` in a single pass with adaptive Huffman
+  /// coding, where the tree changes with every byte instead of being grown
+  /// beforehand and stored with the data.
+  ///
+  /// That leaves out the table of code lengths, which makes it a good fit
+  /// for small messages. `HuffmanCoding::decompress` reverses this like any
+  /// other compressed bytes.
+  pub fn compress_adaptive(b: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+    return Self::encode_adaptive(b.iter().copied(), Some(ChecksumKind::Crc32));
+  }
+
+  /// Compress the given `symbols` of any Symbol type in a single pass with
+  /// adaptive Huffman coding, like `HuffmanCoding::compress_adaptive`, with
+  /// the checksum of the original data of the given algorithm or none at
+  /// all.
+  ///
+  /// Every symbol is written as it is in `Symbol::RAW_BITS` bits the first
+  /// time it comes up. `HuffmanCoding::decode` with the same Symbol type
+  /// reverses this. Fails if a symbol doesn't fit in that many bits.
+  pub fn encode_adaptive<T: Symbol>(
+    symbols: impl IntoIterator<Item = T>,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    let header = Header {
+      adaptive: true,
+      ..Header::new(T::KIND, checksum, Vec::new(), 0)
+    };
+
+    let mut data_checksum = checksum.map(Checksum::new);
+    let mut tree: AdaptiveHuffmanTree<T> = AdaptiveHuffmanTree::new();
+    let mut writer = BitWriter::new();
+    for symbol in symbols {
+      if let Some(data_checksum) = &mut data_checksum {
+        symbol.update_checksum(data_checksum);
+      }
+
+      tree.encode(&symbol, &mut writer)?;
+    }
 
-    // at this point, current_byte_index represents total number of bits that
-    // are used to represent the compressed data. this is used in the metadata
-    // to let the decompression algorithm know exactly the number of bits it
-    // should care about, ignoring any extra bits left in a byte so it wont
-    // think those extra bits are part of the message.
-    let metadata = Self::generate_metadata(
-      &char_codes,
-      current_byte_index
-    );
+    // all the bits in a single piece, then the 0 that ends the pieces
+    let mut result: Vec<u8> = header.as_bytes();
+    if writer.bits() > 0 {
+      Varint::write(&mut result, writer.bits());
+      result.append(&mut writer.finish());
+    }
+
+    Varint::write(&mut result, 0);
+
+    if let Some(data_checksum) = data_checksum {
+      result.extend_from_slice(&data_checksum.finish().to_be_bytes());
+    }
+
+    return Ok(result);
+  }
+
+  /// Compress the given bytes `b` with an order-1 context model, where
+  /// every byte is coded with a table of codes for the byte before it.
+  ///
+  /// That suits text, where which letter comes next depends a lot on the
+  /// one before it. Bytes that are too rare before others to pay for a
+  /// table of their own share a fallback table. `HuffmanCoding::decompress`
+  /// reverses this like any other compressed bytes.
+  pub fn compress_with_contexts(b: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+    return Self::encode_with_contexts(b.iter().copied(), Some(ChecksumKind::Crc32));
+  }
+
+  /// Compress the given `symbols` of any Symbol type with an order-1
+  /// context model, like `HuffmanCoding::compress_with_contexts`, with the
+  /// checksum of the original data of the given algorithm or none at all.
+  ///
+  /// The symbols are stored as they are when coding them wouldn't make them
+  /// any smaller. `HuffmanCoding::decode` with the same Symbol type reverses
+  /// this.
+  pub fn encode_with_contexts<T: Symbol>(
+    symbols: impl IntoIterator<Item = T>,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    let symbols: Vec<T> = symbols.into_iter().collect();
+    let codes = ContextCodes::from_frequencies(ContextFrequencies::count(&symbols), MAX_CODE_BITS)?;
+
+    let mut writer = BitWriter::new();
+    let mut previous: Option<&T> = None;
+    for symbol in &symbols {
+      codes.encode(symbol, previous, &mut writer)?;
+      previous = Some(symbol);
+    }
+
+    let mut result: Vec<u8> = codes.header(checksum).as_bytes();
+    result.append(&mut writer.finish());
+
+    return Self::finish_encoding(&symbols, result, checksum);
+  }
 
-    result.append(&mut Self::metadata_to_bytes(&metadata));
+  /// Compress the given &str `s` a word at a time, where words, runs of
+  /// whitespace and every other char are the symbols.
+  ///
+  /// Every token that comes up more than once goes in a dictionary in the
+  /// header. The others are spelled out after an escape code, char by char
+  /// with a tree of their own. That beats coding natural language char by
+  /// char by a lot. `HuffmanCoding::decompress` reverses this like any other
+  /// compressed bytes.
+  pub fn compress_words(s: &str) -> Result<Vec<u8>, HuffmanError> {
+    return Self::compress_words_with_checksum(s, Some(ChecksumKind::Crc32));
+  }
+
+  /// Compress the given &str `s` a word at a time like
+  /// `HuffmanCoding::compress_words`, with the checksum of the original data
+  /// of the given algorithm or none at all.
+  pub fn compress_words_with_checksum(
+    s: &str,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    let tokens = tokenize(s);
+    let codes = WordCodes::from_tokens(&tokens)?;
+
+    let mut writer = BitWriter::new();
+    for token in &tokens {
+      codes.encode(token, &mut writer)?;
+    }
+
+    let mut result: Vec<u8> = codes.header(checksum, writer.bits()).as_bytes();
+    result.append(&mut writer.finish());
+
+    let chars: Vec<char> = s.chars().collect();
+    return Self::finish_encoding(&chars, result, checksum);
+  }
+
+  /// Compress the given bytes `b` after running them through the transforms
+  /// of bzip2, which leave much less redundancy for Huffman coding in text
+  /// and other data with repeated strings.
+  ///
+  /// Every block of up to BWT_BLOCK_SIZE bytes goes through the
+  /// Burrows-Wheeler transform, which sorts the rotations of the block and
+  /// keeps the last byte of every one, so bytes that come before the same
+  /// strings end up next to each other. Move-to-front coding turns those
+  /// into ranks that are mostly low, and zero-run coding shortens the runs
+  /// of rank 0. The ranks and runs of every block are coded with the codes
+  /// of a HuffmanTree of its own, and its BlockHeader holds the primary
+  /// index to turn it back.
+  ///
+  /// The compressed bytes end with the CRC-32 of `b`, and a block is stored
+  /// as it is when that's smaller. `HuffmanCoding::decompress` reverses
+  /// this.
+  pub fn compress_transformed(b: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+    return Self::encode_transformed(b.iter().copied(), Some(ChecksumKind::Crc32));
+  }
+
+  /// Compress the given `symbols` of any Symbol type like
+  /// `HuffmanCoding::compress_transformed`, with the checksum of the
+  /// original data of the given algorithm or none at all.
+  ///
+  /// What goes through the transforms are the bytes `Symbol::write_stored`
+  /// writes, split into blocks between whole symbols. `HuffmanCoding::decode`
+  /// with the same Symbol type reverses this.
+  pub fn encode_transformed<T: Symbol>(
+    symbols: impl IntoIterator<Item = T>,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    let header = Header {
+      blocks: true,
+      transform: true,
+      ..Header::new(T::KIND, checksum, Vec::new(), 0)
+    };
+
+    let mut result: Vec<u8> = header.as_bytes();
+    let mut data_checksum = checksum.map(Checksum::new);
+    let mut block: Vec<u8> = Vec::new();
+    for symbol in symbols {
+      if let Some(data_checksum) = &mut data_checksum {
+        symbol.update_checksum(data_checksum);
+      }
+
+      // a symbol that doesn't fit in the block anymore starts the next one
+      let symbol_start = block.len();
+      T::write_stored(std::slice::from_ref(&symbol), &mut block);
+      if block.len() > BWT_BLOCK_SIZE {
+        let next_block = block.split_off(symbol_start);
+        result.append(&mut Self::encode_transformed_block(&block)?);
+        block = next_block;
+      }
+    }
+
+    if !block.is_empty() {
+      result.append(&mut Self::encode_transformed_block(&block)?);
+    }
+
+    result.push(END_OF_BLOCKS);
+
+    if let Some(data_checksum) = data_checksum {
+      result.extend_from_slice(&data_checksum.finish().to_be_bytes());
+    }
+
+    return Ok(result);
+  }
+
+  // compress a `block` of up to BWT_BLOCK_SIZE bytes that isn't empty into
+  // its BlockHeader and bits, after running it through the transforms and
+  // with the codes of a tree grown from the symbols that came out. if that's
+  // not smaller than the block itself, it's stored as it is
+  pub(crate) fn encode_transformed_block(block: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+    let (symbols, primary_index) = transform_block(block);
+
+    let frequencies = HuffmanTree::count_char_frequencies(symbols.iter().copied());
+    let tree = HuffmanTree::from_frequencies_with_max_code_length(frequencies, MAX_CODE_BITS)?;
+    let char_codes = Self::get_canonical_codes(&tree)?;
+    let writer = Self::write_codes(&symbols, &char_codes)?;
+
+    let block_header = BlockHeader {
+      code_lengths: Some(char_codes.iter().map(CodeLengthEntry::new).collect()),
+      stored: false,
+      transform: Some(BlockTransform { length: block.len() as u64, primary_index }),
+      bits: writer.bits(),
+    };
+
+    let stored_header = BlockHeader {
+      code_lengths: None,
+      stored: true,
+      transform: None,
+      bits: block.len() as u64 * 8,
+    };
+
+    let block_header_bytes = block_header.as_bytes();
+    let stored_header_bytes = stored_header.as_bytes();
+    let coded_length = block_header_bytes.len() + writer.bits().div_ceil(8) as usize;
+    if stored_header_bytes.len() + block.len() <= coded_length {
+      let mut result = stored_header_bytes;
+      result.extend_from_slice(block);
+      return Ok(result);
+    }
+
+    let mut result = block_header_bytes;
+    result.append(&mut writer.finish());
+    return Ok(result);
+  }
+
+  /// Compress the given bytes `b` with every Codec there is, all of them
+  /// coding the same frequencies of the bytes, and keep whichever comes out
+  /// smallest.
+  ///
+  /// `HuffmanCoding::decompress` reverses this like any other compressed
+  /// bytes.
+  pub fn compress_with_best_codec(b: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+    return Self::encode_with_best_codec(b, Some(ChecksumKind::Crc32));
+  }
+
+  /// Compress the given `symbols` of any Symbol type with every Codec there
+  /// is, like `HuffmanCoding::compress_with_best_codec`, with the checksum of
+  /// the original data of the given algorithm or none at all.
+  pub fn encode_with_best_codec<T: Symbol>(
+    symbols: &[T],
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    let frequencies = HuffmanTree::count_char_frequencies(symbols.iter().cloned());
+
+    let mut best: Option<Vec<u8>> = None;
+    for codec in Codec::ALL {
+      let result = Self::encode_with_codec(symbols, &frequencies, codec, checksum)?;
+      if best.as_ref().is_none_or(|best| result.len() < best.len()) {
+        best = Some(result);
+      }
+    }
+
+    return Ok(best.unwrap_or_default());
+  }
+
+  /// Compress the given `symbols` of any Symbol type with the given `codec`,
+  /// which codes them by the given `frequencies`, as counted by
+  /// `HuffmanTree::count_char_frequencies`. The compressed bytes end with the
+  /// checksum of the original data of the given algorithm or none at all.
+  ///
+  /// With `Codec::Huffman`, that's the codes of a tree grown from the
+  /// frequencies, like `HuffmanCoding::encode_with_checksum`. With
+  /// `Codec::Arithmetic`, the frequencies are stored in the header instead,
+  /// scaled down if they're too high. `Codec::Tans` and `Codec::Rans` store
+  /// them quantized to add up to a power of two, the size of their tables.
+  /// A single symbol always gets a Huffman code, because the other codecs
+  /// would code it in no bits at all. The symbols are stored as they are
+  /// when coding them wouldn't make them any smaller.
+  /// `HuffmanCoding::decode` with the same Symbol type reverses this.
+  ///
+  /// Fails if a symbol has no frequency, or with
+  /// `HuffmanError::TooManySymbols` if there are too many different ones for
+  /// the codec.
+  pub fn encode_with_codec<T: Symbol>(
+    symbols: &[T],
+    frequencies: &[CharFrequencyPair<T>],
+    codec: Codec,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    // decoders refuse a single symbol, as nothing bounds how many of it the
+    // bits hold
+    let codec = if frequencies.len() == 1 { Codec::Huffman } else { codec };
+    let (frequencies, mut bytes, bits) = match codec {
+      Codec::Huffman => {
+        let tree = HuffmanTree::from_frequencies_with_max_code_length(frequencies.to_vec(), MAX_CODE_BITS)?;
+        return Self::encode_with_checksum(symbols.iter().cloned(), &tree, checksum);
+      },
+      Codec::Arithmetic => {
+        let frequencies = scale_frequencies(frequencies)?;
+        let mut encoder = ArithmeticEncoder::new(frequencies.clone())?;
+        let mut writer = BitWriter::new();
+        for symbol in symbols {
+          encoder.encode(symbol, &mut writer)?;
+        }
+
+        encoder.finish(&mut writer)?;
+        let bits = writer.bits();
+        (frequencies, writer.finish(), bits)
+      },
+      Codec::Tans => {
+        let frequencies = quantize_frequencies(frequencies)?;
+        let (bytes, bits) = TansEncoder::new(frequencies.clone())?.encode(symbols)?;
+        (frequencies, bytes, bits)
+      },
+      Codec::Rans => {
+        let frequencies = quantize_frequencies(frequencies)?;
+        let (bytes, bits) = RansEncoder::new(frequencies.clone())?.encode(symbols)?;
+        (frequencies, bytes, bits)
+      },
+    };
+
+    let header = Header {
+      codec,
+      frequencies: frequencies.iter()
+        .map(|pair| CharFrequencyPair { value: pair.value.to_u32(), count: pair.count })
+        .collect(),
+      symbol_count: symbols.len() as u64,
+      ..Header::new(T::KIND, checksum, Vec::new(), bits)
+    };
+
+    let mut result: Vec<u8> = header.as_bytes();
     result.append(&mut bytes);
 
-    return result;
+    return Self::finish_encoding(symbols, result, checksum);
+  }
+
+  // finish the compressed `result` of the `symbols` by storing them as
+  // they are instead when that's smaller, then adding the checksum of the
+  // given algorithm, if any
+  fn finish_encoding<T: Symbol>(
+    symbols: &[T],
+    result: Vec<u8>,
+    checksum: Option<ChecksumKind>
+  ) -> Result<Vec<u8>, HuffmanError> {
+    let (stored_bytes, stored_bits) = Self::write_stored(symbols)?;
+    let header = Header {
+      stored: true,
+      ..Header::new(T::KIND, checksum, Vec::new(), stored_bits)
+    };
+
+    let mut result = result;
+    let mut stored_result = header.as_bytes();
+    if stored_result.len() + stored_bytes.len() < result.len() {
+      stored_result.extend_from_slice(&stored_bytes);
+      result = stored_result;
+    }
+
+    if let Some(checksum) = checksum {
+      let mut data_checksum = Checksum::new(checksum);
+      T::update_checksum_with_all(symbols, &mut data_checksum);
+      result.extend_from_slice(&data_checksum.finish().to_be_bytes());
+    }
+
+    return Ok(result);
+  }
+
+  // the codes of `char_codes` for the `symbols`. fails if a symbol has no
+  // code
+  fn write_codes<T: Symbol>(
+    symbols: &[T],
+    char_codes: &[CharCodePair<T>]
+  ) -> Result<BitWriter, HuffmanError> {
+    let index = SymbolIndex::from_symbols(char_codes.iter().map(|pair| &pair.value));
+
+    let mut writer = BitWriter::new();
+    for symbol in symbols {
+      let pair = Self::get_char_code_pair(symbol, char_codes, &index)?;
+      writer.write(pair.code, pair.bits)?;
+    }
+
+    return Ok(writer);
   }
 
-  pub fn decompress(mut b: Vec<u8>) -> String {
-    const FIRST_BIT_1_U8: u8 = 0b1000_0000;
+  // compress a single block of `symbols` into its BlockHeader and bits, with
+  // the codes of a tree grown from them or, if that's smaller and allowed by
+  // the `options`, with the `previous_codes`. if neither is smaller than the
+  // symbols themselves, they're stored as they are. afterwards the
+  // `previous_codes` are the ones the last coded block used
+  pub(crate) fn encode_block<T: Symbol>(
+    symbols: &[T],
+    previous_codes: &mut Option<Vec<CharCodePair<T>>>,
+    options: &BlockOptions
+  ) -> Result<Vec<u8>, HuffmanError> {
+    let frequencies = HuffmanTree::count_char_frequencies(symbols.iter().cloned());
+    let tree = HuffmanTree::from_frequencies_with_max_code_length(
+      frequencies.clone(),
+      options.max_code_length
+    )?;
+    let char_codes = Self::get_canonical_codes(&tree)?;
+
+    let mut block_header = BlockHeader {
+      code_lengths: Some(char_codes.iter().map(CodeLengthEntry::new).collect()),
+      stored: false,
+      transform: None,
+      bits: match Self::count_bits(&frequencies, &char_codes) {
+        Some(bits) => bits,
+        None => return Err(HuffmanError::LengthOverflow),
+      },
+    };
+
+    // the previous codes can only be used if they have a code for every
+    // symbol of this block
+    let previous_bits = match previous_codes {
+      Some(previous_codes) if options.reuse_trees => Self::count_bits(&frequencies, previous_codes),
+      _ => None,
+    };
+
+    // compare the whole size of the block, header included, in bits
+    let size = |header: &BlockHeader| header.as_bytes().len() as u64 * 8 + header.bits;
+
+    if let Some(previous_bits) = previous_bits {
+      let previous_header = BlockHeader {
+        code_lengths: None,
+        stored: false,
+        transform: None,
+        bits: previous_bits,
+      };
+      if size(&previous_header) <= size(&block_header) {
+        block_header = previous_header;
+      }
+    }
+
+    let (stored_bytes, stored_bits) = Self::write_stored(symbols)?;
+    let stored_header = BlockHeader { code_lengths: None, stored: true, transform: None, bits: stored_bits };
+    if size(&stored_header) < size(&block_header) {
+      let mut result = stored_header.as_bytes();
+      result.extend_from_slice(&stored_bytes);
+      return Ok(result);
+    }
+
+    if block_header.code_lengths.is_some() {
+      *previous_codes = Some(char_codes);
+    }
+
+    // previous_codes is always set by now
+    let char_codes = previous_codes.as_ref().unwrap();
+    let index = SymbolIndex::from_symbols(char_codes.iter().map(|pair| &pair.value));
+
+    let mut writer = BitWriter::new();
+    for symbol in symbols {
+      let pair = Self::get_char_code_pair(symbol, char_codes, &index)?;
+      writer.write(pair.code, pair.bits)?;
+    }
 
-    const LAST_BIT_0_U32: u32 = 0b1111_1111_1111_1111_1111_1111_1111_1110;
-    const LAST_BIT_1_U32: u32 = 0b0000_0000_0000_0000_0000_0000_0000_0001;
+    let mut result = block_header.as_bytes();
+    result.append(&mut writer.finish());
+    return Ok(result);
+  }
 
-    let mut result = String::new();
-    let metadata = Self::get_metadata_from_bytes(&b);
-    let dict_entries = Self::get_metadata_dictionary_entries(&metadata);
-    let char_codes = Self::dictionary_entries_to_char_code_pairs(dict_entries);
+  // the `symbols` as they are, and the number of bits they take up
+  fn write_stored<T: Symbol>(symbols: &[T]) -> Result<(Vec<u8>, u64), HuffmanError> {
+    let mut stored_bytes: Vec<u8> = Vec::new();
+    T::write_stored(symbols, &mut stored_bytes);
 
-    let last_metadata = &metadata[metadata.len() - 1];
-    let total_bits = match last_metadata.is_end() {
-      true => last_metadata.value,
-      false => panic!("Last metadata entry wasn't an END_METADATA entry"),
+    return match (stored_bytes.len() as u64).checked_mul(8) {
+      Some(bits) => Ok((stored_bytes, bits)),
+      None => Err(HuffmanError::LengthOverflow),
     };
+  }
 
-    let metadata_byte_count = metadata.len() * 10;
-    let compressed_bytes = &mut b[metadata_byte_count..];
+  // the number of bits the symbols of the `frequencies` take up with the
+  // codes of `char_codes`. None if a symbol has no code or it doesn't fit in
+  // a u64
+  pub(crate) fn count_bits<T: Symbol>(
+    frequencies: &[CharFrequencyPair<T>],
+    char_codes: &[CharCodePair<T>]
+  ) -> Option<u64> {
+    let index = SymbolIndex::from_symbols(char_codes.iter().map(|pair| &pair.value));
+
+    let mut bits: u64 = 0;
+    for pair in frequencies {
+      let code_bits = Self::get_char_code_pair(&pair.value, char_codes, &index).ok()?.bits;
+      bits = bits.checked_add(pair.count.checked_mul(code_bits as u64)?)?;
+    }
 
-    let mut current_bit_index: u64 = 0;
-    let mut current_code: u32 = 0;
-    let mut current_bits: u8 = 0;
-    while current_bit_index < total_bits {
-      let current_byte_index = current_bit_index / 8;
-      let current_byte = &mut compressed_bytes[current_byte_index as usize];
+    return Some(bits);
+  }
+
+  /// Decompress bytes that were produced by `HuffmanCoding::compress` or
+  /// `HuffmanCoding::compress_str`. Text compressed by the latter is
+  /// returned as its UTF-8 bytes.
+  ///
+  /// Fails instead of panicking when the bytes are truncated or corrupt, or
+  /// with `HuffmanError::ChecksumMismatch` when they hold a checksum of the
+  /// original data that doesn't match.
+  pub fn decompress(b: &[u8]) -> Result<Vec<u8>, HuffmanError> {
+    match Self::get_symbol_kind(b)? {
+      SymbolKind::Chars => {
+        let chars: String = Self::decode::<char>(b)?.into_iter().collect();
+        return Ok(chars.into_bytes());
+      },
+      SymbolKind::Bytes => return Self::decode::<u8>(b),
+      SymbolKind::Custom => {
+        return Err(HuffmanError::BadHeader("custom symbols must be decoded with HuffmanCoding::decode"));
+      },
+    }
+  }
+
+  /// Decompress bytes that were produced by `HuffmanCoding::encode` into the
+  /// symbols of type `T`.
+  ///
+  /// Fails if the bytes hold symbols of a different kind, or when they are
+  /// truncated or corrupt.
+  pub fn decode<T: Symbol>(b: &[u8]) -> Result<Vec<T>, HuffmanError> {
+    let (result, byte_count, checksum) = Self::decode_unchecked::<T>(b)?;
+    Self::check_checksum(b, byte_count, checksum, |data_checksum| {
+      T::update_checksum_with_all(&result, data_checksum);
+    })?;
+
+    return Ok(result);
+  }
 
-      if *current_byte & FIRST_BIT_1_U8 == FIRST_BIT_1_U8 {
-        // current bit of the byte is 1, write 1 to current bit in code
-        current_code |= LAST_BIT_1_U32;
+  // decode the symbols of `b` without checking the checksum of the original
+  // data. returns the symbols, the number of bytes up to the checksum and
+  // the kind of checksum, if any
+  fn decode_unchecked<T: Symbol>(b: &[u8]) -> Result<(Vec<T>, usize, Option<ChecksumKind>), HuffmanError> {
+    if Self::get_symbol_kind(b)? != T::KIND {
+      return Err(HuffmanError::BadHeader("symbols are of a different kind"));
+    }
+
+    let (result, byte_count, checksum) = if Header::is_header(b) {
+      let (header, header_byte_count) = Header::from_bytes(b)?;
+      let compressed_bytes = &b[header_byte_count..];
+
+      let (result, byte_count) = if header.blocks {
+        Self::decode_blocks(compressed_bytes, header.transform)?
+      } else if header.adaptive {
+        Self::decode_adaptive(compressed_bytes)?
+      } else if header.stored {
+        let mut result: Vec<T> = Vec::new();
+        let byte_count = Self::read_stored(compressed_bytes, header.bits, &mut result)?;
+        (result, byte_count)
+      } else if let Some(contexts) = &header.contexts {
+        let mut result: Vec<T> = Vec::new();
+        let byte_count = Self::decode_bits(
+          compressed_bytes,
+          header.bits,
+          &mut ContextReader::new(&header.code_lengths, contexts)?,
+          &mut result
+        )?;
+
+        (result, byte_count)
+      } else if let Some(words) = &header.words {
+        let mut result: Vec<T> = Vec::new();
+        let byte_count = Self::decode_bits(
+          compressed_bytes,
+          header.bits,
+          &mut WordReader::new(&header.code_lengths, words)?,
+          &mut result
+        )?;
+
+        (result, byte_count)
+      } else if header.codec != Codec::Huffman {
+        let mut result: Vec<T> = Vec::new();
+        let byte_count = Self::decode_bits(
+          compressed_bytes,
+          header.bits,
+          &mut CodecReader::new(&header)?,
+          &mut result
+        )?;
+
+        (result, byte_count)
       } else {
-        // current bit of the byte is 0, write 0 to current bit in code
-        current_code &= LAST_BIT_0_U32;
+        let char_codes = Self::digit_length_entries_to_char_code_pairs(&header.code_lengths, header.arity)?;
+        let mut result: Vec<T> = Vec::new();
+        let byte_count = Self::decode_bits(
+          compressed_bytes,
+          header.bits,
+          &mut CodeReader::new(char_codes)?,
+          &mut result
+        )?;
+
+        (result, byte_count)
+      };
+
+      (result, header_byte_count + byte_count, header.checksum)
+    } else {
+      let (char_codes, total_bits, header_byte_count) = Self::read_legacy_metadata(b)?;
+      let mut result: Vec<T> = Vec::new();
+      let byte_count = Self::decode_bits(
+        &b[header_byte_count..],
+        total_bits,
+        &mut CodeReader::new(char_codes)?,
+        &mut result
+      )?;
+
+      (result, header_byte_count + byte_count, None)
+    };
+
+    return Ok((result, byte_count, checksum));
+  }
+
+  // check the checksum of the given kind, if any, that follows right after
+  // the first `byte_count` bytes of `b`, against the checksum `update`
+  // takes of the original data
+  fn check_checksum(
+    b: &[u8],
+    byte_count: usize,
+    checksum: Option<ChecksumKind>,
+    update: impl FnOnce(&mut Checksum)
+  ) -> Result<(), HuffmanError> {
+    let kind = match checksum {
+      Some(kind) => kind,
+      None => return Ok(()),
+    };
+
+    let expected_checksum = match b.get(byte_count..byte_count + 4) {
+      Some(bytes) => u32::from_be_bytes(bytes.try_into().unwrap()),
+      None => return Err(HuffmanError::TruncatedInput),
+    };
+
+    let mut data_checksum = Checksum::new(kind);
+    update(&mut data_checksum);
+
+    if data_checksum.finish() != expected_checksum {
+      return Err(HuffmanError::ChecksumMismatch);
+    }
+
+    return Ok(());
+  }
+
+  // decode the blocks at the start of `b`, up to and including the
+  // END_OF_BLOCKS byte, where every block that isn't stored went through
+  // the transforms if `transformed`. returns the symbols and the number of
+  // bytes read
+  fn decode_blocks<T: Symbol>(b: &[u8], transformed: bool) -> Result<(Vec<T>, usize), HuffmanError> {
+    let mut result: Vec<T> = Vec::new();
+    let mut reader: Option<CodeReader<T>> = None;
+    let mut position: usize = 0;
+    loop {
+      let (block_header, header_byte_count) = match BlockHeader::from_bytes(&b[position..])? {
+        (Some(block_header), header_byte_count) => (block_header, header_byte_count),
+        (None, end_byte_count) => return Ok((result, position + end_byte_count)),
+      };
+      position += header_byte_count;
+
+      if block_header.stored {
+        position += Self::read_stored(&b[position..], block_header.bits, &mut result)?;
+        continue;
       }
 
-      // increment bits in the code
-      current_bits += 1;
-
-      let found_char = Self::get_char_for_code_and_bits(
-        &char_codes,
-        current_code,
-        current_bits
-      );
-
-      match found_char {
-        Some(c) => {
-          // found a char!!
-          result.push(c);
-          current_code = 0;
-          current_bits = 0;
-        },
-        None => {
-          current_code = current_code.rotate_left(1);
-        }
+      if block_header.transform.is_some() != transformed {
+        return Err(HuffmanError::BadHeader("block doesn't match the transform flag"));
+      }
+
+      // a transformed block always has codes of its own
+      if let (Some(code_lengths), Some(transform)) = (&block_header.code_lengths, &block_header.transform) {
+        let char_codes = Self::code_length_entries_to_char_code_pairs(code_lengths)?;
+        let mut symbols: Vec<TransformedSymbol> = Vec::new();
+        position += Self::decode_bits(
+          &b[position..],
+          block_header.bits,
+          &mut CodeReader::new(char_codes)?,
+          &mut symbols
+        )?;
+
+        T::read_stored(&undo_transform_block(&symbols, transform)?, &mut result)?;
+        continue;
+      }
+
+      if let Some(code_lengths) = &block_header.code_lengths {
+        let char_codes = Self::code_length_entries_to_char_code_pairs(code_lengths)?;
+        reader = Some(CodeReader::new(char_codes)?);
       }
 
-      // move on to next bit
-      *current_byte = current_byte.rotate_left(1);
-      current_bit_index += 1;
+      let reader = match &mut reader {
+        Some(reader) => reader,
+        None => return Err(HuffmanError::BadHeader("first block has no codes")),
+      };
+
+      position += Self::decode_bits(&b[position..], block_header.bits, reader, &mut result)?;
     }
+  }
 
-    return result;
+  // decode the pieces of adaptive coded bits at the start of `b`, up to and
+  // including the 0 varint after them. returns the symbols and the number of
+  // bytes read
+  fn decode_adaptive<T: Symbol>(b: &[u8]) -> Result<(Vec<T>, usize), HuffmanError> {
+    let mut result: Vec<T> = Vec::new();
+    let mut reader: AdaptiveReader<T> = AdaptiveReader::new();
+    let mut position: usize = 0;
+    loop {
+      let bits = Varint::read(b, &mut position)?;
+      if bits == 0 {
+        return Ok((result, position));
+      }
+
+      position += Self::decode_bits(&b[position..], bits, &mut reader, &mut result)?;
+    }
   }
 
-  // Traverse the HuffmanTreeNode to get all the char code pairs
-  fn get_char_code_pairs_from_tree(
-    tree: &HuffmanTreeNode,
+  // decode the first `bits` bits of `b` with the `reader` into `result`.
+  // returns the number of bytes the bits take up
+  fn decode_bits<T: Symbol>(
+    b: &[u8],
+    bits: u64,
+    reader: &mut impl SymbolReader<T>,
+    result: &mut Vec<T>
+  ) -> Result<usize, HuffmanError> {
+    if bits.div_ceil(8) > b.len() as u64 {
+      return Err(HuffmanError::TruncatedInput);
+    }
+
+    let byte_count = bits.div_ceil(8) as usize;
+    reader.read_bytes(&b[..byte_count], bits, result)?;
+
+    // the last bits didn't make up a whole code
+    reader.finish(result)?;
+
+    return Ok(byte_count);
+  }
+
+  // read the symbols stored as they are in the first `bits` bits of `b` into
+  // `result`. returns the number of bytes they take up
+  fn read_stored<T: Symbol>(
+    b: &[u8],
+    bits: u64,
+    result: &mut Vec<T>
+  ) -> Result<usize, HuffmanError> {
+    if !bits.is_multiple_of(8) {
+      return Err(HuffmanError::InvalidStoredData);
+    }
+
+    if bits / 8 > b.len() as u64 {
+      return Err(HuffmanError::TruncatedInput);
+    }
+
+    let byte_count = (bits / 8) as usize;
+    T::read_stored(&b[..byte_count], result)?;
+
+    return Ok(byte_count);
+  }
+
+  // get the canonical codes for the symbols of the `tree`. fails if a code
+  // of the tree would be longer than MAX_CODE_BITS
+  pub(crate) fn get_canonical_codes<T: Symbol>(
+    tree: &HuffmanTree<T>
+  ) -> Result<Vec<CharCodePair<T>>, HuffmanError> {
+    let mut char_codes: Vec<CharCodePair<T>> = Vec::new();
+    Self::get_char_code_pairs_from_tree(
+      &tree.trunk, // start with the trunk node
+      0, // initial call so current_code is empty (0)
+      0, // initial call so current_code_bits is none (0)
+      &mut char_codes // the Vec where char_codes will be placed
+    )?;
+
+    // only the code lengths are stored in the metadata, so swap the codes of
+    // the tree for the canonical codes that decompression can get back
+    return CanonicalCodes::from_char_code_pairs(char_codes);
+  }
+
+  // Traverse the HuffmanTreeNode to get all the char code pairs. Fails if
+  // a code would be longer than MAX_CODE_BITS
+  fn get_char_code_pairs_from_tree<T: Symbol>(
+    tree: &HuffmanTreeNode<T>,
     current_code: u32,
     current_code_bits: u8,
-    all_pairs: &mut Vec<CharCodePair>
-  ) {
+    all_pairs: &mut Vec<CharCodePair<T>>
+  ) -> Result<(), HuffmanError> {
+    let is_branch = tree.left.is_some() || tree.right.is_some();
+    if is_branch && current_code_bits >= MAX_CODE_BITS {
+      // the codes below this node don't fit in a u32
+      return Err(HuffmanError::CodeTooLong);
+    }
+
     // used to set last bit to 0
     let last_bit_0 = 0b1111_1111_1111_1111_1111_1111_1111_1110;
     // used to set last bit to 1
     let last_bit_1 = 0b0000_0000_0000_0000_0000_0000_0000_0001;
 
     let left_char = match &tree.left {
-      Some(lnode) => lnode.value,
+      Some(lnode) => lnode.value.clone(),
       None => None,
     };
 
     let right_char = match &tree.right {
-      Some(rnode) => rnode.value,
+      Some(rnode) => rnode.value.clone(),
       None => None,
     };
 
-    if left_char.is_some() {
+    if let Some(left_char) = left_char {
       // found char on the left. rotate the current code by 1 to make
       // space for the final bit. set the final bit to 0 (left). increment
       // the bit count for the left code.
-      let left_char = left_char.unwrap();
       let left_code = current_code.rotate_left(1) & last_bit_0;
 
       all_pairs.push(CharCodePair::new(
@@ -183,11 +1008,10 @@ impl HuffmanCoding {
       ));
     }
 
-    if right_char.is_some() {
+    if let Some(right_char) = right_char {
       // found char on the right. rotate the current code by 1 to make
       // space for the final bit. set the final bit to 1 (right). increment
       // the bit count for the right code.
-      let right_char = right_char.unwrap();
       let right_code = current_code.rotate_left(1) | last_bit_1;
 
       all_pairs.push(CharCodePair::new(
@@ -197,94 +1021,152 @@ impl HuffmanCoding {
       ));
     }
 
-    if tree.left.is_some() {
+    if let Some(lnode) = &tree.left {
       // moving on to the left side of the node, rotate the current code
       // to make room for this branch, and set the last bit to 0 (left)
       let branch_left_code = current_code.rotate_left(1) & last_bit_0;
 
       Self::get_char_code_pairs_from_tree(
-        &tree.left.as_ref().unwrap(),
+        lnode,
         branch_left_code,
         current_code_bits + 1,
         all_pairs
-      );
+      )?;
     }
 
-    if tree.right.is_some() {
+    if let Some(rnode) = &tree.right {
       // moving on to the right side of the node, rotate the current code
       // to make room for this branch, and set the last bit to 1 (right)
       let branch_right_code = current_code.rotate_left(1) | last_bit_1;
 
       Self::get_char_code_pairs_from_tree(
-        &tree.right.as_ref().unwrap(),
+        rnode,
         branch_right_code,
         current_code_bits + 1,
         all_pairs
-      );
+      )?;
     }
+
+    return Ok(());
   }
 
-  // get the CharCodePair for the given char `c` out of given `pairs`
-  fn get_char_code_pair(c: char, pairs: &Vec<CharCodePair>) -> &CharCodePair {
-    for pair in pairs {
-      if pair.value == c {
-        return &pair;
-      }
+  // get the CharCodePair for the given char `c` out of given `pairs`, with
+  // the `index` of where each symbol is in them
+  pub(crate) fn get_char_code_pair<'a, T: Symbol>(
+    c: &T,
+    pairs: &'a [CharCodePair<T>],
+    index: &SymbolIndex<T>
+  ) -> Result<&'a CharCodePair<T>, HuffmanError> {
+    return match index.get(c) {
+      Some(i) => Ok(&pairs[i]),
+      None => Err(HuffmanError::SymbolNotInTree(c.to_u32())),
+    };
+  }
+
+  // get the SymbolKind of compressed data `b` from its header, or from the
+  // START_METADATA entry of data from before format versions
+  fn get_symbol_kind(b: &[u8]) -> Result<SymbolKind, HuffmanError> {
+    if Header::is_header(b) {
+      return Ok(Header::from_bytes(b)?.0.kind);
     }
 
-    // crash program if char doesn't exist in given pairs
-    // maybe should do better handling but this works.
-    panic!();
+    let first_entry: [u8; 10] = match b.get(0..10) {
+      Some(entry) => entry.try_into().unwrap(),
+      None if b.len() < 2 || b.starts_with(&[0, 0]) => {
+        return Err(HuffmanError::TruncatedInput);
+      },
+      None => return Err(HuffmanError::UnknownFormat),
+    };
+
+    let first_entry = MetadataKeyValuePair::from_bytes(first_entry);
+    if !first_entry.is_start() {
+      return Err(HuffmanError::UnknownFormat);
+    }
+
+    return first_entry.symbol_kind();
   }
 
-  // generate the metadata for the CharCodePairs `pairs` and the length
-  // of the compressed data `bits`. 
-  fn generate_metadata(
-    pairs: &Vec<CharCodePair>, 
-    bits: u64
-  ) -> Vec<MetadataKeyValuePair> {
-    let mut result: Vec<MetadataKeyValuePair> = Vec::new();
-    result.push(MetadataKeyValuePair::start_metadata());
+  // read the metadata of data from before format versions, made up of 10
+  // byte entries. returns the CharCodePairs, the number of compressed bits
+  // and the number of bytes the metadata takes up
+  fn read_legacy_metadata<T: Symbol>(
+    b: &[u8]
+  ) -> Result<(Vec<CharCodePair<T>>, u64, usize), HuffmanError> {
+    let metadata = Self::get_metadata_from_bytes(b)?;
 
-    for pair in pairs {
-      result.push(MetadataKeyValuePair::new_dict_entry(pair));
-    }
+    let char_codes = if metadata.entries[0].is_canonical() {
+      Self::code_length_entries_to_char_code_pairs(&metadata.code_lengths)?
+    } else {
+      // metadata from before canonical codes has the full codes
+      let dict_entries = Self::get_metadata_dictionary_entries(&metadata.entries);
+      Self::dictionary_entries_to_char_code_pairs(dict_entries)?
+    };
 
-    result.push(MetadataKeyValuePair::end_metadata(bits));
+    // get_metadata_from_bytes only succeeds when the last entry is the end
+    let total_bits = metadata.entries[metadata.entries.len() - 1].value;
 
-    return result;
+    return Ok((char_codes, total_bits, metadata.byte_count));
   }
 
-  // convert the Vec of MetadataKeyValuePair to a Vec of bytes (u8)
-  fn metadata_to_bytes(md: &Vec<MetadataKeyValuePair>) -> Vec<u8> {
-    let mut result: Vec<u8> = Vec::new();
+  // get the canonical CharCodePairs for the code lengths of the `entries`
+  pub(crate) fn code_length_entries_to_char_code_pairs<T: Symbol>(
+    entries: &[CodeLengthEntry]
+  ) -> Result<Vec<CharCodePair<T>>, HuffmanError> {
+    return Self::digit_length_entries_to_char_code_pairs(entries, 2);
+  }
 
-    for entry in md {
-      result.extend_from_slice(&entry.as_bytes());
+  // get the canonical CharCodePairs for the code lengths of the `entries`,
+  // which count digits of the `arity`
+  pub(crate) fn digit_length_entries_to_char_code_pairs<T: Symbol>(
+    entries: &[CodeLengthEntry],
+    arity: u16
+  ) -> Result<Vec<CharCodePair<T>>, HuffmanError> {
+    let mut digit_lengths: Vec<(T, u8)> = Vec::new();
+    for entry in entries {
+      digit_lengths.push(entry.to_code_length()?);
     }
 
-    return result;
+    return CanonicalCodes::from_digit_lengths(digit_lengths, arity);
   }
 
-  // go through the compressed bytes and gather just the metadata entries
-  fn get_metadata_from_bytes(b: &Vec<u8>) -> Vec<MetadataKeyValuePair> {
-    let mut result: Vec<MetadataKeyValuePair> = Vec::new();
+  // go through the compressed bytes and gather the metadata entries, along
+  // with the code length entries that follow a canonical start entry
+  fn get_metadata_from_bytes(b: &[u8]) -> Result<Metadata, HuffmanError> {
+    let mut result = Metadata {
+      entries: Vec::new(),
+      code_lengths: Vec::new(),
+      byte_count: 0,
+    };
 
     // iterate through each possible metadata section
     // one metadata entry is 10 bytes long
-    for i in 0..(b.len() / 10) {
-      let current_bytes: [u8; 10] = b[i*10..i*10+10].try_into().unwrap();
-      let md = MetadataKeyValuePair::from_bytes(current_bytes);
+    while let Some(current_bytes) = b.get(result.byte_count..result.byte_count + 10) {
+      let md = MetadataKeyValuePair::from_bytes(current_bytes.try_into().unwrap());
+      result.byte_count += 10;
 
       if md.is_end() {
-        result.push(md);
-        return result;
-      } else {
-        result.push(md);
+        result.entries.push(md);
+        return Ok(result);
       }
+
+      if result.entries.is_empty() && md.is_canonical() {
+        // the code length entries are 5 bytes long
+        for _ in 0..md.code_length_entries() {
+          let entry_bytes = match b.get(result.byte_count..result.byte_count + 5) {
+            Some(entry_bytes) => entry_bytes.try_into().unwrap(),
+            None => return Err(HuffmanError::TruncatedInput),
+          };
+
+          result.code_lengths.push(CodeLengthEntry::from_bytes(entry_bytes));
+          result.byte_count += 5;
+        }
+      }
+
+      result.entries.push(md);
     }
 
-    return result;
+    // ran out of bytes before finding the END_METADATA entry
+    return Err(HuffmanError::TruncatedInput);
   }
 
   // filter only for the dictionary metadata entries
@@ -295,7 +1177,7 @@ impl HuffmanCoding {
 
     for entry in md {
       if entry.is_dict_entry() {
-        result.push(&entry);
+        result.push(entry);
       }
     }
 
@@ -303,31 +1185,25 @@ impl HuffmanCoding {
   }
 
   // convert all dictionary entries to char code pairs
-  fn dictionary_entries_to_char_code_pairs(
+  fn dictionary_entries_to_char_code_pairs<T: Symbol>(
     md: Vec<&MetadataKeyValuePair>,
-  ) -> Vec<CharCodePair> {
-    let mut result: Vec<CharCodePair> = Vec::new();
+  ) -> Result<Vec<CharCodePair<T>>, HuffmanError> {
+    let mut result: Vec<CharCodePair<T>> = Vec::new();
 
     for entry in md {
-      result.push(entry.to_char_code_pair());
+      result.push(entry.to_char_code_pair()?);
     }
 
-    return result;
+    return Ok(result);
   }
+}
 
-  // search the given `pairs` for a pair that has the same
-  // `bits` and `code`
-  fn get_char_for_code_and_bits(
-    pairs: &Vec<CharCodePair>,
-    code: u32, 
-    bits: u8
-  ) -> Option<char> {
-    for pair in pairs {
-      if pair.bits == bits && pair.code == code {
-        return Some(pair.value);
-      }
-    }
-
-    return None;
-  }
+// the metadata read from the start of data from before format versions
+struct Metadata {
+  // every 10 byte entry, from the START_METADATA to the END_METADATA
+  entries: Vec<MetadataKeyValuePair>,
+  // the code length entries of canonical metadata
+  code_lengths: Vec<CodeLengthEntry>,
+  // the number of bytes the metadata takes up
+  byte_count: usize,
 }
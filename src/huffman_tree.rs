@@ -1,14 +1,48 @@
+use crate::canonical::CanonicalCodes;
+use crate::char_code::MAX_CODE_BITS;
 use crate::char_frequency::CharFrequencyPair;
+use crate::error::HuffmanError;
 use crate::merge_sort::MergeSort;
+use crate::package_merge::PackageMerge;
+use crate::symbol::Symbol;
+use crate::symbol_index::SymbolIndex;
 
-pub struct HuffmanTree {
-  pub trunk: HuffmanTreeNode,
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// A Huffman tree grown from the frequencies of symbols, which are the
+/// chars of some text unless stated otherwise.
+///
+/// A tree of a single symbol gives it the 1 bit code 0, and a tree of no
+/// symbols at all is a trunk without any branches.
+pub struct HuffmanTree<T = char> {
+  pub trunk: HuffmanTreeNode<T>,
 }
 
-impl HuffmanTree {
-  // Grow a new HuffmanTree based on the given &str `s`
-  pub fn new(s: &str) -> HuffmanTree {
-    let frequencies = Self::count_char_frequencies(s);
+impl HuffmanTree<char> {
+  /// Grow a new HuffmanTree based on the given &str `s`
+  pub fn new(s: &str) -> HuffmanTree<char> {
+    return Self::from_symbols(s.chars());
+  }
+}
+
+impl HuffmanTree<u8> {
+  /// Grow a new HuffmanTree based on the given bytes `b`
+  pub fn from_bytes(b: &[u8]) -> HuffmanTree<u8> {
+    return Self::from_symbols(b.iter().copied());
+  }
+}
+
+impl<T: Symbol> HuffmanTree<T> {
+  /// Grow a new HuffmanTree based on the given `symbols`
+  pub fn from_symbols(symbols: impl IntoIterator<Item = T>) -> HuffmanTree<T> {
+    return Self::from_frequencies(Self::count_char_frequencies(symbols));
+  }
+
+  /// Grow a new HuffmanTree based on frequencies that were already counted,
+  /// like while reading through a file. Symbols of equal frequency are put
+  /// in the tree in the order of the `frequencies`
+  pub fn from_frequencies(frequencies: Vec<CharFrequencyPair<T>>) -> HuffmanTree<T> {
     let frequencies = MergeSort::run(frequencies, |a, b| {
       a.count >= b.count
     });
@@ -17,188 +51,252 @@ impl HuffmanTree {
     return HuffmanTree { trunk };
   }
 
-  // count all the char frequencies of the given string `s` and return
-  // a Vec of CharFrequencyPair
-  fn count_char_frequencies(s: &str) -> Vec<CharFrequencyPair> {
-    let mut frequencies: Vec<CharFrequencyPair> = Vec::new();
-
-    for ch in s.chars() {
-      Self::increment_char_frequency_in_pairs(ch, &mut frequencies);
-    }
-
-    return frequencies;
+  /// Grow a new HuffmanTree based on the given `symbols`, where no code is
+  /// longer than `max_code_length` bits.
+  ///
+  /// The tree is the same as `HuffmanTree::from_symbols` grows when that
+  /// one is short enough. Otherwise the code lengths are found with the
+  /// package-merge algorithm, which makes the smallest output possible
+  /// within the limit. Fails if `max_code_length` is 0, longer than
+  /// `MAX_CODE_BITS` or too short to give every symbol its own code.
+  pub fn from_symbols_with_max_code_length(
+    symbols: impl IntoIterator<Item = T>,
+    max_code_length: u8
+  ) -> Result<HuffmanTree<T>, HuffmanError> {
+    return Self::from_frequencies_with_max_code_length(
+      Self::count_char_frequencies(symbols),
+      max_code_length
+    );
   }
 
-  // increment (by one) the `CharFrequencyPair.count` for the given char `c`
-  // in the given `pairs` Vec
-  fn increment_char_frequency_in_pairs(
-    c: char, 
-    pairs: &mut Vec<CharFrequencyPair>
-  ) {
-    // check for an existing CharFrequencyPair
-    let found = Self::get_char_frequency_pair_mut(c, pairs);
-
-    match found {
-      Some(pair) => {
-        pair.count += 1;
-      },
-      None => {
-        pairs.push(CharFrequencyPair {
-          value: c,
-          count: 1,
-        });
-      }
-    }
-  }
+  /// Grow a new HuffmanTree based on frequencies that were already counted,
+  /// where no code is longer than `max_code_length` bits. Fails like
+  /// `HuffmanTree::from_symbols_with_max_code_length`
+  pub fn from_frequencies_with_max_code_length(
+    frequencies: Vec<CharFrequencyPair<T>>,
+    max_code_length: u8
+  ) -> Result<HuffmanTree<T>, HuffmanError> {
+    let frequencies = MergeSort::run(frequencies, |a, b| {
+      a.count >= b.count
+    });
 
-  // get a mutable reference to the CharFrequencyPair for the given char `c`
-  // from the given `pairs`
-  fn get_char_frequency_pair_mut(
-    c: char,
-    pairs: &mut Vec<CharFrequencyPair>
-  ) -> Option<&mut CharFrequencyPair> {
-    for pair in pairs {
-      if pair.value == c {
-        return Some(pair);
-      }
+    let fits_codes = 1u64.checked_shl(max_code_length as u32)
+      .is_none_or(|codes| codes >= frequencies.len() as u64);
+    if max_code_length == 0 || max_code_length > MAX_CODE_BITS || !fits_codes {
+      return Err(HuffmanError::InvalidCodeLengthLimit(max_code_length));
     }
 
-    return None;
-  }
-
-  fn get_total_frequency_below_node(node: &GrowingHuffmanTreeNode) -> u64 {
-    let left_frequency = match &node.left {
-      Some(lnode) => Self::get_total_frequency_below_node(&lnode),
-      None => 0,
-    };
+    let trunk = Self::grow(frequencies.clone());
+    if Self::get_depth(&trunk) <= max_code_length as u32 {
+      return Ok(HuffmanTree { trunk });
+    }
 
-    let right_frequency = match &node.right {
-      Some(rnode) => Self::get_total_frequency_below_node(&rnode),
-      None => 0,
-    };
+    let weights: Vec<u64> = frequencies.iter().map(|pair| pair.count).collect();
+    let lengths = PackageMerge::run(&weights, max_code_length);
 
-    let char_frequency = match &node.value {
-      Some(cf) => cf.count,
-      None => 0,
-    };
+    let mut code_lengths: Vec<(T, u8)> = Vec::new();
+    for (pair, bits) in frequencies.into_iter().zip(lengths) {
+      code_lengths.push((pair.value, bits));
+    }
 
-    return left_frequency + right_frequency + char_frequency;
+    let trunk = Self::grow_from_code_lengths(code_lengths)?;
+    return Ok(HuffmanTree { trunk });
   }
 
-  // insert the given `node` into the given `queue` in an ordered manner,
-  // where the total node frequencies are in order from highest to lowest
-  fn ordered_insert_node_into_queue(
-    queue: &mut Vec<GrowingHuffmanTreeNode>,
-    node: GrowingHuffmanTreeNode
-  ) -> () {
-    let node_frequency = Self::get_total_frequency_below_node(&node);
-    for i in 0..queue.len() {
-      let current_frequency = Self::get_total_frequency_below_node(&queue[i]);
-      if node_frequency > current_frequency {
-        return queue.insert(i, node);
+  /// Count how often every one of the given `symbols` appears, in the order
+  /// they first appear.
+  ///
+  /// That's the model every codec codes the symbols by, see
+  /// `HuffmanCoding::encode_with_codec`.
+  pub fn count_char_frequencies(
+    symbols: impl IntoIterator<Item = T>
+  ) -> Vec<CharFrequencyPair<T>> {
+    let mut frequencies: Vec<CharFrequencyPair<T>> = Vec::new();
+    let mut index: SymbolIndex<T> = SymbolIndex::new();
+
+    for symbol in symbols {
+      match index.get(&symbol) {
+        Some(i) => frequencies[i].count += 1,
+        None => {
+          index.insert(symbol.clone(), frequencies.len());
+          frequencies.push(CharFrequencyPair {
+            value: symbol,
+            count: 1,
+          });
+        },
       }
     }
 
-    // didn't get inserted yet, so push to top of queue
-    queue.push(node);
+    return frequencies;
   }
 
-  // grow the HuffmanTree based on the given `frequencies`
-  fn grow(frequencies: Vec<CharFrequencyPair>) -> HuffmanTreeNode {
-    let mut queue: Vec<GrowingHuffmanTreeNode> = Vec::new();
+  // grow the HuffmanTree based on the given `frequencies`, sorted from the
+  // highest count to the lowest
+  fn grow(frequencies: Vec<CharFrequencyPair<T>>) -> HuffmanTreeNode<T> {
+    // the nodes that haven't been put below another node yet, by the order
+    // they were added in. the queue holds the weight and order of each of
+    // them and pops the lowest weight first. of equal weights it pops the
+    // node added last, so symbols of equal frequency end up in the tree in
+    // the order of the `frequencies` and new branches go before older ones
+    let mut nodes: Vec<Option<GrowingHuffmanTreeNode<T>>> = Vec::new();
+    let mut queue: BinaryHeap<(Reverse<u64>, usize)> = BinaryHeap::new();
 
     for pair in frequencies {
-      queue.push(GrowingHuffmanTreeNode {
+      queue.push((Reverse(pair.count), nodes.len()));
+      nodes.push(Some(GrowingHuffmanTreeNode {
         left: None,
         right: None,
+        weight: pair.count,
         value: Some(pair),
-      });
+      }));
     }
 
     while queue.len() > 1 {
       // only enter the loop when queue has > 1 elements (at least 2 elements)
-      // so popping twice will always succeed.
-      let bottom_1 = queue.pop().unwrap();
-      let bottom_2 = queue.pop().unwrap();
-
-      let bottom_1_freq = Self::get_total_frequency_below_node(&bottom_1);
-      let bottom_2_freq = Self::get_total_frequency_below_node(&bottom_2);
+      // so popping twice will always succeed. every node is in the queue
+      // once, so it's still in `nodes` when it gets popped
+      let (_, index_1) = queue.pop().unwrap();
+      let (_, index_2) = queue.pop().unwrap();
+      let bottom_1 = nodes[index_1].take().unwrap();
+      let bottom_2 = nodes[index_2].take().unwrap();
 
       // put the bottom two nodes into left and right of a new node
       // default to placing higher frequency nodes to the right
-      if bottom_1_freq < bottom_2_freq {
+      let weight = bottom_1.weight + bottom_2.weight;
+      let (left, right) = if bottom_1.weight < bottom_2.weight {
         // bottom_1 has lesser frequency, put to the left
-        let new_node = GrowingHuffmanTreeNode {
-          left: Some(Box::new(bottom_1)),
-          right: Some(Box::new(bottom_2)),
-          value: None,
-        };
-
-        // insert the node back into the queue in order of total node frequency
-        Self::ordered_insert_node_into_queue(&mut queue, new_node);
+        (bottom_1, bottom_2)
       } else {
         // bottom_1 has greater or equal frequency, put to right
-        let new_node = GrowingHuffmanTreeNode {
-          left: Some(Box::new(bottom_2)),
-          right: Some(Box::new(bottom_1)),
-          value: None,
-        };
-
-        // insert the node back into the queue in order of total node frequency
-        Self::ordered_insert_node_into_queue(&mut queue, new_node);
-      }
+        (bottom_2, bottom_1)
+      };
+
+      queue.push((Reverse(weight), nodes.len()));
+      nodes.push(Some(GrowingHuffmanTreeNode {
+        left: Some(Box::new(left)),
+        right: Some(Box::new(right)),
+        weight,
+        value: None,
+      }));
     }
 
+    let index = match queue.pop() {
+      Some((_, index)) => index,
+      // there are no symbols at all, so there are no codes either
+      None => return HuffmanTreeNode {
+        left: None,
+        right: None,
+        value: None,
+      },
+    };
+
     // tree is built, convert to normal tree without frequencies
-    return queue[0].to_tree_node();
+    let trunk = nodes[index].take().unwrap().to_tree_node();
+    if trunk.value.is_some() {
+      // a lone symbol is a leaf without a path to it, so put it to the left
+      // of a branch to give it the 1 bit code 0
+      return HuffmanTreeNode {
+        left: Some(Box::new(trunk)),
+        right: None,
+        value: None,
+      };
+    }
+
+    return trunk;
+  }
+
+  // grow the HuffmanTree where every symbol has the canonical code of the
+  // given length
+  fn grow_from_code_lengths(
+    code_lengths: Vec<(T, u8)>
+  ) -> Result<HuffmanTreeNode<T>, HuffmanError> {
+    let mut trunk = HuffmanTreeNode {
+      left: None,
+      right: None,
+      value: None,
+    };
+
+    for pair in CanonicalCodes::from_code_lengths(code_lengths)? {
+      Self::insert_leaf(&mut trunk, pair.value, pair.code, pair.bits);
+    }
+
+    return Ok(trunk);
+  }
+
+  // insert a leaf for the `symbol` at the end of the path of the lowest
+  // `bits` bits of `code` below `node`, creating any branches along the way
+  fn insert_leaf(node: &mut HuffmanTreeNode<T>, symbol: T, code: u32, bits: u8) {
+    let mut current = node;
+    for i in (0..bits).rev() {
+      let branch = if code >> i & 1 == 1 {
+        &mut current.right
+      } else {
+        &mut current.left
+      };
+
+      current = branch.get_or_insert_with(|| Box::new(HuffmanTreeNode {
+        left: None,
+        right: None,
+        value: None,
+      }));
+    }
+
+    current.value = Some(symbol);
+  }
+
+  // the length of the longest code of the tree below `node`
+  fn get_depth(node: &HuffmanTreeNode<T>) -> u32 {
+    let left_depth = match &node.left {
+      Some(lnode) => Self::get_depth(lnode) + 1,
+      None => 0,
+    };
+
+    let right_depth = match &node.right {
+      Some(rnode) => Self::get_depth(rnode) + 1,
+      None => 0,
+    };
+
+    return left_depth.max(right_depth);
   }
 }
 
-pub struct HuffmanTreeNode {
-  pub left: Option<Box<HuffmanTreeNode>>,
-  pub right: Option<Box<HuffmanTreeNode>>,
-  pub value: Option<char>,
+/// A node of a HuffmanTree. Leaves hold a symbol `value`, branches don't.
+///
+/// Going `left` adds a 0 bit to the code, going `right` adds a 1 bit.
+pub struct HuffmanTreeNode<T = char> {
+  pub left: Option<Box<HuffmanTreeNode<T>>>,
+  pub right: Option<Box<HuffmanTreeNode<T>>>,
+  pub value: Option<T>,
 }
 
-// A HuffmanTreeNode with extra info like frequency of the char `value`
+// A HuffmanTreeNode with extra info like frequency of the symbol `value`
 //
 // "Growing" because this is used while growing or creating a Huffman tree.
 // The extra info is necessary during growth but is tossed out once it is done.
-struct GrowingHuffmanTreeNode {
-  left: Option<Box<GrowingHuffmanTreeNode>>,
-  right: Option<Box<GrowingHuffmanTreeNode>>,
-  value: Option<CharFrequencyPair>,
+struct GrowingHuffmanTreeNode<T> {
+  left: Option<Box<GrowingHuffmanTreeNode<T>>>,
+  right: Option<Box<GrowingHuffmanTreeNode<T>>>,
+  // the total frequency of all symbols below this node
+  weight: u64,
+  value: Option<CharFrequencyPair<T>>,
 }
 
-impl GrowingHuffmanTreeNode {
-  fn to_tree_node(&self) -> HuffmanTreeNode {
+impl<T: Clone> GrowingHuffmanTreeNode<T> {
+  fn to_tree_node(&self) -> HuffmanTreeNode<T> {
     // convert left growing node to tree node
-    let left: Option<HuffmanTreeNode> = match &self.left {
-      Some(lnode) => Some(lnode.to_tree_node()),
-      None => None,
-    };
+    let left: Option<HuffmanTreeNode<T>> = self.left.as_ref()
+      .map(|lnode| lnode.to_tree_node());
 
     // convert right growing node to tree node
-    let right: Option<HuffmanTreeNode> = match &self.right {
-      Some(rnode) => Some(rnode.to_tree_node()),
-      None => None,
-    };
+    let right: Option<HuffmanTreeNode<T>> = self.right.as_ref()
+      .map(|rnode| rnode.to_tree_node());
 
-    // isolate the char of this growing node without frequency
-    let value: Option<char> = match &self.value {
-      Some(cf) => Some(cf.value),
-      None => None,
-    };
+    // isolate the symbol of this growing node without frequency
+    let value: Option<T> = self.value.as_ref().map(|cf| cf.value.clone());
 
     return HuffmanTreeNode {
-      left: match left {
-        Some(lnode) => Some(Box::new(lnode)),
-        None => None,
-      },
-      right: match right {
-        Some(rnode) => Some(Box::new(rnode)),
-        None => None,
-      },
+      left: left.map(Box::new),
+      right: right.map(Box::new),
       value
     }
   }
@@ -0,0 +1,127 @@
+//! Compression and decompression of text and binary data with Huffman coding.
+//!
+//! Grow a [`HuffmanTree`] from the text, then hand both to
+//! [`HuffmanCoding::compress_str`]. Any other data can be compressed byte by
+//! byte with [`HuffmanTree::from_bytes`] and [`HuffmanCoding::compress`].
+//! The compressed bytes carry the code table as metadata, so
+//! [`HuffmanCoding::decompress`] only needs the bytes.
+//!
+//! Anything else that implements [`Symbol`], like `u16` token IDs, can be
+//! coded with [`HuffmanTree::from_symbols`], [`HuffmanCoding::encode`] and
+//! [`HuffmanCoding::decode`].
+//!
+//! ```
+//! use huffman_coding::{ HuffmanCoding, HuffmanTree };
+//!
+//! let text = "huffman coding is fun";
+//! let tree = HuffmanTree::new(text);
+//! let compressed = HuffmanCoding::compress_str(text, &tree)?;
+//!
+//! assert_eq!(HuffmanCoding::decompress(&compressed)?, text.as_bytes());
+//! # Ok::<(), huffman_coding::HuffmanError>(())
+//! ```
+//!
+//! Files too big to hold in memory can be compressed with a
+//! [`HuffmanEncoder`] and decompressed with a [`HuffmanDecoder`], which only
+//! hold a chunk of the data at a time.
+//!
+//! [`HuffmanCoding::compress_adaptive`] and
+//! [`HuffmanEncoder::compress_adaptive`] compress in a single pass with
+//! adaptive Huffman coding instead, where the tree changes along with the
+//! data and isn't stored at all. [`HuffmanCoding::compress_with_contexts`]
+//! and [`HuffmanEncoder::compress_with_contexts`] code every byte with a
+//! table of codes for the byte before it.
+//!
+//! [`HuffmanCoding::compress_words`] codes text a word at a time, with a
+//! dictionary of its words in the header, which suits large amounts of
+//! natural language.
+//!
+//! [`HuffmanCoding::encode_with_codec`] codes the same frequencies with
+//! another [`Codec`], like arithmetic coding or tANS and rANS, which can
+//! spend a fraction of a bit on a symbol.
+//! [`HuffmanCoding::compress_with_best_codec`] tries them all and keeps the
+//! smallest.
+//!
+//! [`CodeBuilder`] builds Shannon-Fano and Shannon codes from the same
+//! frequencies, for [`HuffmanCoding::encode_with_codes`] to code with and
+//! [`CodeReport`] to compare with the entropy.
+//!
+//! [`NaryHuffmanTree`] grows trees with more than two children per branch,
+//! for codes of ternary, quaternary or any other digits up to whole bytes,
+//! which [`HuffmanCoding::encode_nary`] codes with.
+//!
+//! [`HuffmanCoding::compress_transformed`] and
+//! [`HuffmanEncoder::compress_transformed`] run every block through the
+//! Burrows-Wheeler transform, move-to-front and zero-run coding first, like
+//! bzip2, which suits text and other data with repeated strings.
+//!
+//! The compressed bytes end with a CRC-32 of the original data by default,
+//! so damaged data fails to decompress instead of coming out wrong. Pick
+//! another [`ChecksumKind`] or none with
+//! [`HuffmanCoding::encode_with_checksum`].
+//!
+//! Nothing panics on bad input, every failure is returned as a
+//! [`HuffmanError`].
+
+pub mod block;
+pub mod canonical;
+pub mod char_code;
+pub mod char_frequency;
+pub mod checksum;
+pub mod code_builder;
+pub mod codec;
+pub mod error;
+pub mod header;
+pub mod huffman_coding;
+pub mod huffman_tree;
+pub mod metadata;
+pub mod nary_tree;
+pub mod stream;
+pub mod symbol;
+
+mod adaptive;
+mod ans;
+mod arithmetic;
+mod bits;
+mod context;
+mod merge_sort;
+mod package_merge;
+mod symbol_index;
+mod transform;
+mod varint;
+mod words;
+
+pub use crate::block::{ BlockOptions, BWT_BLOCK_SIZE, DEFAULT_BLOCK_SIZE };
+pub use crate::canonical::CanonicalCodes;
+pub use crate::char_code::{ CharCodePair, MAX_CODE_BITS };
+pub use crate::char_frequency::CharFrequencyPair;
+pub use crate::error::HuffmanError;
+pub use crate::checksum::{ Checksum, ChecksumKind, Crc32, XxHash32 };
+pub use crate::code_builder::{ CodeBuilder, CodeReport };
+pub use crate::codec::Codec;
+pub use crate::header::{
+  BlockHeader,
+  BlockTransform,
+  ContextCodeLengths,
+  Header,
+  WordDictionary,
+  END_OF_BLOCKS,
+  EXTENDED_FLAG_ARITY,
+  EXTENDED_FLAG_TRANSFORM,
+  FLAG_ADAPTIVE,
+  FLAG_BLOCKS,
+  FLAG_CHECKSUM,
+  FLAG_CODEC,
+  FLAG_CONTEXTS,
+  FLAG_EXTENDED,
+  FLAG_STORED,
+  FLAG_WORDS,
+  FORMAT_VERSION,
+  SIGNATURE,
+};
+pub use crate::huffman_coding::HuffmanCoding;
+pub use crate::huffman_tree::{ HuffmanTree, HuffmanTreeNode };
+pub use crate::metadata::{ CodeLengthEntry, MetadataKeyValuePair, SymbolKind };
+pub use crate::nary_tree::{ NaryHuffmanTree, NaryHuffmanTreeNode, MAX_ARITY, MIN_ARITY };
+pub use crate::stream::{ HuffmanDecoder, HuffmanEncoder };
+pub use crate::symbol::Symbol;
@@ -1,19 +1,24 @@
-mod char_code;
-mod char_frequency;
 mod cl_args;
-mod huffman_coding;
-mod huffman_tree;
-mod merge_sort;
-mod metadata;
 
-use crate::cl_args::ClArgs;
-use crate::huffman_coding::HuffmanCoding;
-use crate::huffman_tree::HuffmanTree;
+use crate::cl_args::{ ChecksumArg, ClArgs, CodeArg, CodecArg };
+
+use huffman_coding::{
+  ChecksumKind,
+  CodeBuilder,
+  CodeReport,
+  Codec,
+  HuffmanCoding,
+  HuffmanDecoder,
+  HuffmanEncoder,
+  HuffmanError,
+  HuffmanTree,
+  NaryHuffmanTree
+};
 
-use std::{ 
+use std::{
   fs,
-  io::Write,
-  path::PathBuf
+  io::{ BufReader, BufWriter, Read, Write },
+  path::Path
 };
 
 use clap::Parser;
@@ -26,16 +31,15 @@ fn main() {
     std::process::exit(args_status_code);
   }
 
-  if args.compress {
-    let contents = fs::read_to_string(args.file)
-      .expect("Failed to read passed file");
-
-    run_compression(&contents, args.output);
-  } else if args.decompress {
-    let contents = fs::read(args.file) 
-      .expect("Failed to read passed file");
+  let result = if args.compress {
+    run_compression(&args)
+  } else {
+    run_decompression(&args)
+  };
 
-    run_decompression(contents, args.output);
+  if let Err(e) = result {
+    println!("Error: {e}");
+    std::process::exit(3);
   }
 }
 
@@ -53,31 +57,134 @@ fn validate_args(args: &ClArgs) -> i32 {
   return 0;
 }
 
-fn run_compression(s: &str, output: PathBuf) {
-  let tree = HuffmanTree::new(s);
-  let bytes = HuffmanCoding::compress(s, &tree);
+fn run_compression(args: &ClArgs) -> Result<(), HuffmanError> {
+  let mut input = fs::File::open(&args.file)?;
+  let checksum = match args.checksum {
+    ChecksumArg::Crc32 => Some(ChecksumKind::Crc32),
+    ChecksumArg::Xxhash32 => Some(ChecksumKind::XxHash32),
+    ChecksumArg::None => None,
+  };
 
-  let mut file = fs::OpenOptions::new()
-    .create(true)
-    .write(true)
-    .open(output).unwrap();
+  if args.report {
+    print_report(&args.file)?;
+  }
 
-  match file.write_all(&bytes) {
-    Ok(_) => {},
-    Err(e) => println!("Error: {e}"),
-  };
+  // the codes are built from the frequencies of the whole file
+  if let Some(code) = args.code.filter(|code| *code != CodeArg::Huffman) {
+    let builder = match code {
+      CodeArg::Huffman => CodeBuilder::Huffman,
+      CodeArg::ShannonFano => CodeBuilder::ShannonFano,
+      CodeArg::Shannon => CodeBuilder::Shannon,
+    };
+
+    let mut bytes: Vec<u8> = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let char_codes = builder.build(&HuffmanTree::count_char_frequencies(bytes.iter().copied()))?;
+    let compressed = HuffmanCoding::encode_with_codes(bytes, char_codes, checksum)?;
+    create_output(&args.output)?.write_all(&compressed)?;
+    return Ok(());
+  }
+
+  // the tree is grown from the frequencies of the whole file too
+  if let Some(arity) = args.arity {
+    let mut bytes: Vec<u8> = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let tree = NaryHuffmanTree::from_bytes(&bytes, arity)?;
+    let compressed = HuffmanCoding::encode_nary(bytes, &tree, checksum)?;
+    create_output(&args.output)?.write_all(&compressed)?;
+    return Ok(());
+  }
+
+  // the words are picked from the whole text, so it's read all at once
+  if args.words {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    let compressed = HuffmanCoding::compress_words_with_checksum(&text, checksum)?;
+    create_output(&args.output)?.write_all(&compressed)?;
+    return Ok(());
+  }
+
+  // the codecs other than Huffman coding only work on all the bytes at once
+  if let Some(codec) = args.codec.filter(|codec| *codec != CodecArg::Huffman) {
+    let mut bytes: Vec<u8> = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let codec = match codec {
+      CodecArg::Huffman => Some(Codec::Huffman),
+      CodecArg::Arithmetic => Some(Codec::Arithmetic),
+      CodecArg::Tans => Some(Codec::Tans),
+      CodecArg::Rans => Some(Codec::Rans),
+      CodecArg::Best => None,
+    };
+
+    let compressed = match codec {
+      Some(codec) => {
+        let frequencies = HuffmanTree::count_char_frequencies(bytes.iter().copied());
+        HuffmanCoding::encode_with_codec(&bytes, &frequencies, codec, checksum)?
+      },
+      None => HuffmanCoding::encode_with_best_codec(&bytes, checksum)?,
+    };
+
+    create_output(&args.output)?.write_all(&compressed)?;
+    return Ok(());
+  }
+
+  let mut encoder = HuffmanEncoder::new(BufWriter::new(create_output(&args.output)?));
+  encoder.set_checksum(checksum);
+  encoder.set_max_code_length(args.max_code_length);
+  if args.adaptive {
+    encoder.compress_adaptive(&mut BufReader::new(input))?;
+    return Ok(());
+  }
+
+  if args.transform {
+    encoder.compress_transformed(&mut BufReader::new(input))?;
+    return Ok(());
+  }
+
+  if args.contexts {
+    encoder.compress_with_contexts(&mut input)?;
+    return Ok(());
+  }
+
+  match args.block_size {
+    Some(block_size) => {
+      encoder.set_block_size(block_size);
+      encoder.compress_blocks(&mut BufReader::new(input))?;
+    },
+    None => encoder.compress(&mut input)?,
+  }
+
+  return Ok(());
+}
+
+fn run_decompression(args: &ClArgs) -> Result<(), HuffmanError> {
+  let mut decoder = HuffmanDecoder::new(BufReader::new(fs::File::open(&args.file)?));
+  let mut output = BufWriter::new(create_output(&args.output)?);
+  decoder.decompress(&mut output)?;
+  return Ok(());
 }
 
-fn run_decompression(b: Vec<u8>, output: PathBuf) {
-  let string = HuffmanCoding::decompress(b);
+// print the average length of the codes of every CodeBuilder for the bytes
+// of the `file`, next to their entropy
+fn print_report(file: &Path) -> Result<(), HuffmanError> {
+  let frequencies = HuffmanTree::count_char_frequencies(fs::read(file)?);
+  for builder in CodeBuilder::ALL {
+    match builder.build(&frequencies) {
+      Ok(char_codes) => println!("{builder}: {}", CodeReport::new(&frequencies, &char_codes)?),
+      Err(e) => println!("{builder}: {e}"),
+    }
+  }
 
-  let mut file = fs::OpenOptions::new()
+  return Ok(());
+}
+
+// open the `output` file for writing, replacing anything that was in it
+fn create_output(output: &Path) -> Result<fs::File, HuffmanError> {
+  let file = fs::OpenOptions::new()
     .create(true)
     .write(true)
-    .open(output).unwrap();
+    .truncate(true)
+    .open(output)?;
 
-  match file.write_all(&string.as_bytes()) {
-    Ok(_) => {},
-    Err(e) => println!("Error: {e}"),
-  };
+  return Ok(file);
 }
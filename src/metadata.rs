@@ -1,4 +1,6 @@
-use crate::char_code::CharCodePair;
+use crate::char_code::{ CharCodePair, MAX_CODE_BITS };
+use crate::error::HuffmanError;
+use crate::symbol::Symbol;
 
 const START_METADATA: u16 = 0b0000_0000_0000_0000;
 
@@ -7,6 +9,13 @@ const MAX_DICTIONARY_ENTRY: u16 = 0b0001_1111_1111_1111;
 
 const END_METADATA: u16 = 0b1111_1111_1111_1111;
 
+// parts of the START_METADATA value. the lowest 8 bits are the SymbolKind,
+// the next bit says the metadata is canonical, and the highest 32 bits are
+// the number of CodeLengthEntry that follow a canonical start entry
+const SYMBOL_KIND_BITS: u64 = 0b1111_1111;
+const CANONICAL_FLAG: u64 = 0b1_0000_0000;
+const KNOWN_START_BITS: u64 = 0b1_1111_1111 | (u32::MAX as u64) << 32;
+
 const FIRST_BIT_0_U8: u8 = 0b0111_1111;
 const FIRST_BIT_1_U8: u8 = 0b1000_0000;
 
@@ -30,6 +39,34 @@ const LAST_BIT_1_U64: u64 = 0b0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_
 const MID_BIT_0_U64: u64 = 0b1111_1111_1111_1111_1111_1111_1111_1110_1111_1111_1111_1111_1111_1111_1111_1111;
 const MID_BIT_1_U64: u64 = 0b0000_0000_0000_0000_0000_0000_0000_0001_0000_0000_0000_0000_0000_0000_0000_0000;
 
+/// What the dictionary entries hold, stated by the START_METADATA value or
+/// the SymbolKind byte of the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+  /// Every symbol is a `char` of UTF-8 text
+  Chars = 0,
+  /// Every symbol is a byte
+  Bytes = 1,
+  /// The symbols are of a type with its own `Symbol` implementation
+  Custom = 2,
+}
+
+impl SymbolKind {
+  /// get the SymbolKind of the given number, as stored in the metadata
+  pub fn from_u8(kind: u8) -> Result<SymbolKind, HuffmanError> {
+    return match kind {
+      0 => Ok(SymbolKind::Chars),
+      1 => Ok(SymbolKind::Bytes),
+      2 => Ok(SymbolKind::Custom),
+      _ => Err(HuffmanError::BadHeader("unknown symbol kind")),
+    };
+  }
+}
+
+/// One 10 byte entry of the metadata at the start of compressed data.
+///
+/// The 2 byte `key` states what kind of entry it is, the 8 byte `value`
+/// holds the entry's data.
 #[derive(Debug)]
 pub struct MetadataKeyValuePair {
   key: u16,
@@ -37,6 +74,7 @@ pub struct MetadataKeyValuePair {
 }
 
 impl MetadataKeyValuePair {
+  /// Read an entry from its 10 bytes, as written by `as_bytes`
   pub fn from_bytes(b: [u8; 10]) -> MetadataKeyValuePair {
     let mut key: u16 = 0;
     let mut value: u64 = 0;
@@ -76,6 +114,7 @@ impl MetadataKeyValuePair {
     return MetadataKeyValuePair { key, value };
   }
 
+  /// Write this entry as 10 bytes, key first, most significant bit first
   pub fn as_bytes(&self) -> [u8; 10] {
     let mut result: [u8; 10] = [0; 10];
 
@@ -117,13 +156,29 @@ impl MetadataKeyValuePair {
     return result;
   }
 
-  pub fn start_metadata() -> MetadataKeyValuePair {
+  /// The entry that signifies the start of the metadata. `kind` states
+  /// what the dictionary entries that follow hold
+  pub fn start_metadata(kind: SymbolKind) -> MetadataKeyValuePair {
     return MetadataKeyValuePair {
       key: START_METADATA,
-      value: 0, 
+      value: kind as u64,
     };
   }
 
+  /// The entry that signifies the start of canonical metadata, which is
+  /// followed by `entries` CodeLengthEntry instead of dictionary entries
+  pub fn start_canonical_metadata(
+    kind: SymbolKind,
+    entries: u32
+  ) -> MetadataKeyValuePair {
+    return MetadataKeyValuePair {
+      key: START_METADATA,
+      value: kind as u64 | CANONICAL_FLAG | (entries as u64) << 32,
+    };
+  }
+
+  /// The entry that signifies the end of the metadata. `bits` is the number
+  /// of compressed bits that follow the metadata
   pub fn end_metadata(bits: u64) -> MetadataKeyValuePair {
     return MetadataKeyValuePair {
       key: END_METADATA,
@@ -131,13 +186,13 @@ impl MetadataKeyValuePair {
     };
   }
 
-  // create a MetadataKeyValuePair that signifies a dictionary entry of the
-  // huffman tree. So it states what char it is for, and what bits represent
-  // that char, as well as how many bits are necessary for that char
-  pub fn new_dict_entry(pair: &CharCodePair) -> MetadataKeyValuePair {
+  /// create a MetadataKeyValuePair that signifies a dictionary entry of the
+  /// huffman tree. So it states what symbol it is for, and what bits
+  /// represent that symbol, as well as how many bits are necessary for it
+  pub fn new_dict_entry<T: Symbol>(pair: &CharCodePair<T>) -> MetadataKeyValuePair {
     let mut key = DICTIONARY_ENTRY;
     let mut value: u64 = u64::MAX;
-    let mut char_value = pair.value as u32;
+    let mut char_value = pair.value.to_u32();
     let mut char_code = pair.code;
     let mut char_bits = pair.bits;
     // the dictionary key states that it's a dictionary, and how many bits
@@ -163,8 +218,8 @@ impl MetadataKeyValuePair {
     key = key.rotate_left(8);
 
     // write the value
-    // first 32 bits are for the char, because whole char can be up to 32 bits
-    // next 32 bits are for the code that represents the char
+    // first 32 bits are for the symbol, serialized as a u32
+    // next 32 bits are for the code that represents the symbol
     for i in 0..32 {
       if char_value & FIRST_BIT_1_U32 == FIRST_BIT_1_U32 {
         // current bit of char value is 1, write 1 to current bit of
@@ -201,23 +256,52 @@ impl MetadataKeyValuePair {
     };
   }
 
-  // check if this is the end of the metadata
+  /// check if this is the start of the metadata
+  pub fn is_start(&self) -> bool {
+    return self.key == START_METADATA;
+  }
+
+  /// get the SymbolKind of a START_METADATA entry. files from before there
+  /// were byte symbols always have a value of 0, so they are chars
+  pub fn symbol_kind(&self) -> Result<SymbolKind, HuffmanError> {
+    if !self.is_start() {
+      return Err(HuffmanError::BadHeader("not a start entry"));
+    }
+
+    if self.value & !KNOWN_START_BITS != 0 {
+      return Err(HuffmanError::BadHeader("unknown start flags"));
+    }
+
+    return SymbolKind::from_u8((self.value & SYMBOL_KIND_BITS) as u8);
+  }
+
+  /// check if this is the start of canonical metadata
+  pub fn is_canonical(&self) -> bool {
+    return self.is_start() && self.value & CANONICAL_FLAG == CANONICAL_FLAG;
+  }
+
+  /// the number of CodeLengthEntry that follow a canonical start entry
+  pub fn code_length_entries(&self) -> u32 {
+    return (self.value >> 32) as u32;
+  }
+
+  /// check if this is the end of the metadata
   pub fn is_end(&self) -> bool {
     return self.key & END_METADATA == END_METADATA;
   }
 
-  // check if this is a dictionary entry
+  /// check if this is a dictionary entry
   pub fn is_dict_entry(&self) -> bool {
     return self.key >= DICTIONARY_ENTRY && self.key <= MAX_DICTIONARY_ENTRY;
   }
 
-  // convert this to a CharCodePair
-  // 
-  // only works if self.is_dict_entry() == true
-  // will panic otherwise
-  pub fn to_char_code_pair(&self) -> CharCodePair {
+  /// convert this to a CharCodePair
+  ///
+  /// only works if self.is_dict_entry() == true
+  /// will return an error otherwise
+  pub fn to_char_code_pair<T: Symbol>(&self) -> Result<CharCodePair<T>, HuffmanError> {
     if !self.is_dict_entry() {
-      panic!("Tried to convert non- dictionary entry to CharCodePair");
+      return Err(HuffmanError::BadHeader("not a dictionary entry"));
     } else {
       let mut key = self.key;
       let mut value = self.value;
@@ -268,12 +352,53 @@ impl MetadataKeyValuePair {
         }
       }
 
-      return CharCodePair::new(
-        char::from_u32(char_value).unwrap(),
-        char_bits,
-        char_code,
-      );
+      // a code is at least 1 bit and at most MAX_CODE_BITS
+      if char_bits == 0 || char_bits > MAX_CODE_BITS {
+        return Err(HuffmanError::BadHeader("invalid code length"));
+      }
+
+      let value = T::from_u32(char_value)?;
+
+      return Ok(CharCodePair::new(value, char_bits, char_code));
     }
   }
 }
 
+
+/// One 5 byte entry of canonical metadata: a symbol, serialized as a `u32`,
+/// followed by the length of its code. The code itself isn't stored, because
+/// `CanonicalCodes` gets it back from the lengths.
+#[derive(Debug)]
+pub struct CodeLengthEntry {
+  pub value: u32,
+  pub bits: u8,
+}
+
+impl CodeLengthEntry {
+  /// create the entry for the symbol and code length of `pair`
+  pub fn new<T: Symbol>(pair: &CharCodePair<T>) -> CodeLengthEntry {
+    return CodeLengthEntry {
+      value: pair.value.to_u32(),
+      bits: pair.bits,
+    };
+  }
+
+  /// Read an entry from its 5 bytes, as written by `as_bytes`
+  pub fn from_bytes(b: [u8; 5]) -> CodeLengthEntry {
+    return CodeLengthEntry {
+      value: u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+      bits: b[4],
+    };
+  }
+
+  /// Write this entry as 5 bytes, most significant byte of the symbol first
+  pub fn as_bytes(&self) -> [u8; 5] {
+    let value = self.value.to_be_bytes();
+    return [value[0], value[1], value[2], value[3], self.bits];
+  }
+
+  /// deserialize the symbol of this entry, paired with its code length
+  pub fn to_code_length<T: Symbol>(&self) -> Result<(T, u8), HuffmanError> {
+    return Ok((T::from_u32(self.value)?, self.bits));
+  }
+}
@@ -0,0 +1,197 @@
+use crate::char_code::MAX_CODE_BITS;
+use crate::char_frequency::CharFrequencyPair;
+use crate::error::HuffmanError;
+use crate::huffman_tree::HuffmanTree;
+use crate::merge_sort::MergeSort;
+use crate::symbol::Symbol;
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// The fewest different digits the codes of an NaryHuffmanTree can have
+pub const MIN_ARITY: u16 = 2;
+
+/// The most different digits the codes of an NaryHuffmanTree can have, so
+/// a digit fits in a byte
+pub const MAX_ARITY: u16 = 256;
+
+/// The number of bits every digit of a code of the given `arity` takes up,
+/// enough for the highest digit. 1 for binary codes and 8 for codes of 256
+/// digits, which are whole bytes.
+pub fn digit_bits(arity: u16) -> u8 {
+  return (u16::BITS - arity.saturating_sub(1).leading_zeros()) as u8;
+}
+
+/// A Huffman tree where every branch has up to `arity` children instead of
+/// two, so the codes are made of digits of the `arity`, like ternary or
+/// quaternary codes. An arity of 256 gives codes of whole bytes, which can
+/// be decoded a byte at a time.
+///
+/// Growing it merges the `arity` nodes of the lowest frequencies at a
+/// time. For every branch to get all of its children, the symbols are
+/// padded with dummy symbols of frequency 0 until one less than their
+/// number is a multiple of `arity - 1`. The dummies sink to the bottom of
+/// the tree as leaves without a value, so no symbol gets their codes.
+///
+/// Like a HuffmanTree, a tree of a single symbol gives it the 1 digit code
+/// 0, and a tree of no symbols at all is a trunk without any children.
+pub struct NaryHuffmanTree<T = char> {
+  pub arity: u16,
+  pub trunk: NaryHuffmanTreeNode<T>,
+}
+
+impl NaryHuffmanTree<char> {
+  /// Grow a new NaryHuffmanTree of the given `arity` based on the given
+  /// &str `s`. Fails like `NaryHuffmanTree::from_frequencies`
+  pub fn new(s: &str, arity: u16) -> Result<NaryHuffmanTree<char>, HuffmanError> {
+    return Self::from_symbols(s.chars(), arity);
+  }
+}
+
+impl NaryHuffmanTree<u8> {
+  /// Grow a new NaryHuffmanTree of the given `arity` based on the given
+  /// bytes `b`. Fails like `NaryHuffmanTree::from_frequencies`
+  pub fn from_bytes(b: &[u8], arity: u16) -> Result<NaryHuffmanTree<u8>, HuffmanError> {
+    return Self::from_symbols(b.iter().copied(), arity);
+  }
+}
+
+impl<T: Symbol> NaryHuffmanTree<T> {
+  /// Grow a new NaryHuffmanTree of the given `arity` based on the given
+  /// `symbols`. Fails like `NaryHuffmanTree::from_frequencies`
+  pub fn from_symbols(
+    symbols: impl IntoIterator<Item = T>,
+    arity: u16
+  ) -> Result<NaryHuffmanTree<T>, HuffmanError> {
+    return Self::from_frequencies(HuffmanTree::count_char_frequencies(symbols), arity);
+  }
+
+  /// Grow a new NaryHuffmanTree of the given `arity` based on frequencies
+  /// that were already counted. An arity of 2 grows a tree with the same
+  /// code lengths as `HuffmanTree::from_frequencies`.
+  ///
+  /// Fails with `HuffmanError::InvalidArity` if `arity` is below
+  /// `MIN_ARITY` or above `MAX_ARITY`.
+  pub fn from_frequencies(
+    frequencies: Vec<CharFrequencyPair<T>>,
+    arity: u16
+  ) -> Result<NaryHuffmanTree<T>, HuffmanError> {
+    if !(MIN_ARITY..=MAX_ARITY).contains(&arity) {
+      return Err(HuffmanError::InvalidArity(arity));
+    }
+
+    let frequencies = MergeSort::run(frequencies, |a, b| {
+      a.count >= b.count
+    });
+
+    let trunk = Self::grow(frequencies, arity as usize);
+    return Ok(NaryHuffmanTree { arity, trunk });
+  }
+
+  /// The number of bits every digit of the codes of this tree takes up
+  pub fn digit_bits(&self) -> u8 {
+    return digit_bits(self.arity);
+  }
+
+  /// The number of digits of the code of every symbol of this tree. Fails
+  /// with `HuffmanError::CodeTooLong` if a code would take up more than
+  /// `MAX_CODE_BITS` bits
+  pub fn digit_lengths(&self) -> Result<Vec<(T, u8)>, HuffmanError> {
+    let mut result: Vec<(T, u8)> = Vec::new();
+    Self::get_digit_lengths(&self.trunk, 0, self.digit_bits(), &mut result)?;
+    return Ok(result);
+  }
+
+  // grow the tree of `arity` based on the given `frequencies`, sorted from
+  // the highest count to the lowest
+  fn grow(frequencies: Vec<CharFrequencyPair<T>>, arity: usize) -> NaryHuffmanTreeNode<T> {
+    if frequencies.len() <= 1 {
+      // a lone symbol is a leaf without a path to it, so put it below a
+      // branch to give it the 1 digit code 0
+      let children = frequencies.into_iter()
+        .map(|pair| NaryHuffmanTreeNode { children: Vec::new(), value: Some(pair.value) })
+        .collect();
+
+      return NaryHuffmanTreeNode { children, value: None };
+    }
+
+    // every merge takes `arity` nodes and puts back one, so it takes this
+    // many dummies to end up with exactly one node
+    let dummy_count = (arity - 1 - (frequencies.len() - 1) % (arity - 1)) % (arity - 1);
+
+    // the nodes that haven't been put below another node yet, by the order
+    // they were added in. like in a HuffmanTree, the queue pops the lowest
+    // weight first and of equal weights the node added last, so the dummies
+    // are added last to go first
+    let mut nodes: Vec<Option<NaryHuffmanTreeNode<T>>> = Vec::new();
+    let mut queue: BinaryHeap<(Reverse<u64>, usize)> = BinaryHeap::new();
+
+    for pair in frequencies {
+      queue.push((Reverse(pair.count), nodes.len()));
+      nodes.push(Some(NaryHuffmanTreeNode { children: Vec::new(), value: Some(pair.value) }));
+    }
+
+    for _ in 0..dummy_count {
+      queue.push((Reverse(0), nodes.len()));
+      nodes.push(Some(NaryHuffmanTreeNode { children: Vec::new(), value: None }));
+    }
+
+    while queue.len() > 1 {
+      // with the dummies, there are always `arity` nodes left to merge.
+      // every node is in the queue once, so it's still in `nodes` when it
+      // gets popped
+      let mut weight: u64 = 0;
+      let mut children: Vec<NaryHuffmanTreeNode<T>> = Vec::new();
+      while children.len() < arity {
+        let (Reverse(child_weight), index) = queue.pop().unwrap();
+        weight += child_weight;
+        children.push(nodes[index].take().unwrap());
+      }
+
+      queue.push((Reverse(weight), nodes.len()));
+      nodes.push(Some(NaryHuffmanTreeNode { children, value: None }));
+    }
+
+    let (_, index) = queue.pop().unwrap();
+    return nodes[index].take().unwrap();
+  }
+
+  // add the symbol of every leaf below `node`, which is `digits` deep, to
+  // `result` with the depth of its leaf. fails if a code would take up
+  // more than MAX_CODE_BITS bits
+  fn get_digit_lengths(
+    node: &NaryHuffmanTreeNode<T>,
+    digits: u8,
+    digit_bits: u8,
+    result: &mut Vec<(T, u8)>
+  ) -> Result<(), HuffmanError> {
+    if let Some(value) = &node.value {
+      result.push((value.clone(), digits));
+      return Ok(());
+    }
+
+    if node.children.is_empty() {
+      // a dummy, or the trunk of a tree without symbols
+      return Ok(());
+    }
+
+    if (digits as u32 + 1) * digit_bits as u32 > MAX_CODE_BITS as u32 {
+      return Err(HuffmanError::CodeTooLong);
+    }
+
+    for child in &node.children {
+      Self::get_digit_lengths(child, digits + 1, digit_bits, result)?;
+    }
+
+    return Ok(());
+  }
+}
+
+/// A node of an NaryHuffmanTree. Leaves hold a symbol `value`, or none for
+/// the dummy symbols, and branches don't.
+///
+/// Going to the child at `i` adds the digit `i` to the code.
+pub struct NaryHuffmanTreeNode<T = char> {
+  pub children: Vec<NaryHuffmanTreeNode<T>>,
+  pub value: Option<T>,
+}
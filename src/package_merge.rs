@@ -0,0 +1,91 @@
+// The package-merge algorithm, which finds the optimal code lengths for the
+// given weights where no code is longer than a given maximum.
+//
+// Coins of every weight are placed at every depth from 1 to the maximum.
+// Starting at the deepest depth, the cheapest coins are paired into
+// packages which get merged with the coins of the depth above. The cheapest
+// `2n - 2` items at depth 1 then hold every coin that is part of the optimal
+// code, and a weight's code length is the number of its coins in there.
+pub struct PackageMerge;
+
+// a coin of a weight, or a package of two items from the depth below
+#[derive(Clone, Copy)]
+enum Item {
+  Coin(usize),
+  Package(usize, usize),
+}
+
+impl PackageMerge {
+  // get the optimal code length for each of the `weights`, in the same order
+  // as `weights`, with no length longer than `max_code_length`.
+  //
+  // there must be at least 2 weights and no more than 2^max_code_length
+  pub fn run(weights: &[u64], max_code_length: u8) -> Vec<u8> {
+    // coins of every depth are in order of lowest to highest weight
+    let mut coins: Vec<usize> = (0..weights.len()).collect();
+    coins.sort_by_key(|i| weights[*i]);
+
+    // the items at every depth, deepest first, in order of weight
+    let mut depths: Vec<Vec<(u64, Item)>> = Vec::new();
+    depths.push(coins.iter().map(|i| (weights[*i], Item::Coin(*i))).collect());
+
+    for _ in 1..max_code_length {
+      let below = &depths[depths.len() - 1];
+
+      // pair the items of the depth below into packages. an odd item out is
+      // never part of the code, so it's dropped
+      let mut packages: Vec<(u64, Item)> = Vec::new();
+      for i in 0..(below.len() / 2) {
+        let weight = below[2 * i].0 + below[2 * i + 1].0;
+        packages.push((weight, Item::Package(2 * i, 2 * i + 1)));
+      }
+
+      // merge the packages with this depth's coins, coins first on ties
+      let mut merged: Vec<(u64, Item)> = Vec::new();
+      let mut coin_index: usize = 0;
+      let mut package_index: usize = 0;
+      while coin_index < coins.len() || package_index < packages.len() {
+        let take_coin = package_index >= packages.len() || (
+          coin_index < coins.len() &&
+          weights[coins[coin_index]] <= packages[package_index].0
+        );
+
+        if take_coin {
+          let i = coins[coin_index];
+          merged.push((weights[i], Item::Coin(i)));
+          coin_index += 1;
+        } else {
+          merged.push(packages[package_index]);
+          package_index += 1;
+        }
+      }
+
+      depths.push(merged);
+    }
+
+    // count the coins of each weight in the cheapest 2n - 2 items at depth 1
+    let mut lengths: Vec<u8> = vec![0; weights.len()];
+    let top = depths.len() - 1;
+    for i in 0..(2 * weights.len() - 2) {
+      Self::count_coins(&depths, top, i, &mut lengths);
+    }
+
+    return lengths;
+  }
+
+  // add one to the length of every coin inside item `i` of depth `depth`
+  fn count_coins(
+    depths: &[Vec<(u64, Item)>],
+    depth: usize,
+    i: usize,
+    lengths: &mut [u8]
+  ) {
+    match depths[depth][i].1 {
+      Item::Coin(weight_index) => lengths[weight_index] += 1,
+      Item::Package(a, b) => {
+        Self::count_coins(depths, depth - 1, a, lengths);
+        Self::count_coins(depths, depth - 1, b, lengths);
+      },
+    }
+  }
+}
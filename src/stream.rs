@@ -0,0 +1,785 @@
+use crate::adaptive::{ AdaptiveHuffmanTree, AdaptiveReader };
+use crate::bits::{ BitWriter, CodeReader, SymbolReader };
+use crate::block::{ BlockOptions, BWT_BLOCK_SIZE, DEFAULT_BLOCK_SIZE };
+use crate::char_code::{ CharCodePair, MAX_CODE_BITS };
+use crate::char_frequency::CharFrequencyPair;
+use crate::checksum::{ Checksum, ChecksumKind };
+use crate::codec::{ Codec, CodecReader };
+use crate::context::{ ContextCodes, ContextFrequencies, ContextReader };
+use crate::error::HuffmanError;
+use crate::header::{ BlockHeader, BlockTransform, Header, END_OF_BLOCKS, SIGNATURE };
+use crate::huffman_coding::HuffmanCoding;
+use crate::huffman_tree::HuffmanTree;
+use crate::metadata::{ CodeLengthEntry, SymbolKind };
+use crate::symbol::Symbol;
+use crate::transform::{ undo_transform_block, TransformedSymbol };
+use crate::varint::Varint;
+use crate::words::WordReader;
+
+use std::io::{ ErrorKind, Read, Seek, SeekFrom, Write };
+
+// the number of bytes read at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Compresses the bytes of a seekable source, like a file, into a writer
+/// while holding only a chunk of them at a time.
+///
+/// The source is read twice, first to count the bytes and grow the tree,
+/// then to compress them, or to copy them as they are if compressing
+/// wouldn't make them any smaller. The output is in the same format as
+/// `HuffmanCoding::compress` writes, so both `HuffmanCoding::decompress`
+/// and a HuffmanDecoder can read it.
+///
+/// ```
+/// use huffman_coding::{ HuffmanDecoder, HuffmanEncoder };
+/// use std::io::Cursor;
+///
+/// let text = "huffman coding is fun";
+/// let mut encoder = HuffmanEncoder::new(Vec::new());
+/// encoder.compress(&mut Cursor::new(text))?;
+///
+/// let compressed = encoder.into_inner();
+/// let mut decompressed: Vec<u8> = Vec::new();
+/// HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed)?;
+///
+/// assert_eq!(decompressed, text.as_bytes());
+/// # Ok::<(), huffman_coding::HuffmanError>(())
+/// ```
+pub struct HuffmanEncoder<W: Write> {
+  writer: W,
+  checksum: Option<ChecksumKind>,
+  max_code_length: Option<u8>,
+  block_size: usize,
+  reuse_trees: bool,
+}
+
+impl<W: Write> HuffmanEncoder<W> {
+  /// Start compressing into `writer`, with a CRC-32 of the original data
+  /// and no limit on the length of the codes
+  pub fn new(writer: W) -> HuffmanEncoder<W> {
+    return HuffmanEncoder {
+      writer,
+      checksum: Some(ChecksumKind::Crc32),
+      max_code_length: None,
+      block_size: DEFAULT_BLOCK_SIZE,
+      reuse_trees: true,
+    };
+  }
+
+  /// Store the checksum of the original data with the given algorithm, or
+  /// none at all when `checksum` is `None`
+  pub fn set_checksum(&mut self, checksum: Option<ChecksumKind>) {
+    self.checksum = checksum;
+  }
+
+  /// Keep every code at most `max_code_length` bits long, or lift the limit
+  /// when it's `None`
+  pub fn set_max_code_length(&mut self, max_code_length: Option<u8>) {
+    self.max_code_length = max_code_length;
+  }
+
+  /// Split the data into blocks of `block_size` bytes when compressing it
+  /// with `HuffmanEncoder::compress_blocks`
+  pub fn set_block_size(&mut self, block_size: usize) {
+    self.block_size = block_size;
+  }
+
+  /// Let a block use the codes of the block before it when that's smaller,
+  /// when compressing with `HuffmanEncoder::compress_blocks`
+  pub fn set_reuse_trees(&mut self, reuse_trees: bool) {
+    self.reuse_trees = reuse_trees;
+  }
+
+  /// Compress everything from the current position of `reader` to its end.
+  ///
+  /// Fails if reading or writing fails, if the maximum code length is too
+  /// short, or with `HuffmanError::InputChanged` if the bytes change
+  /// between the two times they're read.
+  pub fn compress<R: Read + Seek>(&mut self, reader: &mut R) -> Result<(), HuffmanError> {
+    let start = reader.stream_position()?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+
+    // read through once to count how often every byte appears
+    let mut counts = [0u64; 256];
+    loop {
+      let n = read_chunk(reader, &mut buffer)?;
+      if n == 0 {
+        break;
+      }
+
+      for byte in &buffer[..n] {
+        counts[*byte as usize] += 1;
+      }
+    }
+
+    let mut frequencies: Vec<CharFrequencyPair<u8>> = Vec::new();
+    for (byte, count) in counts.iter().enumerate() {
+      if *count > 0 {
+        frequencies.push(CharFrequencyPair { value: byte as u8, count: *count });
+      }
+    }
+
+    let tree = match self.max_code_length {
+      Some(bits) => HuffmanTree::from_frequencies_with_max_code_length(frequencies, bits)?,
+      None => HuffmanTree::from_frequencies(frequencies),
+    };
+    let char_codes = HuffmanCoding::get_canonical_codes(&tree)?;
+
+    // the code and code length of every byte, so each one is a single lookup.
+    // bytes without a code have a length of 0
+    let mut codes = [(0u32, 0u8); 256];
+    let mut bits: u64 = 0;
+    for pair in &char_codes {
+      codes[pair.value as usize] = (pair.code, pair.bits);
+
+      let code_bits = counts[pair.value as usize].checked_mul(pair.bits as u64);
+      bits = match code_bits.and_then(|code_bits| bits.checked_add(code_bits)) {
+        Some(bits) => bits,
+        None => return Err(HuffmanError::LengthOverflow),
+      };
+    }
+
+    let code_lengths = char_codes.iter().map(CodeLengthEntry::new).collect();
+    let header = Header::new(SymbolKind::Bytes, self.checksum, code_lengths, bits);
+
+    let byte_count = counts.iter().sum();
+    return self.write_coded_or_stored(reader, start, header, byte_count, |chunk, bit_writer| {
+      for byte in chunk {
+        let (code, code_bits) = codes[*byte as usize];
+        if code_bits == 0 {
+          return Err(HuffmanError::SymbolNotInTree(*byte as u32));
+        }
+
+        bit_writer.write(code, code_bits)?;
+      }
+
+      return Ok(());
+    });
+  }
+
+  /// Compress everything from the current position of `reader` to its end
+  /// with an order-1 context model, like
+  /// `HuffmanCoding::compress_with_contexts`.
+  ///
+  /// Fails like `HuffmanEncoder::compress`.
+  pub fn compress_with_contexts<R: Read + Seek>(&mut self, reader: &mut R) -> Result<(), HuffmanError> {
+    let start = reader.stream_position()?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+
+    // read through once to count how often every byte follows every other
+    let mut counts = vec![[0u64; 256]; 256];
+    let mut first: Option<u8> = None;
+    let mut previous: Option<u8> = None;
+    loop {
+      let n = read_chunk(reader, &mut buffer)?;
+      if n == 0 {
+        break;
+      }
+
+      for byte in &buffer[..n] {
+        match previous {
+          Some(previous) => counts[previous as usize][*byte as usize] += 1,
+          None => first = Some(*byte),
+        }
+
+        previous = Some(*byte);
+      }
+    }
+
+    let mut frequencies = ContextFrequencies { first, contexts: Vec::new() };
+    for (context, context_counts) in counts.iter().enumerate() {
+      let context_frequencies: Vec<CharFrequencyPair<u8>> = context_counts.iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(byte, count)| CharFrequencyPair { value: byte as u8, count: *count })
+        .collect();
+
+      if !context_frequencies.is_empty() {
+        frequencies.contexts.push((context as u8, context_frequencies));
+      }
+    }
+
+    let byte_count = counts.iter().flatten().sum::<u64>() + first.is_some() as u64;
+    let codes = ContextCodes::from_frequencies(
+      frequencies,
+      self.max_code_length.unwrap_or(MAX_CODE_BITS)
+    )?;
+
+    let header = codes.header(self.checksum);
+    let mut previous: Option<u8> = None;
+    return self.write_coded_or_stored(reader, start, header, byte_count, |chunk, bit_writer| {
+      for byte in chunk {
+        codes.encode(byte, previous.as_ref(), bit_writer)?;
+        previous = Some(*byte);
+      }
+
+      return Ok(());
+    });
+  }
+
+  // write the `coded_header`, then read through `reader` again from `start`
+  // and compress every chunk with `encode`. the `byte_count` bytes are
+  // copied as they are instead when that's smaller
+  fn write_coded_or_stored<R: Read + Seek>(
+    &mut self,
+    reader: &mut R,
+    start: u64,
+    coded_header: Header,
+    byte_count: u64,
+    mut encode: impl FnMut(&[u8], &mut BitWriter) -> Result<(), HuffmanError>
+  ) -> Result<(), HuffmanError> {
+    let stored_bits = match byte_count.checked_mul(8) {
+      Some(bits) => bits,
+      None => return Err(HuffmanError::LengthOverflow),
+    };
+    let stored_header = Header {
+      stored: true,
+      ..Header::new(SymbolKind::Bytes, self.checksum, Vec::new(), stored_bits)
+    };
+
+    // store the bytes as they are when compressing them doesn't pay off
+    let coded_header_bytes = coded_header.as_bytes();
+    let stored_header_bytes = stored_header.as_bytes();
+    let stored = (stored_header_bytes.len() as u64).saturating_add(byte_count)
+      < (coded_header_bytes.len() as u64).saturating_add(coded_header.bits.div_ceil(8));
+
+    let (header, header_bytes) = if stored {
+      (stored_header, stored_header_bytes)
+    } else {
+      (coded_header, coded_header_bytes)
+    };
+    self.writer.write_all(&header_bytes)?;
+
+    // read through again to compress the bytes
+    reader.seek(SeekFrom::Start(start))?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut data_checksum = self.checksum.map(Checksum::new);
+    let mut bit_writer = BitWriter::new();
+    let mut stored_bits: u64 = 0;
+    loop {
+      let n = read_chunk(reader, &mut buffer)?;
+      if n == 0 {
+        break;
+      }
+
+      let chunk = &buffer[..n];
+      if let Some(data_checksum) = &mut data_checksum {
+        data_checksum.update(chunk);
+      }
+
+      if stored {
+        self.writer.write_all(chunk)?;
+        stored_bits += n as u64 * 8;
+        continue;
+      }
+
+      encode(chunk, &mut bit_writer)?;
+      self.writer.write_all(&bit_writer.take_bytes())?;
+    }
+
+    // the header already promised this many bits
+    let written_bits = if stored { stored_bits } else { bit_writer.bits() };
+    if written_bits != header.bits {
+      return Err(HuffmanError::InputChanged);
+    }
+
+    self.writer.write_all(&bit_writer.finish())?;
+    if let Some(data_checksum) = data_checksum {
+      self.writer.write_all(&data_checksum.finish().to_be_bytes())?;
+    }
+
+    self.writer.flush()?;
+    return Ok(());
+  }
+
+  /// Compress everything `reader` gives until its end in blocks, each with
+  /// its own tree, like `HuffmanCoding::compress_blocks`.
+  ///
+  /// Only one block is held at a time and the reader is read just once, so
+  /// it doesn't have to be seekable, like a pipe or a socket. Fails if
+  /// reading or writing fails, if the block size is 0 or the maximum code
+  /// length is too short.
+  pub fn compress_blocks<R: Read>(&mut self, reader: &mut R) -> Result<(), HuffmanError> {
+    let options = BlockOptions {
+      block_size: self.block_size,
+      reuse_trees: self.reuse_trees,
+      max_code_length: self.max_code_length.unwrap_or(MAX_CODE_BITS),
+      checksum: self.checksum,
+    };
+
+    if options.block_size == 0 {
+      return Err(HuffmanError::InvalidBlockSize);
+    }
+
+    let header = Header {
+      blocks: true,
+      ..Header::new(SymbolKind::Bytes, options.checksum, Vec::new(), 0)
+    };
+    self.writer.write_all(&header.as_bytes())?;
+
+    let mut data_checksum = options.checksum.map(Checksum::new);
+    let mut previous_codes: Option<Vec<CharCodePair<u8>>> = None;
+    let mut block: Vec<u8> = vec![0u8; options.block_size];
+    loop {
+      let block_length = read_block(reader, &mut block)?;
+      if block_length == 0 {
+        break;
+      }
+
+      let block = &block[..block_length];
+      if let Some(data_checksum) = &mut data_checksum {
+        data_checksum.update(block);
+      }
+
+      let bytes = HuffmanCoding::encode_block(block, &mut previous_codes, &options)?;
+      self.writer.write_all(&bytes)?;
+    }
+
+    self.writer.write_all(&[END_OF_BLOCKS])?;
+    if let Some(data_checksum) = data_checksum {
+      self.writer.write_all(&data_checksum.finish().to_be_bytes())?;
+    }
+
+    self.writer.flush()?;
+    return Ok(());
+  }
+
+  /// Compress everything `reader` gives until its end in blocks of up to
+  /// BWT_BLOCK_SIZE bytes, each run through the Burrows-Wheeler transform,
+  /// move-to-front and zero-run coding on its own before it's coded with
+  /// its own tree, like `HuffmanCoding::compress_transformed`.
+  ///
+  /// Only one block is held at a time and the reader is read just once.
+  /// Fails if reading or writing fails.
+  pub fn compress_transformed<R: Read>(&mut self, reader: &mut R) -> Result<(), HuffmanError> {
+    let header = Header {
+      blocks: true,
+      transform: true,
+      ..Header::new(SymbolKind::Bytes, self.checksum, Vec::new(), 0)
+    };
+    self.writer.write_all(&header.as_bytes())?;
+
+    let mut data_checksum = self.checksum.map(Checksum::new);
+    let mut block: Vec<u8> = vec![0u8; BWT_BLOCK_SIZE];
+    loop {
+      let block_length = read_block(reader, &mut block)?;
+      if block_length == 0 {
+        break;
+      }
+
+      let block = &block[..block_length];
+      if let Some(data_checksum) = &mut data_checksum {
+        data_checksum.update(block);
+      }
+
+      self.writer.write_all(&HuffmanCoding::encode_transformed_block(block)?)?;
+    }
+
+    self.writer.write_all(&[END_OF_BLOCKS])?;
+    if let Some(data_checksum) = data_checksum {
+      self.writer.write_all(&data_checksum.finish().to_be_bytes())?;
+    }
+
+    self.writer.flush()?;
+    return Ok(());
+  }
+
+  /// Compress everything `reader` gives until its end in a single pass with
+  /// adaptive Huffman coding, like `HuffmanCoding::compress_adaptive`.
+  ///
+  /// Every chunk `reader` gives is compressed, written and flushed before
+  /// the next one is read, so over a socket the other side can decompress
+  /// the data as it comes in. Fails if reading or writing fails.
+  pub fn compress_adaptive<R: Read>(&mut self, reader: &mut R) -> Result<(), HuffmanError> {
+    let header = Header {
+      adaptive: true,
+      ..Header::new(SymbolKind::Bytes, self.checksum, Vec::new(), 0)
+    };
+    self.writer.write_all(&header.as_bytes())?;
+
+    let mut data_checksum = self.checksum.map(Checksum::new);
+    let mut tree: AdaptiveHuffmanTree<u8> = AdaptiveHuffmanTree::new();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+      let n = read_chunk(reader, &mut buffer)?;
+      if n == 0 {
+        break;
+      }
+
+      let chunk = &buffer[..n];
+      if let Some(data_checksum) = &mut data_checksum {
+        data_checksum.update(chunk);
+      }
+
+      let mut bit_writer = BitWriter::new();
+      for byte in chunk {
+        tree.encode(byte, &mut bit_writer)?;
+      }
+
+      // every chunk is a piece of its own, the number of its bits first
+      let mut piece: Vec<u8> = Vec::new();
+      Varint::write(&mut piece, bit_writer.bits());
+      piece.append(&mut bit_writer.finish());
+      self.writer.write_all(&piece)?;
+      self.writer.flush()?;
+    }
+
+    let mut end: Vec<u8> = Vec::new();
+    Varint::write(&mut end, 0);
+    self.writer.write_all(&end)?;
+    if let Some(data_checksum) = data_checksum {
+      self.writer.write_all(&data_checksum.finish().to_be_bytes())?;
+    }
+
+    self.writer.flush()?;
+    return Ok(());
+  }
+
+  /// Get back the writer the compressed bytes went to
+  pub fn into_inner(self) -> W {
+    return self.writer;
+  }
+}
+
+/// Decompresses data from a reader into a writer while holding only a
+/// chunk of it at a time.
+///
+/// Reads anything `HuffmanEncoder` or the compression of `HuffmanCoding`
+/// wrote, where text comes out as its UTF-8 bytes. Data from before format
+/// versions is read whole, because its metadata doesn't say how long it is.
+pub struct HuffmanDecoder<R: Read> {
+  reader: R,
+  // the bytes that have been read but not used yet, from `position` on
+  buffer: Vec<u8>,
+  position: usize,
+}
+
+impl<R: Read> HuffmanDecoder<R> {
+  /// Start decompressing what's read from `reader`
+  pub fn new(reader: R) -> HuffmanDecoder<R> {
+    return HuffmanDecoder {
+      reader,
+      buffer: Vec::new(),
+      position: 0,
+    };
+  }
+
+  /// Decompress the data into `writer`. The reader may be read past the end
+  /// of the compressed data.
+  ///
+  /// Fails like `HuffmanCoding::decompress`. Since the bytes are written as
+  /// they're decompressed, what was written before a failure may be
+  /// incomplete or, when the checksum doesn't match, wrong.
+  pub fn decompress<W: Write>(&mut self, writer: &mut W) -> Result<(), HuffmanError> {
+    // data from before format versions has no signature
+    while self.buffer.len() < SIGNATURE.len() && self.read_more()? {}
+    if !Header::is_header(&self.buffer) {
+      let mut b = std::mem::take(&mut self.buffer);
+      self.reader.read_to_end(&mut b)?;
+      writer.write_all(&HuffmanCoding::decompress(&b)?)?;
+      return Ok(());
+    }
+
+    let header = self.read_part(Header::from_bytes)?;
+    match header.kind {
+      SymbolKind::Chars => {
+        return self.decode_symbols::<char, W>(&header, writer, |c, output| {
+          output.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        });
+      },
+      SymbolKind::Bytes => {
+        return self.decode_symbols::<u8, W>(&header, writer, |byte, output| {
+          output.push(*byte);
+        });
+      },
+      SymbolKind::Custom => {
+        return Err(HuffmanError::BadHeader("custom symbols must be decoded with HuffmanCoding::decode"));
+      },
+    }
+  }
+
+  /// Get back the reader the compressed bytes came from
+  pub fn into_inner(self) -> R {
+    return self.reader;
+  }
+
+  // decode the compressed bits after the `header`, however they're laid out,
+  // then check the checksum after them. `write_symbol` turns every symbol
+  // into the bytes to write
+  fn decode_symbols<T: Symbol, W: Write>(
+    &mut self,
+    header: &Header,
+    writer: &mut W,
+    write_symbol: fn(&T, &mut Vec<u8>)
+  ) -> Result<(), HuffmanError> {
+    let mut data_checksum = header.checksum.map(Checksum::new);
+
+    if header.blocks {
+      let mut code_reader: Option<CodeReader<T>> = None;
+      while let Some(block_header) = self.read_part(BlockHeader::from_bytes)? {
+        if block_header.stored {
+          self.copy_stored(block_header.bits, &mut data_checksum, writer)?;
+          continue;
+        }
+
+        if block_header.transform.is_some() != header.transform {
+          return Err(HuffmanError::BadHeader("block doesn't match the transform flag"));
+        }
+
+        // a transformed block always has codes of its own
+        if let (Some(code_lengths), Some(transform)) = (&block_header.code_lengths, &block_header.transform) {
+          let char_codes = HuffmanCoding::code_length_entries_to_char_code_pairs(code_lengths)?;
+          let mut code_reader: CodeReader<TransformedSymbol> = CodeReader::new(char_codes)?;
+          let symbols = self.read_transformed_symbols(block_header.bits, &mut code_reader, transform)?;
+
+          // like stored bytes, the bytes of the block are the very bytes
+          // that get written and checksummed
+          let bytes = undo_transform_block(&symbols, transform)?;
+          if let Some(data_checksum) = &mut data_checksum {
+            data_checksum.update(&bytes);
+          }
+
+          writer.write_all(&bytes)?;
+          continue;
+        }
+
+        if let Some(code_lengths) = &block_header.code_lengths {
+          let char_codes = HuffmanCoding::code_length_entries_to_char_code_pairs(code_lengths)?;
+          code_reader = Some(CodeReader::new(char_codes)?);
+        }
+
+        let code_reader = match &mut code_reader {
+          Some(code_reader) => code_reader,
+          None => return Err(HuffmanError::BadHeader("first block has no codes")),
+        };
+
+        self.decode_bits(block_header.bits, code_reader, &mut data_checksum, writer, write_symbol)?;
+      }
+    } else if header.adaptive {
+      let mut adaptive_reader: AdaptiveReader<T> = AdaptiveReader::new();
+      loop {
+        let bits = self.read_part(|b| {
+          let mut position: usize = 0;
+          let bits = Varint::read(b, &mut position)?;
+          return Ok((bits, position));
+        })?;
+
+        if bits == 0 {
+          break;
+        }
+
+        self.decode_bits(bits, &mut adaptive_reader, &mut data_checksum, writer, write_symbol)?;
+      }
+    } else if header.stored {
+      self.copy_stored(header.bits, &mut data_checksum, writer)?;
+    } else if let Some(contexts) = &header.contexts {
+      let mut context_reader: ContextReader<T> = ContextReader::new(&header.code_lengths, contexts)?;
+      self.decode_bits(header.bits, &mut context_reader, &mut data_checksum, writer, write_symbol)?;
+    } else if let Some(words) = &header.words {
+      let mut word_reader: WordReader<T> = WordReader::new(&header.code_lengths, words)?;
+      self.decode_bits(header.bits, &mut word_reader, &mut data_checksum, writer, write_symbol)?;
+    } else if header.codec != Codec::Huffman {
+      let mut codec_reader: CodecReader<T> = CodecReader::new(header)?;
+      self.decode_bits(header.bits, &mut codec_reader, &mut data_checksum, writer, write_symbol)?;
+    } else {
+      let char_codes = HuffmanCoding::digit_length_entries_to_char_code_pairs(&header.code_lengths, header.arity)?;
+      let mut code_reader = CodeReader::new(char_codes)?;
+      self.decode_bits(header.bits, &mut code_reader, &mut data_checksum, writer, write_symbol)?;
+    }
+
+    if let Some(data_checksum) = data_checksum {
+      let trailer = self.read_part(|b| match b.get(0..4) {
+        Some(bytes) => Ok((u32::from_be_bytes(bytes.try_into().unwrap()), 4)),
+        None => Err(HuffmanError::TruncatedInput),
+      })?;
+
+      if data_checksum.finish() != trailer {
+        return Err(HuffmanError::ChecksumMismatch);
+      }
+    }
+
+    writer.flush()?;
+    return Ok(());
+  }
+
+  // decode the next `bits` bits with the `code_reader` and write the
+  // symbols to `writer`, a chunk at a time
+  fn decode_bits<T: Symbol, W: Write>(
+    &mut self,
+    bits: u64,
+    code_reader: &mut impl SymbolReader<T>,
+    data_checksum: &mut Option<Checksum>,
+    writer: &mut W,
+    write_symbol: fn(&T, &mut Vec<u8>)
+  ) -> Result<(), HuffmanError> {
+    let mut symbols: Vec<T> = Vec::new();
+    let mut output: Vec<u8> = Vec::new();
+    let mut bits_left = bits;
+    while bits_left > 0 {
+      if self.position == self.buffer.len() && !self.read_more()? {
+        return Err(HuffmanError::TruncatedInput);
+      }
+
+      // as many of the bits as there are bytes of them
+      let bytes = &self.buffer[self.position..];
+      let bytes = &bytes[..bytes.len().min(bits_left.div_ceil(8) as usize)];
+      let bits_in_bytes = bits_left.min(bytes.len() as u64 * 8);
+
+      code_reader.read_bytes(bytes, bits_in_bytes, &mut symbols)?;
+      self.position += bytes.len();
+      bits_left -= bits_in_bytes;
+
+      if bits_left == 0 {
+        // the last bits didn't make up a whole code
+        code_reader.finish(&mut symbols)?;
+      }
+
+      if let Some(data_checksum) = data_checksum {
+        T::update_checksum_with_all(&symbols, data_checksum);
+      }
+
+      for symbol in &symbols {
+        write_symbol(symbol, &mut output);
+      }
+
+      writer.write_all(&output)?;
+      symbols.clear();
+      output.clear();
+    }
+
+    return Ok(());
+  }
+
+  // decode the next `bits` bits of a block of the `transform` with the
+  // `code_reader`, a chunk at a time. a block never has more symbols than
+  // bytes, so damaged data that does is refused before it piles up
+  fn read_transformed_symbols(
+    &mut self,
+    bits: u64,
+    code_reader: &mut CodeReader<TransformedSymbol>,
+    transform: &BlockTransform
+  ) -> Result<Vec<TransformedSymbol>, HuffmanError> {
+    let mut symbols: Vec<TransformedSymbol> = Vec::new();
+    let mut bits_left = bits;
+    while bits_left > 0 {
+      if self.position == self.buffer.len() && !self.read_more()? {
+        return Err(HuffmanError::TruncatedInput);
+      }
+
+      let bytes = &self.buffer[self.position..];
+      let bytes = &bytes[..bytes.len().min(bits_left.div_ceil(8) as usize)];
+      let bits_in_bytes = bits_left.min(bytes.len() as u64 * 8);
+
+      code_reader.read_bytes(bytes, bits_in_bytes, &mut symbols)?;
+      self.position += bytes.len();
+      bits_left -= bits_in_bytes;
+
+      if symbols.len() as u64 > transform.length {
+        return Err(HuffmanError::InvalidTransform);
+      }
+    }
+
+    // the last bits didn't make up a whole code
+    code_reader.finish(&mut symbols)?;
+    return Ok(symbols);
+  }
+
+  // copy the next `bits` bits of symbols that are stored as they are to
+  // `writer`, a chunk at a time. bytes and chars are stored as the very
+  // bytes that get written and checksummed, so there's nothing to decode
+  fn copy_stored<W: Write>(
+    &mut self,
+    bits: u64,
+    data_checksum: &mut Option<Checksum>,
+    writer: &mut W
+  ) -> Result<(), HuffmanError> {
+    if !bits.is_multiple_of(8) {
+      return Err(HuffmanError::InvalidStoredData);
+    }
+
+    let mut bytes_left = bits / 8;
+    while bytes_left > 0 {
+      if self.position == self.buffer.len() && !self.read_more()? {
+        return Err(HuffmanError::TruncatedInput);
+      }
+
+      let bytes = &self.buffer[self.position..];
+      let bytes = &bytes[..(bytes.len() as u64).min(bytes_left) as usize];
+      if let Some(data_checksum) = data_checksum {
+        data_checksum.update(bytes);
+      }
+
+      writer.write_all(bytes)?;
+      self.position += bytes.len();
+      bytes_left -= bytes.len() as u64;
+    }
+
+    return Ok(());
+  }
+
+  // read a part of the data with `parse`, which gets the unused bytes and
+  // returns the part and the number of bytes it takes up. more bytes are
+  // read for as long as `parse` says they're truncated
+  fn read_part<U>(
+    &mut self,
+    parse: impl Fn(&[u8]) -> Result<(U, usize), HuffmanError>
+  ) -> Result<U, HuffmanError> {
+    loop {
+      match parse(&self.buffer[self.position..]) {
+        Ok((part, byte_count)) => {
+          self.position += byte_count;
+          return Ok(part);
+        },
+        Err(HuffmanError::TruncatedInput) => {
+          if !self.read_more()? {
+            return Err(HuffmanError::TruncatedInput);
+          }
+        },
+        Err(e) => return Err(e),
+      }
+    }
+  }
+
+  // drop the bytes that have been used and read another chunk. returns
+  // false if the reader is at its end
+  fn read_more(&mut self) -> Result<bool, HuffmanError> {
+    self.buffer.drain(..self.position);
+    self.position = 0;
+
+    let length = self.buffer.len();
+    self.buffer.resize(length + CHUNK_SIZE, 0);
+    let n = read_chunk(&mut self.reader, &mut self.buffer[length..])?;
+    self.buffer.truncate(length + n);
+
+    return Ok(n > 0);
+  }
+}
+
+// fill up `block` with what `reader` gives, unless it runs out first.
+// returns the number of bytes read, which is only 0 at the end
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8]) -> Result<usize, HuffmanError> {
+  let mut block_length: usize = 0;
+  while block_length < block.len() {
+    let n = read_chunk(reader, &mut block[block_length..])?;
+    if n == 0 {
+      break;
+    }
+
+    block_length += n;
+  }
+
+  return Ok(block_length);
+}
+
+// read the next chunk of `reader` into `buffer`, trying again when the
+// read gets interrupted. returns the number of bytes read, which is only 0
+// at the end
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, HuffmanError> {
+  loop {
+    match reader.read(buffer) {
+      Ok(n) => return Ok(n),
+      Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+      Err(e) => return Err(HuffmanError::Io(e)),
+    }
+  }
+}
@@ -0,0 +1,183 @@
+use crate::checksum::Checksum;
+use crate::error::HuffmanError;
+use crate::metadata::SymbolKind;
+
+use std::hash::Hash;
+
+/// Anything that can be Huffman coded, like a char, a byte, a token ID or
+/// an opcode.
+///
+/// Every symbol gets written to the metadata dictionary as a `u32`, so
+/// implementing this trait is how a type plugs in its own serialization.
+///
+/// ```
+/// use huffman_coding::{ HuffmanError, Symbol };
+///
+/// #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// enum Opcode { Push, Pop, Add }
+///
+/// impl Symbol for Opcode {
+///   fn to_u32(&self) -> u32 {
+///     return self.clone() as u32;
+///   }
+///
+///   fn from_u32(value: u32) -> Result<Opcode, HuffmanError> {
+///     return match value {
+///       0 => Ok(Opcode::Push),
+///       1 => Ok(Opcode::Pop),
+///       2 => Ok(Opcode::Add),
+///       _ => Err(HuffmanError::InvalidSymbol(value)),
+///     };
+///   }
+/// }
+/// ```
+pub trait Symbol: Eq + Hash + Ord + Clone {
+  /// What the dictionary of compressed data holds when the symbols are of
+  /// this type. Only `char` and `u8` have their own kind.
+  const KIND: SymbolKind = SymbolKind::Custom;
+
+  /// The number of bits a symbol is written with as it is, the first time
+  /// adaptive coding comes across it. `to_u32` must fit in that many bits
+  const RAW_BITS: u8 = 32;
+
+  /// Serialize this symbol for the metadata dictionary
+  fn to_u32(&self) -> u32;
+
+  /// Deserialize a symbol from the metadata dictionary. Fails if `value`
+  /// doesn't stand for a symbol of this type
+  fn from_u32(value: u32) -> Result<Self, HuffmanError>;
+
+  /// Add this symbol to the checksum of the original data. By default that's
+  /// the 4 bytes of `to_u32`, most significant first
+  fn update_checksum(&self, checksum: &mut Checksum) {
+    checksum.update(&self.to_u32().to_be_bytes());
+  }
+
+  /// Add all the `symbols` to the checksum, one after the other. Types that
+  /// can hand over their bytes all at once do it faster
+  fn update_checksum_with_all(symbols: &[Self], checksum: &mut Checksum) {
+    for symbol in symbols {
+      symbol.update_checksum(checksum);
+    }
+  }
+
+  /// Write the `symbols` as they are, for data that coding wouldn't make
+  /// any smaller. By default that's the 4 bytes of every `to_u32`, most
+  /// significant first, the same bytes the checksum is taken of
+  fn write_stored(symbols: &[Self], result: &mut Vec<u8>) {
+    for symbol in symbols {
+      result.extend_from_slice(&symbol.to_u32().to_be_bytes());
+    }
+  }
+
+  /// Read the symbols that `write_stored` wrote to `b` into `result`. Fails
+  /// if `b` doesn't hold whole symbols
+  fn read_stored(b: &[u8], result: &mut Vec<Self>) -> Result<(), HuffmanError> {
+    if !b.len().is_multiple_of(4) {
+      return Err(HuffmanError::InvalidStoredData);
+    }
+
+    for bytes in b.chunks_exact(4) {
+      result.push(Self::from_u32(u32::from_be_bytes(bytes.try_into().unwrap()))?);
+    }
+
+    return Ok(());
+  }
+}
+
+impl Symbol for char {
+  const KIND: SymbolKind = SymbolKind::Chars;
+  // enough for char::MAX
+  const RAW_BITS: u8 = 21;
+
+  fn to_u32(&self) -> u32 {
+    return *self as u32;
+  }
+
+  fn from_u32(value: u32) -> Result<char, HuffmanError> {
+    return match char::from_u32(value) {
+      Some(c) => Ok(c),
+      None => Err(HuffmanError::InvalidChar(value)),
+    };
+  }
+
+  // text is checksummed as its UTF-8 bytes, like decompress returns it
+  fn update_checksum(&self, checksum: &mut Checksum) {
+    checksum.update(self.encode_utf8(&mut [0; 4]).as_bytes());
+  }
+
+  // and stored as them too
+  fn write_stored(symbols: &[char], result: &mut Vec<u8>) {
+    for c in symbols {
+      result.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+  }
+
+  fn read_stored(b: &[u8], result: &mut Vec<char>) -> Result<(), HuffmanError> {
+    return match std::str::from_utf8(b) {
+      Ok(s) => {
+        result.extend(s.chars());
+        Ok(())
+      },
+      Err(_) => Err(HuffmanError::InvalidStoredData),
+    };
+  }
+}
+
+impl Symbol for u8 {
+  const KIND: SymbolKind = SymbolKind::Bytes;
+  const RAW_BITS: u8 = 8;
+
+  fn to_u32(&self) -> u32 {
+    return *self as u32;
+  }
+
+  fn from_u32(value: u32) -> Result<u8, HuffmanError> {
+    return match u8::try_from(value) {
+      Ok(byte) => Ok(byte),
+      Err(_) => Err(HuffmanError::InvalidSymbol(value)),
+    };
+  }
+
+  fn update_checksum(&self, checksum: &mut Checksum) {
+    checksum.update(&[*self]);
+  }
+
+  fn update_checksum_with_all(symbols: &[u8], checksum: &mut Checksum) {
+    checksum.update(symbols);
+  }
+
+  fn write_stored(symbols: &[u8], result: &mut Vec<u8>) {
+    result.extend_from_slice(symbols);
+  }
+
+  fn read_stored(b: &[u8], result: &mut Vec<u8>) -> Result<(), HuffmanError> {
+    result.extend_from_slice(b);
+    return Ok(());
+  }
+}
+
+impl Symbol for u16 {
+  const RAW_BITS: u8 = 16;
+
+  fn to_u32(&self) -> u32 {
+    return *self as u32;
+  }
+
+  fn from_u32(value: u32) -> Result<u16, HuffmanError> {
+    return match u16::try_from(value) {
+      Ok(n) => Ok(n),
+      Err(_) => Err(HuffmanError::InvalidSymbol(value)),
+    };
+  }
+}
+
+impl Symbol for u32 {
+  fn to_u32(&self) -> u32 {
+    return *self;
+  }
+
+  fn from_u32(value: u32) -> Result<u32, HuffmanError> {
+    return Ok(value);
+  }
+}
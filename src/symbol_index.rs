@@ -0,0 +1,53 @@
+use crate::symbol::Symbol;
+
+use std::collections::HashMap;
+
+// Where each symbol is in a Vec, found without searching through it.
+// Symbols that serialize to a value below 256, like bytes and ASCII chars,
+// are looked up directly in an array, any others in a hash map.
+pub struct SymbolIndex<T> {
+  small: [Option<u32>; 256],
+  others: HashMap<T, usize>,
+}
+
+impl<T: Symbol> SymbolIndex<T> {
+  pub fn new() -> SymbolIndex<T> {
+    return SymbolIndex {
+      small: [None; 256],
+      others: HashMap::new(),
+    };
+  }
+
+  // index the `symbols` by where they are in the iterator
+  pub fn from_symbols<'a>(symbols: impl IntoIterator<Item = &'a T>) -> SymbolIndex<T>
+  where
+    T: 'a,
+  {
+    let mut result = Self::new();
+    for (index, symbol) in symbols.into_iter().enumerate() {
+      result.insert(symbol.clone(), index);
+    }
+
+    return result;
+  }
+
+  // get the index of the `symbol`, if it has one
+  pub fn get(&self, symbol: &T) -> Option<usize> {
+    let value = symbol.to_u32();
+    if value < 256 {
+      return self.small[value as usize].map(|index| index as usize);
+    }
+
+    return self.others.get(symbol).copied();
+  }
+
+  // give the `symbol` the `index`
+  pub fn insert(&mut self, symbol: T, index: usize) {
+    let value = symbol.to_u32();
+    if value < 256 && index <= u32::MAX as usize {
+      self.small[value as usize] = Some(index as u32);
+    } else {
+      self.others.insert(symbol, index);
+    }
+  }
+}
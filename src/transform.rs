@@ -0,0 +1,401 @@
+use crate::error::HuffmanError;
+use crate::header::BlockTransform;
+use crate::symbol::Symbol;
+
+// the symbol of the next of a run of move-to-front ranks of 0, with a
+// weight of 1, 2, 4 and so on
+const RUN_A: u16 = 0;
+
+// the symbol of the next of a run of ranks of 0, with twice the weight of
+// RUN_A
+const RUN_B: u16 = 1;
+
+// A symbol of bytes that went through the Burrows-Wheeler transform,
+// move-to-front and zero-run coding: RUN_A and RUN_B for the digits of the
+// length of a run of ranks of 0, or one more than any other rank
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TransformedSymbol(u16);
+
+impl Symbol for TransformedSymbol {
+  // enough for one more than the highest rank
+  const RAW_BITS: u8 = 9;
+
+  fn to_u32(&self) -> u32 {
+    return self.0 as u32;
+  }
+
+  fn from_u32(value: u32) -> Result<TransformedSymbol, HuffmanError> {
+    if value > u8::MAX as u32 + 1 {
+      return Err(HuffmanError::InvalidSymbol(value));
+    }
+
+    return Ok(TransformedSymbol(value as u16));
+  }
+}
+
+// run the bytes of a `block` that isn't empty through the Burrows-Wheeler
+// transform, then move-to-front and zero-run coding. returns the symbols
+// and the primary index of the block
+pub fn transform_block(block: &[u8]) -> (Vec<TransformedSymbol>, u64) {
+  let mut last_column: Vec<u8> = Vec::with_capacity(block.len());
+  let primary_index = burrows_wheeler(block, &mut last_column);
+
+  let ranks = move_to_front(&last_column);
+  return (code_zero_runs(&ranks), primary_index);
+}
+
+// turn the `symbols` of transform_block back into the bytes of the block
+// of the `transform`, whose length and primary index BlockHeader checked.
+// fails if they don't make up the block
+pub fn undo_transform_block(
+  symbols: &[TransformedSymbol],
+  transform: &BlockTransform
+) -> Result<Vec<u8>, HuffmanError> {
+  let ranks = undo_zero_runs(symbols, transform.length as usize)?;
+  let last_column = undo_move_to_front(&ranks);
+
+  let mut result: Vec<u8> = Vec::with_capacity(last_column.len());
+  undo_burrows_wheeler(&last_column, transform.primary_index, &mut result)?;
+  return Ok(result);
+}
+
+// add the last column of the sorted rotations of `block` to `result`,
+// where the end of the block sorts before any byte. the end itself is left
+// out, its row is the returned primary index
+fn burrows_wheeler(block: &[u8], result: &mut Vec<u8>) -> u64 {
+  // the rotation that starts at the end comes first, and the byte before
+  // the end is the last of it
+  result.push(block[block.len() - 1]);
+
+  let mut primary_index: u64 = 0;
+  for (row, start) in suffix_array(block).into_iter().enumerate() {
+    match start {
+      0 => primary_index = row as u64 + 1,
+      _ => result.push(block[start as usize - 1]),
+    }
+  }
+
+  return primary_index;
+}
+
+// add the bytes of the block whose `last_column` burrows_wheeler returned,
+// with the end at the row of `primary_index`, to `result`. fails if the
+// primary index is outside the rows or the block ends too soon
+fn undo_burrows_wheeler(
+  last_column: &[u8],
+  primary_index: u64,
+  result: &mut Vec<u8>
+) -> Result<(), HuffmanError> {
+  let length = last_column.len();
+  if primary_index == 0 || primary_index > length as u64 {
+    return Err(HuffmanError::BadHeader("primary index outside its block"));
+  }
+
+  let primary_index = primary_index as usize;
+  // the byte of every row, with the end at the primary index
+  let byte_of_row = |row: usize| last_column[row - (row > primary_index) as usize];
+
+  // the first row that starts with every byte, after the one that starts
+  // with the end
+  let mut starts: [usize; 256] = [0; 256];
+  for byte in last_column {
+    starts[*byte as usize] += 1;
+  }
+
+  let mut total: usize = 1;
+  for start in &mut starts {
+    let count = *start;
+    *start = total;
+    total += count;
+  }
+
+  // the row of every rotation that starts with the last byte of the row,
+  // one byte further back in the block. a block is no longer than
+  // BWT_BLOCK_SIZE, so every row fits in a u32
+  let mut previous_rows: Vec<u32> = vec![0; length + 1];
+  for (row, previous_row) in previous_rows.iter_mut().enumerate() {
+    if row != primary_index {
+      let byte = byte_of_row(row);
+      *previous_row = starts[byte as usize] as u32;
+      starts[byte as usize] += 1;
+    }
+  }
+
+  // walk the block from the back, starting at the rotation that starts with
+  // the end
+  let start = result.len();
+  result.resize(start + length, 0);
+  let mut row: usize = 0;
+  for i in (0..length).rev() {
+    // only damaged data gets to the end before the start of the block
+    if row == primary_index {
+      return Err(HuffmanError::InvalidTransform);
+    }
+
+    result[start + i] = byte_of_row(row);
+    row = previous_rows[row] as usize;
+  }
+
+  return Ok(());
+}
+
+// where every suffix of `b` starts, in the order of the suffixes, where a
+// suffix that's the start of another sorts before it
+fn suffix_array(b: &[u8]) -> Vec<u32> {
+  // the bytes one higher, so the end can be a 0 that sorts before them all
+  let mut s: Vec<u32> = b.iter().map(|byte| *byte as u32 + 1).collect();
+  s.push(0);
+
+  // the suffix of just the end comes first
+  let mut result = induced_suffix_array(&s, u8::MAX as usize + 2);
+  result.remove(0);
+  return result;
+}
+
+// the suffix array of `s`, whose values are below `alphabet_size` and whose
+// last value is a 0 that's nowhere else, sorted by induced sorting (SA-IS)
+// in time linear to its length, however repetitive it is.
+//
+// a suffix is S-type when it sorts before the suffix after it and L-type
+// otherwise, and an LMS suffix is an S-type suffix after an L-type one. once
+// the LMS suffixes are sorted, scanning the suffix array forwards puts every
+// L-type suffix in place from the one after it, and scanning it backwards
+// does the same for S-type suffixes. the LMS suffixes themselves are sorted
+// by the suffix array of the shorter string of their substrings up to the
+// next LMS suffix, named in the order of those substrings
+fn induced_suffix_array(s: &[u32], alphabet_size: usize) -> Vec<u32> {
+  let length = s.len();
+  if length == 1 {
+    return vec![0];
+  }
+
+  let mut is_s_type: Vec<bool> = vec![false; length];
+  is_s_type[length - 1] = true;
+  for i in (0..length - 1).rev() {
+    is_s_type[i] = s[i] < s[i + 1] || (s[i] == s[i + 1] && is_s_type[i + 1]);
+  }
+
+  let is_lms = |i: usize| i > 0 && is_s_type[i] && !is_s_type[i - 1];
+  let lms_positions: Vec<u32> = (0..length).filter(|i| is_lms(*i)).map(|i| i as u32).collect();
+
+  let mut counts: Vec<u32> = vec![0; alphabet_size];
+  for value in s {
+    counts[*value as usize] += 1;
+  }
+
+  // sort the LMS substrings by putting the LMS suffixes at the ends of their
+  // buckets in any order
+  let mut result: Vec<u32> = vec![EMPTY; length];
+  let mut ends = bucket_ends(&counts);
+  for position in &lms_positions {
+    let value = s[*position as usize] as usize;
+    ends[value] -= 1;
+    result[ends[value] as usize] = *position;
+  }
+
+  induce(s, &is_s_type, &counts, &mut result);
+
+  // name the LMS substrings in their order, the same ones the same
+  let mut names: Vec<u32> = vec![EMPTY; length];
+  let mut name: u32 = 0;
+  let mut previous: Option<usize> = None;
+  for position in result.iter().map(|position| *position as usize).filter(|position| is_lms(*position)) {
+    if let Some(previous) = previous {
+      if !lms_substrings_equal(s, &is_s_type, previous, position) {
+        name += 1;
+      }
+    }
+
+    names[position] = name;
+    previous = Some(position);
+  }
+
+  // the LMS substrings in the order of the string, which ends with the
+  // substring of just the end, the only one named 0
+  let reduced: Vec<u32> = lms_positions.iter().map(|position| names[*position as usize]).collect();
+  let reduced_suffixes = if name as usize + 1 == reduced.len() {
+    // every name is different, so they sort the suffixes on their own
+    let mut reduced_suffixes: Vec<u32> = vec![0; reduced.len()];
+    for (i, name) in reduced.iter().enumerate() {
+      reduced_suffixes[*name as usize] = i as u32;
+    }
+
+    reduced_suffixes
+  } else {
+    induced_suffix_array(&reduced, name as usize + 1)
+  };
+
+  // sort all the suffixes by putting the sorted LMS suffixes at the ends of
+  // their buckets, the last of them first
+  result.fill(EMPTY);
+  let mut ends = bucket_ends(&counts);
+  for i in reduced_suffixes.iter().rev() {
+    let position = lms_positions[*i as usize];
+    let value = s[position as usize] as usize;
+    ends[value] -= 1;
+    result[ends[value] as usize] = position;
+  }
+
+  induce(s, &is_s_type, &counts, &mut result);
+  return result;
+}
+
+// the place of a suffix array that isn't filled yet
+const EMPTY: u32 = u32::MAX;
+
+// where the bucket of every value of `counts` ends in a suffix array, the
+// bucket of the suffixes that start with it
+fn bucket_ends(counts: &[u32]) -> Vec<u32> {
+  let mut total: u32 = 0;
+  return counts.iter().map(|count| {
+    total += count;
+    return total;
+  }).collect();
+}
+
+// put the L-type suffixes of `s` in place in the `suffixes` from the sorted
+// LMS suffixes already at the ends of their buckets, then the S-type ones
+// from the L-type ones
+fn induce(s: &[u32], is_s_type: &[bool], counts: &[u32], suffixes: &mut [u32]) {
+  let ends = bucket_ends(counts);
+  let mut starts: Vec<u32> = ends.iter().zip(counts).map(|(end, count)| end - count).collect();
+  for i in 0..suffixes.len() {
+    let position = suffixes[i];
+    if position != EMPTY && position > 0 && !is_s_type[position as usize - 1] {
+      let value = s[position as usize - 1] as usize;
+      suffixes[starts[value] as usize] = position - 1;
+      starts[value] += 1;
+    }
+  }
+
+  let mut ends = ends;
+  for i in (0..suffixes.len()).rev() {
+    let position = suffixes[i];
+    if position != EMPTY && position > 0 && is_s_type[position as usize - 1] {
+      let value = s[position as usize - 1] as usize;
+      ends[value] -= 1;
+      suffixes[ends[value] as usize] = position - 1;
+    }
+  }
+}
+
+// whether the LMS substrings of `s` at `a` and `b`, up to and including the
+// next LMS suffix, are the same values of the same types
+fn lms_substrings_equal(s: &[u32], is_s_type: &[bool], a: usize, b: usize) -> bool {
+  let is_lms = |i: usize| i > 0 && is_s_type[i] && !is_s_type[i - 1];
+  let mut i: usize = 0;
+  loop {
+    if s[a + i] != s[b + i] || is_s_type[a + i] != is_s_type[b + i] {
+      return false;
+    }
+
+    // the end is an LMS suffix, so neither of them goes past it
+    if i > 0 && (is_lms(a + i) || is_lms(b + i)) {
+      return is_lms(a + i) && is_lms(b + i);
+    }
+
+    i += 1;
+  }
+}
+
+// the rank of every one of the `bytes` in a list of all bytes, where every
+// byte moves to the front once it's used
+fn move_to_front(bytes: &[u8]) -> Vec<u8> {
+  let mut list: [u8; 256] = std::array::from_fn(|i| i as u8);
+  let mut result: Vec<u8> = Vec::with_capacity(bytes.len());
+  for byte in bytes {
+    let rank = list.iter().position(|b| b == byte).unwrap();
+    list.copy_within(0..rank, 1);
+    list[0] = *byte;
+    result.push(rank as u8);
+  }
+
+  return result;
+}
+
+// the bytes of the `ranks` of move_to_front
+fn undo_move_to_front(ranks: &[u8]) -> Vec<u8> {
+  let mut list: [u8; 256] = std::array::from_fn(|i| i as u8);
+  let mut result: Vec<u8> = Vec::with_capacity(ranks.len());
+  for rank in ranks {
+    let byte = list[*rank as usize];
+    list.copy_within(0..*rank as usize, 1);
+    list[0] = byte;
+    result.push(byte);
+  }
+
+  return result;
+}
+
+// the symbols of the `ranks`, where every run of 0s is its length in the
+// digits RUN_A and RUN_B, lowest first, and every other rank is one more
+fn code_zero_runs(ranks: &[u8]) -> Vec<TransformedSymbol> {
+  let mut result: Vec<TransformedSymbol> = Vec::new();
+  let mut run: u64 = 0;
+  for rank in ranks {
+    if *rank == 0 {
+      run += 1;
+      continue;
+    }
+
+    code_run(run, &mut result);
+    run = 0;
+    result.push(TransformedSymbol(*rank as u16 + 1));
+  }
+
+  code_run(run, &mut result);
+  return result;
+}
+
+// add the digits of a run of `run` 0s to `result`. a RUN_A is worth 1 and a
+// RUN_B 2, times 2 for every digit before it, so every length has its own
+// digits without a digit for 0
+fn code_run(run: u64, result: &mut Vec<TransformedSymbol>) {
+  let mut run = run;
+  while run > 0 {
+    if run & 1 == 1 {
+      result.push(TransformedSymbol(RUN_A));
+      run = (run - 1) / 2;
+    } else {
+      result.push(TransformedSymbol(RUN_B));
+      run = (run - 2) / 2;
+    }
+  }
+}
+
+// the ranks of the `symbols` of code_zero_runs, which make up a block of
+// `length` bytes. fails unless there are exactly that many of them, before
+// a run of ranks of 0 gets any longer than what's left of the block
+fn undo_zero_runs(symbols: &[TransformedSymbol], length: usize) -> Result<Vec<u8>, HuffmanError> {
+  let mut result: Vec<u8> = Vec::with_capacity(length);
+  let mut run: usize = 0;
+  let mut weight: usize = 1;
+  for symbol in symbols {
+    if symbol.0 == RUN_A || symbol.0 == RUN_B {
+      let digit = if symbol.0 == RUN_A { weight } else { weight.saturating_mul(2) };
+      run = run.saturating_add(digit);
+      weight = weight.saturating_mul(2);
+      if run > length - result.len() {
+        return Err(HuffmanError::InvalidTransform);
+      }
+
+      continue;
+    }
+
+    if run + 1 > length - result.len() {
+      return Err(HuffmanError::InvalidTransform);
+    }
+
+    result.resize(result.len() + run, 0);
+    result.push((symbol.0 - 1) as u8);
+    run = 0;
+    weight = 1;
+  }
+
+  result.resize(result.len() + run, 0);
+  if result.len() != length {
+    return Err(HuffmanError::InvalidTransform);
+  }
+
+  return Ok(result);
+}
@@ -0,0 +1,43 @@
+use crate::error::HuffmanError;
+
+// Variable length integers, 7 bits per byte, lowest bits first. The highest
+// bit of every byte says if another byte follows, so small numbers only
+// take up a single byte.
+pub struct Varint;
+
+impl Varint {
+  // write the varint of `n` to the end of `out`
+  pub fn write(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0b1000_0000 {
+      out.push((n as u8 & 0b0111_1111) | 0b1000_0000);
+      n >>= 7;
+    }
+
+    out.push(n as u8);
+  }
+
+  // read the varint at `*position` in `b` and move `position` past it
+  pub fn read(b: &[u8], position: &mut usize) -> Result<u64, HuffmanError> {
+    let mut n: u64 = 0;
+    let mut shift: u32 = 0;
+    loop {
+      let byte = match b.get(*position) {
+        Some(byte) => *byte,
+        None => return Err(HuffmanError::TruncatedInput),
+      };
+      *position += 1;
+
+      let bits = (byte & 0b0111_1111) as u64;
+      if shift > 63 || (shift == 63 && bits > 1) {
+        return Err(HuffmanError::BadHeader("varint doesn't fit in a u64"));
+      }
+
+      n |= bits << shift;
+      if byte & 0b1000_0000 == 0 {
+        return Ok(n);
+      }
+
+      shift += 7;
+    }
+  }
+}
@@ -0,0 +1,276 @@
+use crate::bits::{ BitBuffer, BitWriter, CodeTable, SymbolReader };
+use crate::char_code::{ CharCodePair, MAX_CODE_BITS };
+use crate::checksum::ChecksumKind;
+use crate::error::HuffmanError;
+use crate::header::{ Header, WordDictionary };
+use crate::huffman_coding::HuffmanCoding;
+use crate::huffman_tree::HuffmanTree;
+use crate::metadata::{ CodeLengthEntry, SymbolKind };
+use crate::symbol::Symbol;
+use crate::symbol_index::SymbolIndex;
+
+use std::collections::HashMap;
+
+// how often a token has to come up to be put in the dictionary. one that
+// comes up just once is spelled out in about the same bits it would take up
+// in the dictionary
+const MIN_WORD_COUNT: u64 = 2;
+
+// the symbol of a token that's spelled out
+const ESCAPE: u32 = 0;
+
+// the symbol that ends a token that's spelled out
+const END_OF_TOKEN: u32 = 0;
+
+// What kind of token a char belongs to
+#[derive(Clone, Copy, PartialEq)]
+enum CharClass {
+  Word,
+  Space,
+  // a token on its own, like punctuation
+  Other,
+}
+
+impl CharClass {
+  fn of(c: char) -> CharClass {
+    if c.is_alphanumeric() {
+      return CharClass::Word;
+    } else if c.is_whitespace() {
+      return CharClass::Space;
+    } else {
+      return CharClass::Other;
+    }
+  }
+}
+
+// split `s` into the tokens it's coded as: runs of letters and digits, runs
+// of whitespace and every other char on its own
+pub fn tokenize(s: &str) -> Vec<&str> {
+  let mut tokens: Vec<&str> = Vec::new();
+  let mut start: usize = 0;
+  let mut previous_class = CharClass::Other;
+  for (i, c) in s.char_indices() {
+    let class = CharClass::of(c);
+    if i > start && (class == CharClass::Other || class != previous_class) {
+      tokens.push(&s[start..i]);
+      start = i;
+    }
+
+    previous_class = class;
+  }
+
+  if start < s.len() {
+    tokens.push(&s[start..]);
+  }
+
+  return tokens;
+}
+
+// The codes of text coded a token at a time.
+//
+// Every token that comes up at least MIN_WORD_COUNT times is a word of the
+// dictionary with a code of its own. Any other token gets the code of the
+// ESCAPE symbol, followed by the codes of its chars from a second tree and
+// the code of END_OF_TOKEN.
+pub struct WordCodes<'a> {
+  // the words of the dictionary, from lowest to highest
+  words: Vec<&'a str>,
+  // the symbol of every word of the dictionary
+  symbols: HashMap<&'a str, u32>,
+  word_codes: Vec<CharCodePair<u32>>,
+  word_index: SymbolIndex<u32>,
+  // the codes of the chars of spelled out tokens, every char as its value
+  // plus one
+  spelling_codes: Vec<CharCodePair<u32>>,
+  spelling_index: SymbolIndex<u32>,
+}
+
+impl<'a> WordCodes<'a> {
+  // pick the words of the dictionary from the `tokens` and grow the trees of
+  // the words and of the chars of the other tokens
+  pub fn from_tokens(tokens: &[&'a str]) -> Result<WordCodes<'a>, HuffmanError> {
+    let mut counts: HashMap<&'a str, u64> = HashMap::new();
+    for token in tokens {
+      *counts.entry(token).or_insert(0) += 1;
+    }
+
+    let mut words: Vec<&'a str> = counts.into_iter()
+      .filter(|(_, count)| *count >= MIN_WORD_COUNT)
+      .map(|(word, _)| word)
+      .collect();
+    words.sort();
+
+    let symbols: HashMap<&'a str, u32> = words.iter()
+      .enumerate()
+      .map(|(i, word)| (*word, i as u32 + 1))
+      .collect();
+
+    let mut token_symbols: Vec<u32> = Vec::new();
+    let mut spelling_symbols: Vec<u32> = Vec::new();
+    for token in tokens {
+      match symbols.get(token) {
+        Some(symbol) => token_symbols.push(*symbol),
+        None => {
+          token_symbols.push(ESCAPE);
+          spelling_symbols.extend(token.chars().map(|c| c as u32 + 1));
+          spelling_symbols.push(END_OF_TOKEN);
+        },
+      }
+    }
+
+    let word_codes = codes_of(token_symbols)?;
+    let spelling_codes = codes_of(spelling_symbols)?;
+
+    return Ok(WordCodes {
+      words,
+      symbols,
+      word_index: SymbolIndex::from_symbols(word_codes.iter().map(|pair| &pair.value)),
+      word_codes,
+      spelling_index: SymbolIndex::from_symbols(spelling_codes.iter().map(|pair| &pair.value)),
+      spelling_codes,
+    });
+  }
+
+  // write the code of the `token`, or spell it out if it isn't a word of the
+  // dictionary. fails if a code is missing
+  pub fn encode(&self, token: &str, writer: &mut BitWriter) -> Result<(), HuffmanError> {
+    let symbol = self.symbols.get(token).copied().unwrap_or(ESCAPE);
+    let pair = HuffmanCoding::get_char_code_pair(&symbol, &self.word_codes, &self.word_index)?;
+    writer.write(pair.code, pair.bits)?;
+
+    if symbol == ESCAPE {
+      for value in token.chars().map(|c| c as u32 + 1).chain([END_OF_TOKEN]) {
+        let pair = HuffmanCoding::get_char_code_pair(&value, &self.spelling_codes, &self.spelling_index)?;
+        writer.write(pair.code, pair.bits)?;
+      }
+    }
+
+    return Ok(());
+  }
+
+  // the header of text coded with these codes in `bits` bits, with a
+  // checksum of the given algorithm or none at all
+  pub fn header(&self, checksum: Option<ChecksumKind>, bits: u64) -> Header {
+    let code_lengths = self.word_codes.iter().map(CodeLengthEntry::new).collect();
+    return Header {
+      words: Some(WordDictionary {
+        words: self.words.iter().map(|word| word.to_string()).collect(),
+        spelling_code_lengths: self.spelling_codes.iter().map(CodeLengthEntry::new).collect(),
+      }),
+      ..Header::new(SymbolKind::Chars, checksum, code_lengths, bits)
+    };
+  }
+}
+
+// The dictionary and the tables of a header with FLAG_WORDS
+struct WordTables<T> {
+  // the symbols of every word, by its symbol minus one
+  words: Vec<Vec<T>>,
+  word_table: CodeTable<u32>,
+  spelling_table: CodeTable<u32>,
+}
+
+impl<T: Symbol> WordTables<T> {
+  // read the next code from the `buffer`, a char of a spelled out token if
+  // `spelling` is set, and add the symbols it stands for to `result`
+  fn decode(
+    &self,
+    buffer: &mut BitBuffer,
+    spelling: &mut bool,
+    result: &mut Vec<T>
+  ) -> Result<(), HuffmanError> {
+    if *spelling {
+      match self.spelling_table.decode(buffer)? {
+        END_OF_TOKEN => *spelling = false,
+        value => result.push(T::from_u32(value - 1)?),
+      }
+
+      return Ok(());
+    }
+
+    match self.word_table.decode(buffer)? {
+      ESCAPE => *spelling = true,
+      symbol => match self.words.get(symbol as usize - 1) {
+        Some(word) => result.extend_from_slice(word),
+        None => return Err(HuffmanError::BadHeader("word isn't in the dictionary")),
+      },
+    }
+
+    return Ok(());
+  }
+}
+
+// Reads text coded a token at a time back into its chars. The bits can come
+// in any number of pieces.
+pub struct WordReader<T> {
+  tables: WordTables<T>,
+  // whether the next code is a char of a spelled out token
+  spelling: bool,
+  // the length of the longest code of either table
+  max_bits: u8,
+  buffer: BitBuffer,
+}
+
+impl<T: Symbol> WordReader<T> {
+  // build the tables of the word `code_lengths` and the `dictionary`. fails
+  // if they don't make up valid codes
+  pub fn new(
+    code_lengths: &[CodeLengthEntry],
+    dictionary: &WordDictionary
+  ) -> Result<WordReader<T>, HuffmanError> {
+    let mut words: Vec<Vec<T>> = Vec::new();
+    for word in &dictionary.words {
+      words.push(word.chars().map(|c| T::from_u32(c as u32)).collect::<Result<Vec<T>, HuffmanError>>()?);
+    }
+
+    let word_codes = HuffmanCoding::code_length_entries_to_char_code_pairs(code_lengths)?;
+    let spelling_codes = HuffmanCoding::code_length_entries_to_char_code_pairs(
+      &dictionary.spelling_code_lengths
+    )?;
+
+    let tables = WordTables {
+      words,
+      word_table: CodeTable::new(word_codes)?,
+      spelling_table: CodeTable::new(spelling_codes)?,
+    };
+
+    return Ok(WordReader {
+      max_bits: tables.word_table.max_bits().max(tables.spelling_table.max_bits()),
+      tables,
+      spelling: false,
+      buffer: BitBuffer::new(),
+    });
+  }
+}
+
+impl<T: Symbol> SymbolReader<T> for WordReader<T> {
+  fn read_bytes(&mut self, bytes: &[u8], bits: u64, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    // with at least the longest code of either table, there's enough for
+    // the next code
+    let tables = &self.tables;
+    let spelling = &mut self.spelling;
+    return self.buffer.read_bytes(bytes, bits, self.max_bits, |buffer| {
+      return tables.decode(buffer, spelling, result);
+    });
+  }
+
+  fn finish(&mut self, result: &mut Vec<T>) -> Result<(), HuffmanError> {
+    while !self.buffer.is_empty() {
+      self.tables.decode(&mut self.buffer, &mut self.spelling, result)?;
+    }
+
+    // the bits ended in the middle of a spelled out token
+    if self.spelling {
+      return Err(HuffmanError::InvalidCode);
+    }
+
+    return Ok(());
+  }
+}
+
+// the canonical codes of a tree grown from the `symbols`
+fn codes_of(symbols: Vec<u32>) -> Result<Vec<CharCodePair<u32>>, HuffmanError> {
+  let frequencies = HuffmanTree::count_char_frequencies(symbols);
+  let tree = HuffmanTree::from_frequencies_with_max_code_length(frequencies, MAX_CODE_BITS)?;
+  return HuffmanCoding::get_canonical_codes(&tree);
+}
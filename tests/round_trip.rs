@@ -0,0 +1,1654 @@
+use huffman_coding::{
+  BlockHeader,
+  BlockOptions,
+  BlockTransform,
+  CanonicalCodes,
+  CharCodePair,
+  CharFrequencyPair,
+  Checksum,
+  ChecksumKind,
+  CodeBuilder,
+  CodeLengthEntry,
+  CodeReport,
+  Codec,
+  Crc32,
+  Header,
+  HuffmanCoding,
+  HuffmanDecoder,
+  HuffmanEncoder,
+  HuffmanError,
+  HuffmanTree,
+  HuffmanTreeNode,
+  MetadataKeyValuePair,
+  NaryHuffmanTree,
+  SymbolKind,
+  XxHash32,
+  BWT_BLOCK_SIZE,
+  END_OF_BLOCKS,
+  EXTENDED_FLAG_ARITY,
+  EXTENDED_FLAG_TRANSFORM,
+  FLAG_ADAPTIVE,
+  FLAG_BLOCKS,
+  FLAG_CODEC,
+  FLAG_CONTEXTS,
+  FLAG_EXTENDED,
+  FLAG_STORED,
+  FLAG_WORDS,
+};
+
+use std::collections::HashSet;
+use std::io::Cursor;
+
+// the length of the longest code in the tree below `node`
+fn max_depth<T>(node: &HuffmanTreeNode<T>) -> u32 {
+  let left = node.left.as_ref().map_or(0, |lnode| 1 + max_depth(lnode));
+  let right = node.right.as_ref().map_or(0, |rnode| 1 + max_depth(rnode));
+  return left.max(right);
+}
+
+// the symbol at the end of the `path` of 0 and 1 bits below `node`, if any
+fn symbol_at<T: Clone>(node: &HuffmanTreeNode<T>, path: &str) -> Option<T> {
+  let mut current = node;
+  for bit in path.chars() {
+    let next = if bit == '1' { &current.right } else { &current.left };
+    current = next.as_ref()?;
+  }
+
+  return current.value.clone();
+}
+
+// text where the `i`th char of `alphabet` appears `counts[i]` times,
+// interleaved so the codes don't just repeat
+fn text_with_counts(alphabet: &[char], counts: &[u64]) -> String {
+  let mut remaining = counts.to_vec();
+  let mut text = String::new();
+  while remaining.iter().any(|count| *count > 0) {
+    for (i, count) in remaining.iter_mut().enumerate() {
+      if *count > 0 {
+        text.push(alphabet[i]);
+        *count -= 1;
+      }
+    }
+  }
+
+  return text;
+}
+
+#[test]
+fn round_trip_short_text() {
+  let text = "This is a short test. Hello, Huffman coding!";
+  let tree = HuffmanTree::new(text);
+  let compressed = HuffmanCoding::compress_str(text, &tree).unwrap();
+
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+}
+
+#[test]
+fn headers_hold_every_code_length() {
+  // long enough that coding it pays off
+  let text = include_str!("../data/short_test.txt").repeat(100);
+  let compressed = HuffmanCoding::compress_str(&text, &HuffmanTree::new(&text)).unwrap();
+  let (header, header_length) = Header::from_bytes(&compressed).unwrap();
+  assert_eq!(header.kind, SymbolKind::Chars);
+  assert_eq!(header.code_lengths.len(), text.chars().collect::<HashSet<char>>().len());
+  // followed by the bits and the CRC-32 of the text
+  assert_eq!(compressed.len() as u64, header_length as u64 + header.bits.div_ceil(8) + 4);
+
+  // about 2 bytes for every char of the alphabet, not 5
+  assert!(header_length < header.code_lengths.len() * 3);
+}
+
+#[test]
+fn legacy_data_still_decompresses() {
+  // compressed before format versions, with the full code of every char in
+  // a dictionary entry
+  let compressed = include_bytes!("../data/short_test.huff");
+  let text = include_bytes!("../data/short_test.txt");
+  assert_eq!(HuffmanCoding::decompress(compressed).unwrap(), text);
+  let chars: Vec<char> = std::str::from_utf8(text).unwrap().chars().collect();
+  assert_eq!(HuffmanCoding::decode::<char>(compressed).unwrap(), chars);
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, text);
+}
+
+// the code length of every symbol below `node`, which is `depth` deep
+fn code_lengths_below<T: Clone>(node: &HuffmanTreeNode<T>, depth: u8, result: &mut Vec<(T, u8)>) {
+  if let Some(value) = &node.value {
+    result.push((value.clone(), depth));
+  }
+
+  for child in [&node.left, &node.right].into_iter().flatten() {
+    code_lengths_below(child, depth + 1, result);
+  }
+}
+
+// the `text` in the layout from before format versions with canonical
+// codes: the start entry with the number of code lengths, a CodeLengthEntry
+// for every char, the end entry with the number of bits, then the bits
+fn legacy_canonical_data(text: &str) -> Vec<u8> {
+  let mut code_lengths: Vec<(char, u8)> = Vec::new();
+  code_lengths_below(&HuffmanTree::new(text).trunk, 0, &mut code_lengths);
+  let char_codes = CanonicalCodes::from_code_lengths(code_lengths).unwrap();
+
+  let mut bits: Vec<bool> = Vec::new();
+  for c in text.chars() {
+    let pair = char_codes.iter().find(|pair| pair.value == c).unwrap();
+    bits.extend((0..pair.bits).rev().map(|i| pair.code >> i & 1 == 1));
+  }
+
+  let start = MetadataKeyValuePair::start_canonical_metadata(SymbolKind::Chars, char_codes.len() as u32);
+  let mut result = start.as_bytes().to_vec();
+  for pair in &char_codes {
+    result.extend_from_slice(&CodeLengthEntry::new(pair).as_bytes());
+  }
+
+  result.extend_from_slice(&MetadataKeyValuePair::end_metadata(bits.len() as u64).as_bytes());
+  for chunk in bits.chunks(8) {
+    result.push(chunk.iter().enumerate().fold(0, |byte, (i, bit)| byte | (*bit as u8) << (7 - i)));
+  }
+
+  return result;
+}
+
+#[test]
+fn legacy_canonical_data_still_decompresses() {
+  let text = "Grüße aus der Zeit vor den Formatversionen";
+  let compressed = legacy_canonical_data(text);
+  assert!(MetadataKeyValuePair::from_bytes(compressed[..10].try_into().unwrap()).is_canonical());
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, text.as_bytes());
+
+  // the entries end too soon
+  let result = HuffmanCoding::decompress(&compressed[..30]);
+  assert!(matches!(result, Err(HuffmanError::TruncatedInput)));
+}
+
+// compressed bytes with a header that holds code lengths, and how long the
+// header is
+fn compressed_with_header() -> (Vec<u8>, usize) {
+  let bytes = include_bytes!("../data/short_test.txt").repeat(100);
+  let compressed = HuffmanCoding::compress(&bytes, &HuffmanTree::from_bytes(&bytes)).unwrap();
+  let (header, header_length) = Header::from_bytes(&compressed).unwrap();
+  assert!(!header.code_lengths.is_empty());
+  return (compressed, header_length);
+}
+
+#[test]
+fn unknown_formats_are_refused() {
+  let (compressed, _) = compressed_with_header();
+  let mut damaged = compressed.clone();
+  damaged[0] = b'X';
+
+  for b in [&damaged[..], b"plain text that was never compressed"] {
+    assert!(matches!(HuffmanCoding::decompress(b), Err(HuffmanError::UnknownFormat)));
+    let result = HuffmanDecoder::new(b).decompress(&mut Vec::new());
+    assert!(matches!(result, Err(HuffmanError::UnknownFormat)));
+  }
+}
+
+#[test]
+fn other_format_versions_are_refused() {
+  let (compressed, _) = compressed_with_header();
+  let mut damaged = compressed.clone();
+  damaged[4] = 3;
+
+  assert!(matches!(HuffmanCoding::decompress(&damaged), Err(HuffmanError::UnsupportedVersion(3))));
+  let result = HuffmanDecoder::new(&damaged[..]).decompress(&mut Vec::new());
+  assert!(matches!(result, Err(HuffmanError::UnsupportedVersion(3))));
+}
+
+#[test]
+fn damaged_headers_fail_their_checksum() {
+  let (compressed, header_length) = compressed_with_header();
+
+  // the CRC-32 itself, and the last byte of the number of bits before it,
+  // which stays a valid varint
+  for (header_byte, bit) in [(header_length - 1, 0b0000_0001), (header_length - 5, 0b0000_0001)] {
+    let mut damaged = compressed.clone();
+    damaged[header_byte] ^= bit;
+
+    let result = HuffmanCoding::decompress(&damaged);
+    assert!(matches!(result, Err(HuffmanError::HeaderChecksumMismatch)));
+    let result = HuffmanDecoder::new(&damaged[..]).decompress(&mut Vec::new());
+    assert!(matches!(result, Err(HuffmanError::HeaderChecksumMismatch)));
+  }
+
+  // the header of a block has a CRC-32 of its own
+  let bytes = include_bytes!("../data/short_test.txt");
+  let mut damaged = HuffmanCoding::compress_blocks(bytes, &BlockOptions::default()).unwrap();
+  let (_, header_length) = Header::from_bytes(&damaged).unwrap();
+  let (_, block_header_length) = BlockHeader::from_bytes(&damaged[header_length..]).unwrap();
+  damaged[header_length + block_header_length - 1] ^= 0b1000_0000;
+  let result = HuffmanCoding::decompress(&damaged);
+  assert!(matches!(result, Err(HuffmanError::HeaderChecksumMismatch)));
+}
+
+#[test]
+fn unsupported_features_are_refused() {
+  // an extended feature flags byte with a feature of a later version, which
+  // is refused before the CRC-32 of the header is even checked
+  let (compressed, _) = compressed_with_header();
+  let mut damaged = compressed.clone();
+  damaged[5] |= FLAG_EXTENDED;
+  damaged.insert(7, 0b1000_0000);
+
+  let result = HuffmanCoding::decompress(&damaged);
+  assert!(matches!(result, Err(HuffmanError::UnsupportedFeatures(0b1000_0000))));
+  let result = HuffmanDecoder::new(&damaged[..]).decompress(&mut Vec::new());
+  assert!(matches!(result, Err(HuffmanError::UnsupportedFeatures(0b1000_0000))));
+}
+
+#[test]
+fn round_trip_fibonacci_frequencies() {
+  // fibonacci frequencies grow the deepest possible tree, one level per char
+  let alphabet: Vec<char> = ('a'..='y').collect();
+  let mut counts: Vec<u64> = vec![1, 1];
+  while counts.len() < alphabet.len() {
+    counts.push(counts[counts.len() - 1] + counts[counts.len() - 2]);
+  }
+
+  let text = text_with_counts(&alphabet, &counts);
+  let tree = HuffmanTree::new(&text);
+  assert_eq!(max_depth(&tree.trunk), 24);
+
+  let compressed = HuffmanCoding::compress_str(&text, &tree).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+}
+
+#[test]
+fn long_codes_decode_through_sub_tables() {
+  // codes of 1 to 14 bits, most of them longer than the first lookup table
+  let bytes: Vec<u8> = (0..15).flat_map(|value: u8| vec![value; 1 << 13_u8.saturating_sub(value)]).collect();
+  let compressed = HuffmanCoding::compress(&bytes, &HuffmanTree::from_bytes(&bytes)).unwrap();
+  let (header, _) = Header::from_bytes(&compressed).unwrap();
+  assert_eq!(header.code_lengths.iter().map(|entry| entry.bits).max(), Some(14));
+
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+}
+
+// the bytes of a header with the given code lengths of bytes, followed by
+// the given `payload` of `bits` bits
+fn crafted_data(code_lengths: &[(u32, u8)], payload: &[u8], bits: u64) -> Vec<u8> {
+  let code_lengths = code_lengths.iter().map(|&(value, bits)| CodeLengthEntry { value, bits }).collect();
+  let mut crafted = Header::new(SymbolKind::Bytes, None, code_lengths, bits).as_bytes();
+  crafted.extend_from_slice(payload);
+  return crafted;
+}
+
+#[test]
+fn invalid_code_lengths_are_refused() {
+  // three codes of one bit can't make up a prefix code
+  let crafted = crafted_data(&[(0x61, 1), (0x62, 1), (0x63, 1)], &[0], 3);
+  assert!(matches!(HuffmanCoding::decompress(&crafted), Err(HuffmanError::BadHeader(_))));
+  let result = HuffmanDecoder::new(&crafted[..]).decompress(&mut Vec::new());
+  assert!(matches!(result, Err(HuffmanError::BadHeader(_))));
+
+  // two codes of two bits leave 10 and 11 without a symbol
+  let crafted = crafted_data(&[(0x61, 2), (0x62, 2)], &[0b0111_0000], 4);
+  assert!(matches!(HuffmanCoding::decompress(&crafted), Err(HuffmanError::InvalidCode)));
+  let result = HuffmanDecoder::new(&crafted[..]).decompress(&mut Vec::new());
+  assert!(matches!(result, Err(HuffmanError::InvalidCode)));
+
+  // but the codes that are there still decode
+  let crafted = crafted_data(&[(0x61, 2), (0x62, 2)], &[0b0001_0100], 6);
+  assert_eq!(HuffmanCoding::decompress(&crafted).unwrap(), b"abb");
+}
+
+#[test]
+fn round_trip_length_limited_fibonacci_frequencies() {
+  let alphabet: Vec<char> = ('a'..='y').collect();
+  let mut counts: Vec<u64> = vec![1, 1];
+  while counts.len() < alphabet.len() {
+    counts.push(counts[counts.len() - 1] + counts[counts.len() - 2]);
+  }
+
+  let text = text_with_counts(&alphabet, &counts);
+  let tree = HuffmanTree::from_symbols_with_max_code_length(text.chars(), 15).unwrap();
+  assert_eq!(max_depth(&tree.trunk), 15);
+
+  let compressed = HuffmanCoding::compress_str(&text, &tree).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+}
+
+#[test]
+fn length_limit_keeps_short_enough_trees() {
+  let text = "This is a short test. Hello, Huffman coding!";
+  let limited = HuffmanTree::from_symbols_with_max_code_length(text.chars(), 32).unwrap();
+  let limited = HuffmanCoding::compress_str(text, &limited).unwrap();
+  let unlimited = HuffmanCoding::compress_str(text, &HuffmanTree::new(text)).unwrap();
+
+  assert_eq!(limited, unlimited);
+}
+
+#[test]
+fn length_limit_must_fit_every_symbol() {
+  let result = HuffmanTree::from_symbols_with_max_code_length("abcde".chars(), 2);
+  assert!(matches!(result, Err(HuffmanError::InvalidCodeLengthLimit(2))));
+}
+
+#[test]
+fn codes_longer_than_32_bits_are_refused() {
+  // a hand grown tree with one code of 40 bits
+  let mut trunk = HuffmanTreeNode { left: None, right: None, value: Some('z') };
+  for c in ('a'..='z').cycle().take(40) {
+    let leaf = HuffmanTreeNode { left: None, right: None, value: Some(c) };
+    trunk = HuffmanTreeNode {
+      left: Some(Box::new(leaf)),
+      right: Some(Box::new(trunk)),
+      value: None,
+    };
+  }
+
+  let tree = HuffmanTree { trunk };
+  let result = HuffmanCoding::compress_str("abc", &tree);
+  assert!(matches!(result, Err(HuffmanError::CodeTooLong)));
+}
+
+#[test]
+fn round_trip_skewed_large_alphabet() {
+  // a few hundred distinct chars with zipf-like frequencies
+  let alphabet: Vec<char> = (0x4e00..0x4e00 + 300)
+    .map(|n| char::from_u32(n).unwrap())
+    .collect();
+  let counts: Vec<u64> = (0..300).map(|i| 1 + 300 / (i + 1)).collect();
+
+  let text = text_with_counts(&alphabet, &counts);
+  let tree = HuffmanTree::new(&text);
+  assert!(max_depth(&tree.trunk) > 8);
+
+  let compressed = HuffmanCoding::compress_str(&text, &tree).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+}
+
+#[test]
+fn equal_frequencies_keep_their_order() {
+  // every symbol and branch ties, so the tree only depends on the order
+  // they were put in
+  let tree = HuffmanTree::new("abcd");
+  assert_eq!(symbol_at(&tree.trunk, "00"), Some('c'));
+  assert_eq!(symbol_at(&tree.trunk, "01"), Some('d'));
+  assert_eq!(symbol_at(&tree.trunk, "10"), Some('a'));
+  assert_eq!(symbol_at(&tree.trunk, "11"), Some('b'));
+
+  let tree = HuffmanTree::new("aabbccd");
+  assert_eq!(symbol_at(&tree.trunk, "00"), Some('d'));
+  assert_eq!(symbol_at(&tree.trunk, "01"), Some('c'));
+  assert_eq!(symbol_at(&tree.trunk, "10"), Some('a'));
+  assert_eq!(symbol_at(&tree.trunk, "11"), Some('b'));
+}
+
+#[test]
+fn round_trip_huge_alphabet() {
+  // tens of thousands of distinct symbols, which took ages to grow a tree
+  // for when every node was inserted into a sorted Vec
+  let symbols: Vec<u32> = (0..200_000u64).map(|n| (n * 7919 % 50_021) as u32).collect();
+  let tree = HuffmanTree::from_symbols(symbols.clone());
+  let compressed = HuffmanCoding::encode(symbols.clone(), &tree).unwrap();
+
+  assert_eq!(HuffmanCoding::decode::<u32>(&compressed).unwrap(), symbols);
+}
+
+#[test]
+fn round_trip_all_byte_values() {
+  let mut bytes: Vec<u8> = Vec::new();
+  for byte in 0..=255u8 {
+    // every byte value, the lower ones far more often than the higher ones
+    for _ in 0..(1 + 1024 / (byte as usize + 1)) {
+      bytes.push(byte);
+    }
+  }
+
+  let tree = HuffmanTree::from_bytes(&bytes);
+  assert!(max_depth(&tree.trunk) > 8);
+
+  let compressed = HuffmanCoding::compress(&bytes, &tree).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+}
+
+#[test]
+fn round_trip_u16_symbols() {
+  let symbols: Vec<u16> = (0..2000).map(|n| (n * n % 517) as u16).collect();
+  let tree = HuffmanTree::from_symbols(symbols.clone());
+  let compressed = HuffmanCoding::encode(symbols.clone(), &tree).unwrap();
+
+  assert_eq!(HuffmanCoding::decode::<u16>(&compressed).unwrap(), symbols);
+}
+
+// compress `bytes` in every way there is and check they all come back
+fn assert_round_trips_every_way(bytes: &[u8]) {
+  let compressed = HuffmanCoding::compress(bytes, &HuffmanTree::from_bytes(bytes)).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  let options = BlockOptions { block_size: 3, ..BlockOptions::default() };
+  let compressed = HuffmanCoding::compress_blocks(bytes, &options).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.compress(&mut Cursor::new(bytes)).unwrap();
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&encoder.into_inner()[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.compress_blocks(&mut &bytes[..]).unwrap();
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&encoder.into_inner()[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+
+  let compressed = HuffmanCoding::compress_adaptive(bytes).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.compress_adaptive(&mut &bytes[..]).unwrap();
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&encoder.into_inner()[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+
+  let compressed = HuffmanCoding::compress_with_contexts(bytes).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  if let Ok(text) = std::str::from_utf8(bytes) {
+    let compressed = HuffmanCoding::compress_words(text).unwrap();
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+  }
+
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.compress_with_contexts(&mut Cursor::new(bytes)).unwrap();
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&encoder.into_inner()[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+
+  let frequencies = HuffmanTree::count_char_frequencies(bytes.iter().copied());
+  for codec in Codec::ALL {
+    let compressed = HuffmanCoding::encode_with_codec(bytes, &frequencies, codec, None).unwrap();
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+  }
+
+  for arity in [3, 4, 256] {
+    let compressed = HuffmanCoding::compress_nary(bytes, arity).unwrap();
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+  }
+
+  let compressed = HuffmanCoding::encode_transformed(bytes.iter().copied(), None).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.compress_transformed(&mut Cursor::new(bytes)).unwrap();
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&encoder.into_inner()[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+}
+
+#[test]
+fn round_trip_empty_input() {
+  assert_round_trips_every_way(b"");
+
+  let tree = HuffmanTree::new("");
+  assert!(tree.trunk.left.is_none() && tree.trunk.right.is_none());
+
+  let compressed = HuffmanCoding::compress_str("", &tree).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), b"");
+
+  let tree = HuffmanTree::<u16>::from_symbols_with_max_code_length([], 4).unwrap();
+  let compressed = HuffmanCoding::encode(Vec::<u16>::new(), &tree).unwrap();
+  assert_eq!(HuffmanCoding::decode::<u16>(&compressed).unwrap(), Vec::<u16>::new());
+}
+
+#[test]
+fn round_trip_single_symbol() {
+  assert_round_trips_every_way(b"z");
+  assert_round_trips_every_way(&[0; 1000]);
+
+  // the symbol gets the 1 bit code 0
+  let text = "a".repeat(1000);
+  let tree = HuffmanTree::new(&text);
+  assert_eq!(symbol_at(&tree.trunk, "0"), Some('a'));
+  assert!(tree.trunk.right.is_none());
+
+  let compressed = HuffmanCoding::compress_str(&text, &tree).unwrap();
+  let doubled = HuffmanCoding::compress_str(&text.repeat(2), &tree).unwrap();
+  assert_eq!(doubled.len() - compressed.len(), 1000 / 8);
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+}
+
+#[test]
+fn round_trip_blocks_of_a_single_symbol() {
+  // a block of one symbol between blocks of many
+  let mut bytes: Vec<u8> = b"abcdefgh".repeat(10);
+  bytes.extend([b'x'; 80]);
+  bytes.extend(b"abcdefgh".repeat(10));
+
+  let options = BlockOptions { block_size: 80, ..BlockOptions::default() };
+  let compressed = HuffmanCoding::compress_blocks(&bytes, &options).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+}
+
+#[test]
+fn round_trip_u32_symbols() {
+  // symbols below 256 are indexed in an array, the others in a hash map,
+  // and symbols from 200 on are in both
+  for offset in [0, 200, 0x10_0000, u32::MAX - 100] {
+    let symbols: Vec<u32> = (0..2000).map(|n: u32| offset.wrapping_add(n * n % 101)).collect();
+    let tree = HuffmanTree::from_symbols(symbols.clone());
+    let compressed = HuffmanCoding::encode(symbols.clone(), &tree).unwrap();
+    assert_eq!(HuffmanCoding::decode::<u32>(&compressed).unwrap(), symbols);
+  }
+}
+
+#[test]
+fn u32_symbols_not_in_the_tree_are_refused() {
+  let tree = HuffmanTree::from_symbols(vec![1u32, 2, 300, 70_000]);
+  for missing in [3, 255, 256, 70_001] {
+    let result = HuffmanCoding::encode(vec![1, 300, missing], &tree);
+    assert!(matches!(result, Err(HuffmanError::SymbolNotInTree(value)) if value == missing));
+  }
+}
+
+#[test]
+fn round_trip_every_checksum() {
+  let text = "This is a short test. Hello, Huffman coding!";
+  let tree = HuffmanTree::new(text);
+  for checksum in [None, Some(ChecksumKind::Crc32), Some(ChecksumKind::XxHash32)] {
+    let compressed = HuffmanCoding::encode_with_checksum(text.chars(), &tree, checksum).unwrap();
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+  }
+}
+
+#[test]
+fn checksums_match_known_answers() {
+  assert_eq!(Crc32::checksum(b""), 0);
+  assert_eq!(Crc32::checksum(b"123456789"), 0xcbf4_3926);
+  assert_eq!(XxHash32::checksum(b""), 0x02cc_5d05);
+  assert_eq!(XxHash32::checksum(b"abc"), 0x32d1_53ff);
+  // long enough for the stripes of 16 bytes
+  assert_eq!(XxHash32::checksum(b"Nobody inspects the spammish repetition"), 0xe229_3b2f);
+}
+
+#[test]
+fn checksums_dont_depend_on_the_chunks() {
+  let bytes = include_bytes!("../data/short_test.txt").repeat(5);
+  let whole = [Crc32::checksum(&bytes), XxHash32::checksum(&bytes)];
+  for chunk_size in [1, 3, 15, 16, 17, 100] {
+    for (kind, whole) in [ChecksumKind::Crc32, ChecksumKind::XxHash32].into_iter().zip(whole) {
+      let mut checksum = Checksum::new(kind);
+      for chunk in bytes.chunks(chunk_size) {
+        checksum.update(chunk);
+      }
+
+      assert_eq!(checksum.finish(), whole);
+    }
+  }
+
+  // the known answer of a string split in the middle of a stripe
+  let mut checksum = XxHash32::new();
+  checksum.update(b"Nobody inspects the");
+  checksum.update(b" spammish repetition");
+  assert_eq!(checksum.finish(), 0xe229_3b2f);
+}
+
+#[test]
+fn flipped_payload_bits_fail_the_checksum() {
+  // every byte has the same code length, so a flipped bit swaps one byte
+  let bytes: Vec<u8> = (0..=255u8).cycle().take(1024).collect();
+  let tree = HuffmanTree::from_bytes(&bytes);
+  let compressed = HuffmanCoding::compress(&bytes, &tree).unwrap();
+
+  let mut damaged = compressed.clone();
+  let payload_byte = damaged.len() - 4 - 100;
+  damaged[payload_byte] ^= 0b0001_0000;
+
+  let result = HuffmanCoding::decompress(&damaged);
+  assert!(matches!(result, Err(HuffmanError::ChecksumMismatch)));
+}
+
+#[test]
+fn round_trip_streams_over_several_chunks() {
+  // more than two chunks of 64 KiB, with a few bytes far more common
+  let bytes: Vec<u8> = (0..150_000u64).map(|n| (n * n % 37 % 11) as u8).collect();
+
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.set_checksum(Some(ChecksumKind::XxHash32));
+  encoder.compress(&mut Cursor::new(&bytes)).unwrap();
+  let compressed = encoder.into_inner();
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+
+  // both ways of decompressing read the same format
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  let compressed = HuffmanCoding::compress(&bytes, &HuffmanTree::from_bytes(&bytes)).unwrap();
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+}
+
+#[test]
+fn streamed_text_comes_out_as_utf8() {
+  let text = "Grüße aus Köln, 東京 und überall";
+  let compressed = HuffmanCoding::compress_str(text, &HuffmanTree::new(text)).unwrap();
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, text.as_bytes());
+}
+
+#[test]
+fn truncated_streams_are_refused() {
+  let text = "This is a short test. Hello, Huffman coding!";
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.compress(&mut Cursor::new(text)).unwrap();
+  let compressed = encoder.into_inner();
+
+  for length in [5, 10, compressed.len() - 1] {
+    let result = HuffmanDecoder::new(&compressed[..length]).decompress(&mut Vec::new());
+    assert!(matches!(result, Err(HuffmanError::TruncatedInput)));
+  }
+}
+
+// source code followed by base64, where the common bytes of one part are
+// rare or missing in the other
+fn code_then_base64() -> Vec<u8> {
+  let mut bytes: Vec<u8> = Vec::new();
+  for i in 0..400 {
+    bytes.extend_from_slice(format!("fn f{i}() {{ return {i}; }}\n").as_bytes());
+  }
+
+  let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  for i in 0..10_000usize {
+    bytes.push(alphabet[i * 7919 % 64]);
+  }
+
+  return bytes;
+}
+
+#[test]
+fn round_trip_blocks() {
+  let bytes = code_then_base64();
+  for reuse_trees in [false, true] {
+    let options = BlockOptions { block_size: 1000, reuse_trees, ..BlockOptions::default() };
+    let compressed = HuffmanCoding::compress_blocks(&bytes, &options).unwrap();
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+    let mut decompressed: Vec<u8> = Vec::new();
+    HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+    assert_eq!(decompressed, bytes);
+  }
+}
+
+#[test]
+fn blocks_follow_changing_frequencies() {
+  let bytes = code_then_base64();
+  let options = BlockOptions { block_size: 4096, ..BlockOptions::default() };
+  let blocks = HuffmanCoding::compress_blocks(&bytes, &options).unwrap();
+  let whole = HuffmanCoding::compress(&bytes, &HuffmanTree::from_bytes(&bytes)).unwrap();
+
+  assert!(blocks.len() < whole.len());
+}
+
+#[test]
+fn reusing_trees_saves_space() {
+  // the same distribution all the way through, so every block after the
+  // first can use the codes of the first
+  let text = "the quick brown fox jumps over the lazy dog. ".repeat(400);
+  let reused = BlockOptions { block_size: 900, ..BlockOptions::default() };
+  let not_reused = BlockOptions { reuse_trees: false, ..reused };
+
+  let reused = HuffmanCoding::encode_blocks(text.chars(), &reused).unwrap();
+  let not_reused = HuffmanCoding::encode_blocks(text.chars(), &not_reused).unwrap();
+  assert!(reused.len() < not_reused.len());
+  assert_eq!(HuffmanCoding::decompress(&reused).unwrap(), text.as_bytes());
+}
+
+#[test]
+fn round_trip_blocks_from_a_reader_that_cant_seek() {
+  let bytes = code_then_base64();
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.set_block_size(3000);
+  // a slice can only be read forwards
+  encoder.compress_blocks(&mut &bytes[..]).unwrap();
+
+  let compressed = encoder.into_inner();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+}
+
+#[test]
+fn blocks_must_hold_symbols() {
+  let options = BlockOptions { block_size: 0, ..BlockOptions::default() };
+  let result = HuffmanCoding::compress_blocks(b"abc", &options);
+  assert!(matches!(result, Err(HuffmanError::InvalidBlockSize)));
+}
+
+// bytes that every value is about as common in, so coding them doesn't pay
+fn noise(length: usize) -> Vec<u8> {
+  let mut state: u32 = 0x2545_f491;
+  return (0..length).map(|_| {
+    state ^= state << 13;
+    state ^= state >> 17;
+    state ^= state << 5;
+    (state >> 24) as u8
+  }).collect();
+}
+
+#[test]
+fn short_text_is_stored() {
+  let text = "This is a short test. Hello, Huffman coding!";
+  let compressed = HuffmanCoding::compress_str(text, &HuffmanTree::new(text)).unwrap();
+
+  // the header, the text and the checksum, with nothing else
+  assert_ne!(compressed[5] & FLAG_STORED, 0);
+  assert_eq!(compressed.len(), text.len() + 18);
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+  assert_eq!(HuffmanCoding::decode::<char>(&compressed).unwrap(), text.chars().collect::<Vec<char>>());
+
+  // text that does get smaller isn't stored
+  let text = text.repeat(100);
+  let compressed = HuffmanCoding::compress_str(&text, &HuffmanTree::new(&text)).unwrap();
+  assert_eq!(compressed[5] & FLAG_STORED, 0);
+  assert!(compressed.len() < text.len());
+}
+
+#[test]
+fn round_trip_stored_noise() {
+  let bytes = noise(100_000);
+  assert_round_trips_every_way(&bytes);
+
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.compress(&mut Cursor::new(&bytes)).unwrap();
+  let compressed = encoder.into_inner();
+  assert_ne!(compressed[5] & FLAG_STORED, 0);
+  assert_eq!(compressed.len(), bytes.len() + 19);
+
+  // both ways of compressing store it the same way
+  let tree = HuffmanTree::from_bytes(&bytes);
+  assert_eq!(HuffmanCoding::compress(&bytes, &tree).unwrap(), compressed);
+}
+
+#[test]
+fn round_trip_stored_custom_symbols() {
+  // a few symbols out of very many, so their dictionary is large
+  let symbols: Vec<u32> = (0..20u32).map(|n| n * 1_000_003).collect();
+  let tree = HuffmanTree::from_symbols(symbols.clone());
+  let compressed = HuffmanCoding::encode(symbols.clone(), &tree).unwrap();
+
+  assert_ne!(compressed[5] & FLAG_STORED, 0);
+  assert_eq!(HuffmanCoding::decode::<u32>(&compressed).unwrap(), symbols);
+}
+
+#[test]
+fn blocks_that_dont_get_smaller_are_stored() {
+  let mut bytes = code_then_base64();
+  let text_length = bytes.len();
+  bytes.extend(noise(8192));
+  bytes.extend(code_then_base64());
+
+  let options = BlockOptions { block_size: 4096, ..BlockOptions::default() };
+  let compressed = HuffmanCoding::compress_blocks(&bytes, &options).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+
+  // the noise costs little more than itself, the text is still compressed
+  assert!(compressed.len() < 2 * text_length + 8192);
+}
+
+#[test]
+fn flipped_stored_bits_fail_the_checksum() {
+  let bytes = noise(1000);
+  let mut compressed = HuffmanCoding::compress(&bytes, &HuffmanTree::from_bytes(&bytes)).unwrap();
+  assert_ne!(compressed[5] & FLAG_STORED, 0);
+
+  let payload_byte = compressed.len() - 4 - 500;
+  compressed[payload_byte] ^= 0b0000_0100;
+
+  let result = HuffmanCoding::decompress(&compressed);
+  assert!(matches!(result, Err(HuffmanError::ChecksumMismatch)));
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  let result = HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed);
+  assert!(matches!(result, Err(HuffmanError::ChecksumMismatch)));
+}
+
+#[test]
+fn round_trip_adaptive() {
+  let bytes = code_then_base64();
+  let compressed = HuffmanCoding::compress_adaptive(&bytes).unwrap();
+  assert_ne!(compressed[5] & FLAG_ADAPTIVE, 0);
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+
+  // within a few percent of a tree grown beforehand
+  let tree = HuffmanTree::from_bytes(&bytes);
+  let static_length = HuffmanCoding::compress(&bytes, &tree).unwrap().len();
+  assert!(compressed.len() < static_length * 105 / 100);
+
+  // data that fits in a single chunk comes out the same from a stream
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.compress_adaptive(&mut &bytes[..]).unwrap();
+  assert_eq!(encoder.into_inner(), compressed);
+}
+
+#[test]
+fn round_trip_adaptive_streams_over_several_chunks() {
+  // the frequencies change from chunk to chunk, and the tree with them
+  let mut bytes: Vec<u8> = Vec::new();
+  for part in 0..5u64 {
+    bytes.extend((0..40_000u64).map(|n| (n * n % 31 % (3 + part * 7)) as u8 + part as u8 * 40));
+  }
+
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.set_checksum(Some(ChecksumKind::XxHash32));
+  encoder.compress_adaptive(&mut &bytes[..]).unwrap();
+  let compressed = encoder.into_inner();
+  assert!(compressed.len() < bytes.len() * 3 / 4);
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+}
+
+#[test]
+fn round_trip_adaptive_symbols() {
+  let text = "Grüße, 世界! Adaptive Huffman coding doesn't need a tree up front.";
+  let compressed = HuffmanCoding::encode_adaptive(text.chars(), None).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+
+  let symbols: Vec<u16> = (0..5000).map(|n| (n * n % 1009) as u16).collect();
+  let compressed = HuffmanCoding::encode_adaptive(symbols.clone(), Some(ChecksumKind::Crc32)).unwrap();
+  assert_eq!(HuffmanCoding::decode::<u16>(&compressed).unwrap(), symbols);
+}
+
+#[test]
+fn small_messages_are_smaller_adaptive() {
+  // a short message of a few symbols, where the table of a tree is a big
+  // part of the output
+  let message = b"0001000100000011000100010000001100010001";
+  let adaptive = HuffmanCoding::compress_adaptive(message).unwrap();
+  let with_tree = HuffmanCoding::compress(message, &HuffmanTree::from_bytes(message)).unwrap();
+
+  assert!(adaptive.len() < with_tree.len());
+  assert!(adaptive.len() < message.len());
+  assert_eq!(HuffmanCoding::decompress(&adaptive).unwrap(), message);
+}
+
+#[test]
+fn damaged_adaptive_data_is_refused() {
+  let bytes = code_then_base64();
+  let compressed = HuffmanCoding::compress_adaptive(&bytes).unwrap();
+
+  let mut damaged = compressed.clone();
+  let payload_byte = damaged.len() / 2;
+  damaged[payload_byte] ^= 0b0010_0000;
+  assert!(HuffmanCoding::decompress(&damaged).is_err());
+
+  for length in [10, compressed.len() / 2, compressed.len() - 1] {
+    let result = HuffmanCoding::decompress(&compressed[..length]);
+    assert!(matches!(result, Err(HuffmanError::TruncatedInput)));
+
+    let mut decompressed: Vec<u8> = Vec::new();
+    let result = HuffmanDecoder::new(&compressed[..length]).decompress(&mut decompressed);
+    assert!(matches!(result, Err(HuffmanError::TruncatedInput)));
+  }
+}
+
+// text of words picked at random, where every letter says a lot about the
+// next one
+fn random_words(word_count: usize) -> Vec<u8> {
+  let words = ["the", "quick", "brown", "fox", "jumps", "over", "lazy", "dog", "huffman", "coding"];
+  let mut text: Vec<u8> = Vec::new();
+  for byte in noise(word_count) {
+    text.extend_from_slice(words[byte as usize % words.len()].as_bytes());
+    text.push(b' ');
+  }
+
+  return text;
+}
+
+#[test]
+fn round_trip_contexts() {
+  let text = random_words(20_000);
+  let compressed = HuffmanCoding::compress_with_contexts(&text).unwrap();
+  assert_ne!(compressed[5] & FLAG_CONTEXTS, 0);
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text);
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, text);
+
+  // far smaller than with a single tree for all of it
+  let tree = HuffmanTree::from_bytes(&text);
+  let single_tree_length = HuffmanCoding::compress(&text, &tree).unwrap().len();
+  assert!(compressed.len() < single_tree_length / 2);
+
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.set_max_code_length(Some(12));
+  encoder.compress_with_contexts(&mut Cursor::new(&text)).unwrap();
+  let streamed = encoder.into_inner();
+  assert!(streamed.len() < single_tree_length / 2);
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&streamed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, text);
+}
+
+#[test]
+fn rare_contexts_share_the_fallback_table() {
+  // only `a`, `b` and `c` come up often enough to pay for a table of their
+  // own
+  let mut bytes: Vec<u8> = b"abc".repeat(2000);
+  bytes.extend_from_slice(b"0123456789");
+
+  let compressed = HuffmanCoding::compress_with_contexts(&bytes).unwrap();
+  let (header, _) = Header::from_bytes(&compressed).unwrap();
+  let contexts: Vec<u32> = header.contexts.unwrap().iter().map(|context| context.value).collect();
+  assert_eq!(contexts, [b'a' as u32, b'b' as u32, b'c' as u32]);
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+}
+
+#[test]
+fn round_trip_context_symbols() {
+  let text = "Grüße, 世界! ".repeat(50) + "Order-1 contexts work for chars too.";
+  let compressed = HuffmanCoding::encode_with_contexts(text.chars(), None).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+
+  let symbols: Vec<u16> = (0..5000).map(|n| (n * n % 1009 % 40 * 1000) as u16).collect();
+  let compressed = HuffmanCoding::encode_with_contexts(symbols.clone(), Some(ChecksumKind::Crc32)).unwrap();
+  assert_eq!(HuffmanCoding::decode::<u16>(&compressed).unwrap(), symbols);
+}
+
+#[test]
+fn damaged_context_data_is_refused() {
+  let text = random_words(2000);
+  let compressed = HuffmanCoding::compress_with_contexts(&text).unwrap();
+
+  let mut damaged = compressed.clone();
+  let payload_byte = damaged.len() - 100;
+  damaged[payload_byte] ^= 0b0000_0100;
+  assert!(HuffmanCoding::decompress(&damaged).is_err());
+
+  let result = HuffmanCoding::decompress(&compressed[..compressed.len() - 1]);
+  assert!(matches!(result, Err(HuffmanError::TruncatedInput)));
+}
+
+#[test]
+fn round_trip_words() {
+  let text = String::from_utf8(random_words(20_000)).unwrap();
+  let compressed = HuffmanCoding::compress_words(&text).unwrap();
+  assert_ne!(compressed[5] & FLAG_WORDS, 0);
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, text.as_bytes());
+
+  // far smaller than coding it char by char
+  let by_chars = HuffmanCoding::compress_str(&text, &HuffmanTree::new(&text)).unwrap();
+  assert!(compressed.len() < by_chars.len() / 2);
+}
+
+#[test]
+fn rare_words_are_spelled_out() {
+  let text = "The cat and the dog, the end.\n".repeat(40) + "Zyzzyva! Grüße, 世界.";
+  let compressed = HuffmanCoding::compress_words_with_checksum(&text, Some(ChecksumKind::XxHash32)).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+
+  let (header, _) = Header::from_bytes(&compressed).unwrap();
+  let words = header.words.unwrap().words;
+  for word in ["The", "cat", "the", " ", ",", ".", "\n"] {
+    assert!(words.iter().any(|w| w == word), "{word:?} should be in the dictionary");
+  }
+
+  for word in ["Zyzzyva", "Grüße", "世界", "!"] {
+    assert!(!words.iter().any(|w| w == word), "{word:?} should be spelled out");
+  }
+}
+
+#[test]
+fn damaged_word_data_is_refused() {
+  let text = String::from_utf8(random_words(2000)).unwrap() + "Zyzzyva";
+  let compressed = HuffmanCoding::compress_words(&text).unwrap();
+
+  let mut damaged = compressed.clone();
+  let payload_byte = damaged.len() - 100;
+  damaged[payload_byte] ^= 0b0100_0000;
+  assert!(HuffmanCoding::decompress(&damaged).is_err());
+
+  for length in [20, compressed.len() - 1] {
+    let result = HuffmanCoding::decompress(&compressed[..length]);
+    assert!(matches!(result, Err(HuffmanError::TruncatedInput)));
+  }
+}
+
+// bytes where `a` is far more common than a whole bit's worth
+fn skewed_bytes() -> Vec<u8> {
+  let mut bytes: Vec<u8> = Vec::new();
+  for byte in noise(20_000) {
+    bytes.push(if byte < 240 { b'a' } else { b'b' + byte % 8 });
+  }
+
+  return bytes;
+}
+
+#[test]
+fn round_trip_arithmetic() {
+  let bytes = skewed_bytes();
+  let frequencies = HuffmanTree::count_char_frequencies(bytes.iter().copied());
+  let compressed = HuffmanCoding::encode_with_codec(
+    &bytes,
+    &frequencies,
+    Codec::Arithmetic,
+    Some(ChecksumKind::Crc32)
+  ).unwrap();
+  assert_ne!(compressed[5] & FLAG_CODEC, 0);
+  assert_eq!(compressed[8], Codec::Arithmetic as u8);
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, bytes);
+
+  // Huffman coding spends a whole bit on every `a`
+  let huffman = HuffmanCoding::encode_with_codec(&bytes, &frequencies, Codec::Huffman, None).unwrap();
+  assert!(compressed.len() < huffman.len() * 2 / 3);
+}
+
+#[test]
+fn best_codec_is_the_smallest() {
+  for bytes in [skewed_bytes(), random_words(5000), noise(1000)] {
+    let frequencies = HuffmanTree::count_char_frequencies(bytes.iter().copied());
+    let smallest = Codec::ALL.iter()
+      .map(|codec| HuffmanCoding::encode_with_codec(&bytes, &frequencies, *codec, None).unwrap().len())
+      .min()
+      .unwrap();
+
+    let compressed = HuffmanCoding::encode_with_best_codec(&bytes, None).unwrap();
+    assert_eq!(compressed.len(), smallest);
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+  }
+
+  // Huffman coding is beaten by all the others
+  let compressed = HuffmanCoding::compress_with_best_codec(&skewed_bytes()).unwrap();
+  assert_ne!(compressed[5] & FLAG_CODEC, 0);
+}
+
+#[test]
+fn round_trip_arithmetic_symbols() {
+  let text = "Grüße, 世界! ".repeat(50);
+  let chars: Vec<char> = text.chars().collect();
+  let frequencies = HuffmanTree::count_char_frequencies(chars.iter().copied());
+  let compressed = HuffmanCoding::encode_with_codec(&chars, &frequencies, Codec::Arithmetic, None).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+
+  let symbols: Vec<u16> = (0..5000).map(|n| (n * n % 1009 % 40 * 1000) as u16).collect();
+  let compressed = HuffmanCoding::encode_with_best_codec(&symbols, Some(ChecksumKind::XxHash32)).unwrap();
+  assert_eq!(HuffmanCoding::decode::<u16>(&compressed).unwrap(), symbols);
+}
+
+#[test]
+fn high_frequencies_are_scaled_down() {
+  let frequencies = [
+    CharFrequencyPair { value: b'a', count: 1 << 40 },
+    CharFrequencyPair { value: b'b', count: 1 },
+  ];
+  let bytes = b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab".repeat(10);
+  let compressed = HuffmanCoding::encode_with_codec(&bytes, &frequencies, Codec::Arithmetic, None).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+  let (header, _) = Header::from_bytes(&compressed).unwrap();
+  let counts: Vec<u64> = header.frequencies.iter().map(|pair| pair.count).collect();
+  assert!(counts[0] + counts[1] <= 1 << 24);
+  assert_eq!(counts[1], 1);
+
+  // a symbol without a frequency has no share to code it with
+  let result = HuffmanCoding::encode_with_codec(b"abc", &frequencies, Codec::Arithmetic, None);
+  assert!(matches!(result, Err(HuffmanError::SymbolNotInTree(0x63))));
+}
+
+#[test]
+fn damaged_arithmetic_data_is_refused() {
+  let bytes = skewed_bytes();
+  let frequencies = HuffmanTree::count_char_frequencies(bytes.iter().copied());
+  let compressed = HuffmanCoding::encode_with_codec(
+    &bytes,
+    &frequencies,
+    Codec::Arithmetic,
+    Some(ChecksumKind::Crc32)
+  ).unwrap();
+
+  let mut damaged = compressed.clone();
+  let payload_byte = damaged.len() - 100;
+  damaged[payload_byte] ^= 0b0001_0000;
+  assert!(HuffmanCoding::decompress(&damaged).is_err());
+
+  for length in [20, compressed.len() - 1] {
+    let result = HuffmanCoding::decompress(&compressed[..length]);
+    assert!(matches!(result, Err(HuffmanError::TruncatedInput)));
+  }
+}
+
+// the bytes of a header of the `codec` with the `frequencies` of bytes and
+// `symbol_count` symbols, followed by the `payload`
+fn crafted_codec_data(codec: Codec, frequencies: &[(u32, u64)], symbol_count: u64, payload: &[u8]) -> Vec<u8> {
+  let header = Header {
+    codec,
+    frequencies: frequencies.iter().map(|&(value, count)| CharFrequencyPair { value, count }).collect(),
+    symbol_count,
+    ..Header::new(SymbolKind::Bytes, None, Vec::new(), payload.len() as u64 * 8)
+  };
+
+  let mut crafted = header.as_bytes();
+  crafted.extend_from_slice(payload);
+  return crafted;
+}
+
+// check that the `crafted` data is refused with a BadHeader, both at once
+// and streamed
+fn assert_bad_header(crafted: &[u8]) {
+  assert!(matches!(HuffmanCoding::decompress(crafted), Err(HuffmanError::BadHeader(_))));
+  let result = HuffmanDecoder::new(crafted).decompress(&mut Vec::new());
+  assert!(matches!(result, Err(HuffmanError::BadHeader(_))));
+}
+
+#[test]
+fn crafted_arithmetic_headers_are_refused() {
+  // a single symbol takes up no bits at all, so any number of it would fit
+  assert_bad_header(&crafted_codec_data(Codec::Arithmetic, &[(0x61, 1)], 1 << 34, &[0]));
+
+  // far more symbols than a byte can hold, even of the most common one
+  let frequencies = [(0x61, (1 << 24) - 1), (0x62, 1)];
+  assert_bad_header(&crafted_codec_data(Codec::Arithmetic, &frequencies, 1 << 34, &[0]));
+
+  // a single symbol gets a Huffman code instead
+  let single = b"a".repeat(1000);
+  let frequencies = HuffmanTree::count_char_frequencies(single.iter().copied());
+  let compressed = HuffmanCoding::encode_with_codec(&single, &frequencies, Codec::Arithmetic, None).unwrap();
+  assert_eq!(compressed[5] & FLAG_CODEC, 0);
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), single);
+}
+
+#[test]
+fn round_trip_ans() {
+  let bytes = skewed_bytes();
+  let frequencies = HuffmanTree::count_char_frequencies(bytes.iter().copied());
+  let huffman = HuffmanCoding::encode_with_codec(&bytes, &frequencies, Codec::Huffman, None).unwrap();
+  for codec in [Codec::Tans, Codec::Rans] {
+    let compressed = HuffmanCoding::encode_with_codec(
+      &bytes,
+      &frequencies,
+      codec,
+      Some(ChecksumKind::Crc32)
+    ).unwrap();
+    assert_eq!(compressed[8], codec as u8);
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+    let mut decompressed: Vec<u8> = Vec::new();
+    HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+    assert_eq!(decompressed, bytes);
+
+    // nearly as small as arithmetic coding
+    assert!(compressed.len() < huffman.len() * 2 / 3);
+
+    // the frequencies add up to the size of the tables
+    let (header, _) = Header::from_bytes(&compressed).unwrap();
+    let total: u64 = header.frequencies.iter().map(|pair| pair.count).sum();
+    assert!(total.is_power_of_two());
+
+    // a single symbol would take up no bits at all, so it gets a Huffman
+    // code instead
+    let single = b"a".repeat(10_000);
+    let single_frequencies = HuffmanTree::count_char_frequencies(single.iter().copied());
+    let compressed = HuffmanCoding::encode_with_codec(&single, &single_frequencies, codec, None).unwrap();
+    assert_eq!(compressed[5] & FLAG_CODEC, 0);
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), single);
+  }
+}
+
+#[test]
+fn round_trip_ans_symbols() {
+  let text = "Grüße, 世界! ".repeat(50);
+  let chars: Vec<char> = text.chars().collect();
+  let frequencies = HuffmanTree::count_char_frequencies(chars.iter().copied());
+
+  // a few thousand different symbols need larger tables
+  let symbols: Vec<u16> = (0..20_000).map(|n| (n * n % 5003) as u16).collect();
+  let symbol_frequencies = HuffmanTree::count_char_frequencies(symbols.iter().copied());
+  for codec in [Codec::Tans, Codec::Rans] {
+    let compressed = HuffmanCoding::encode_with_codec(&chars, &frequencies, codec, None).unwrap();
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+
+    let compressed = HuffmanCoding::encode_with_codec(&symbols, &symbol_frequencies, codec, None).unwrap();
+    assert_eq!(HuffmanCoding::decode::<u16>(&compressed).unwrap(), symbols);
+  }
+
+  // every symbol needs a share of the tables, which can't be that large
+  let symbols: Vec<u32> = (0..1 << 20).collect();
+  let frequencies = HuffmanTree::count_char_frequencies(symbols.iter().copied());
+  let result = HuffmanCoding::encode_with_codec(&symbols, &frequencies, Codec::Tans, None);
+  assert!(matches!(result, Err(HuffmanError::TooManySymbols)));
+}
+
+#[test]
+fn damaged_ans_data_is_refused() {
+  let bytes = random_words(2000);
+  let frequencies = HuffmanTree::count_char_frequencies(bytes.iter().copied());
+  for codec in [Codec::Tans, Codec::Rans] {
+    let compressed = HuffmanCoding::encode_with_codec(&bytes, &frequencies, codec, None).unwrap();
+
+    // the states don't end up where they started, even without a checksum
+    let mut damaged = compressed.clone();
+    let payload_byte = damaged.len() - 100;
+    damaged[payload_byte] ^= 0b0000_0010;
+    assert!(matches!(HuffmanCoding::decompress(&damaged), Err(HuffmanError::InvalidCode)));
+
+    for length in [20, compressed.len() - 1] {
+      let result = HuffmanCoding::decompress(&compressed[..length]);
+      assert!(matches!(result, Err(HuffmanError::TruncatedInput)));
+    }
+  }
+}
+
+#[test]
+fn crafted_ans_headers_are_refused() {
+  // a single symbol with the whole table takes up no bits at all, with tANS
+  // after a state of 12 bits and with rANS after four states of 32 bits
+  assert_bad_header(&crafted_codec_data(Codec::Tans, &[(0x61, 1 << 12)], 1 << 34, &[0; 2]));
+  assert_bad_header(&crafted_codec_data(Codec::Rans, &[(0x61, 1 << 12)], 1 << 34, &[0; 16]));
+
+  // far more symbols than the bytes can hold, even of the most common one
+  let frequencies = [(0x61, (1 << 12) - 1), (0x62, 1)];
+  for codec in [Codec::Tans, Codec::Rans] {
+    assert_bad_header(&crafted_codec_data(codec, &frequencies, 1 << 34, &[0; 16]));
+  }
+}
+
+// the code length of every one of `symbols` in `char_codes`
+fn code_lengths<T: PartialEq>(char_codes: &[CharCodePair<T>], symbols: &[T]) -> Vec<u8> {
+  return symbols.iter()
+    .map(|symbol| char_codes.iter().find(|pair| pair.value == *symbol).unwrap().bits)
+    .collect();
+}
+
+#[test]
+fn code_builders_pick_their_own_lengths() {
+  let text = text_with_counts(&['a', 'b', 'c', 'd', 'e'], &[15, 7, 6, 6, 5]);
+  let frequencies = HuffmanTree::count_char_frequencies(text.chars());
+  let alphabet = ['a', 'b', 'c', 'd', 'e'];
+
+  let huffman = CodeBuilder::Huffman.build(&frequencies).unwrap();
+  assert_eq!(code_lengths(&huffman, &alphabet), [1, 3, 3, 3, 3]);
+
+  // split into {a, b} and {c, d, e}, then {c} and {d, e}
+  let shannon_fano = CodeBuilder::ShannonFano.build(&frequencies).unwrap();
+  assert_eq!(code_lengths(&shannon_fano, &alphabet), [2, 2, 2, 3, 3]);
+
+  // ceil(log2(39 / count))
+  let shannon = CodeBuilder::Shannon.build(&frequencies).unwrap();
+  assert_eq!(code_lengths(&shannon, &alphabet), [2, 3, 3, 3, 3]);
+
+  for builder in CodeBuilder::ALL {
+    let compressed = HuffmanCoding::encode_with_codes(text.chars(), builder.build(&frequencies).unwrap(), None).unwrap();
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+  }
+
+  let bytes = random_words(2000);
+  let frequencies = HuffmanTree::count_char_frequencies(bytes.iter().copied());
+  for builder in CodeBuilder::ALL {
+    let char_codes = builder.build(&frequencies).unwrap();
+    let compressed = HuffmanCoding::encode_with_codes(bytes.clone(), char_codes, Some(ChecksumKind::Crc32)).unwrap();
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+  }
+
+  let symbols: Vec<u8> = vec![7; 100];
+  for builder in CodeBuilder::ALL {
+    let char_codes = builder.build(&HuffmanTree::count_char_frequencies(symbols.iter().copied())).unwrap();
+    assert_eq!(code_lengths(&char_codes, &[7]), [1]);
+  }
+}
+
+#[test]
+fn reports_compare_codes_with_the_entropy() {
+  let bytes = random_words(5000);
+  let frequencies = HuffmanTree::count_char_frequencies(bytes.iter().copied());
+  let reports: Vec<CodeReport> = CodeBuilder::ALL.iter()
+    .map(|builder| CodeReport::new(&frequencies, &builder.build(&frequencies).unwrap()).unwrap())
+    .collect();
+
+  // no code beats Huffman codes, and none of them the entropy
+  let entropy = reports[0].entropy;
+  assert!(entropy <= reports[0].average_bits);
+  assert!(reports[0].average_bits <= reports[1].average_bits);
+  assert!(reports[0].average_bits <= reports[2].average_bits);
+
+  // Shannon codes are less than a bit longer than the entropy
+  assert!(reports[2].redundancy() < 1.0);
+  for report in &reports {
+    assert_eq!(report.entropy, entropy);
+    assert!(report.efficiency() <= 1.0);
+  }
+
+  // every symbol of the frequencies needs a code
+  let result = CodeReport::new(&frequencies, &CodeBuilder::Huffman.build(&frequencies[1..]).unwrap());
+  assert!(matches!(result, Err(HuffmanError::SymbolNotInTree(_))));
+}
+
+#[test]
+fn code_builders_keep_to_the_longest_code() {
+  // fibonacci frequencies need a longer code for almost every symbol, and
+  // the rarest is far too rare for Shannon coding
+  let mut frequencies: Vec<CharFrequencyPair<u32>> = Vec::new();
+  let mut counts: (u64, u64) = (1, 1);
+  for value in 0..50 {
+    frequencies.push(CharFrequencyPair { value, count: counts.0 });
+    counts = (counts.1, counts.0 + counts.1);
+  }
+
+  for builder in CodeBuilder::ALL {
+    assert!(matches!(builder.build(&frequencies), Err(HuffmanError::CodeTooLong)));
+  }
+}
+
+#[test]
+fn round_trip_nary() {
+  let bytes = random_words(2000);
+  for arity in [2, 3, 4, 5, 16, 256] {
+    let compressed = HuffmanCoding::compress_nary(&bytes, arity).unwrap();
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+    let mut decompressed: Vec<u8> = Vec::new();
+    HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+    assert_eq!(decompressed, bytes);
+
+    // binary codes are just Huffman codes, and codes of 256 are a byte for
+    // every one of the few different bytes, so those are stored
+    if arity == 2 || arity == 256 {
+      assert_eq!(compressed[5] & FLAG_EXTENDED, 0);
+    } else {
+      assert_ne!(compressed[5] & FLAG_EXTENDED, 0);
+      assert_eq!(compressed[7], EXTENDED_FLAG_ARITY);
+    }
+  }
+
+  // a binary tree has the same code lengths as a HuffmanTree
+  let tree = NaryHuffmanTree::from_bytes(&bytes, 2).unwrap();
+  let compressed = HuffmanCoding::encode_nary(bytes.clone(), &tree, Some(ChecksumKind::Crc32)).unwrap();
+  assert_eq!(compressed, HuffmanCoding::compress(&bytes, &HuffmanTree::from_bytes(&bytes)).unwrap());
+
+  // with more than 256 different symbols, codes of whole bytes pay off
+  let symbols: Vec<u16> = noise(20000).chunks(2).map(|pair| pair[0] as u16 * 4 + (pair[1] % 4) as u16).collect();
+  let tree = NaryHuffmanTree::from_symbols(symbols.clone(), 256).unwrap();
+  assert_eq!(tree.digit_bits(), 8);
+  let compressed = HuffmanCoding::encode_nary(symbols.clone(), &tree, None).unwrap();
+  assert_eq!(compressed[7], EXTENDED_FLAG_ARITY);
+  assert_eq!(HuffmanCoding::decode::<u16>(&compressed).unwrap(), symbols);
+}
+
+#[test]
+fn unknown_extended_flags_are_refused() {
+  let bytes = random_words(500);
+  let mut compressed = HuffmanCoding::compress_nary(&bytes, 4).unwrap();
+  assert_ne!(compressed[5] & FLAG_EXTENDED, 0);
+
+  // a feature of a later version, which this one can't know how to read
+  compressed[7] |= 0b0100_0000;
+  assert!(matches!(
+    HuffmanCoding::decompress(&compressed),
+    Err(HuffmanError::UnsupportedFeatures(0b0100_0000))
+  ));
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  assert!(matches!(
+    HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed),
+    Err(HuffmanError::UnsupportedFeatures(0b0100_0000))
+  ));
+}
+
+#[test]
+fn nary_trees_are_padded_with_dummies() {
+  let text = text_with_counts(&['a', 'b', 'c', 'd', 'e', 'f'], &[10, 6, 5, 4, 3, 2]);
+  let tree = NaryHuffmanTree::new(&text, 3).unwrap();
+
+  // 6 symbols need one dummy for every branch to get all 3 children, and
+  // the dummy goes below the branch of the two rarest symbols
+  let mut digit_lengths = tree.digit_lengths().unwrap();
+  digit_lengths.sort();
+  assert_eq!(digit_lengths, [('a', 1), ('b', 1), ('c', 2), ('d', 2), ('e', 3), ('f', 3)]);
+
+  let mut branches = vec![&tree.trunk];
+  let mut dummy_count = 0;
+  while let Some(node) = branches.pop() {
+    assert_eq!(node.children.len(), 3);
+    for child in &node.children {
+      if !child.children.is_empty() {
+        branches.push(child);
+      } else if child.value.is_none() {
+        dummy_count += 1;
+      }
+    }
+  }
+
+  assert_eq!(dummy_count, 1);
+
+  // ternary digits take up 2 bits each
+  assert_eq!(tree.digit_bits(), 2);
+  let compressed = HuffmanCoding::encode_nary(text.chars(), &tree, None).unwrap();
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+
+  // a lone symbol still gets a code of one digit
+  let tree = NaryHuffmanTree::from_bytes(b"aaaa", 4).unwrap();
+  assert_eq!(tree.digit_lengths().unwrap(), [(b'a', 1)]);
+}
+
+#[test]
+fn nary_trees_keep_to_the_longest_code() {
+  for arity in [0, 1, 257] {
+    let result = NaryHuffmanTree::from_bytes(b"abc", arity);
+    assert!(matches!(result, Err(HuffmanError::InvalidArity(a)) if a == arity));
+  }
+
+  // fibonacci frequencies make a deep tree for any arity
+  let mut frequencies: Vec<CharFrequencyPair<u32>> = Vec::new();
+  let mut counts: (u64, u64) = (1, 1);
+  for value in 0..50 {
+    frequencies.push(CharFrequencyPair { value, count: counts.0 });
+    counts = (counts.1, counts.0 + counts.1);
+  }
+
+  let tree = NaryHuffmanTree::from_frequencies(frequencies, 3).unwrap();
+  assert!(matches!(tree.digit_lengths(), Err(HuffmanError::CodeTooLong)));
+  let result = HuffmanCoding::encode_nary(Vec::new(), &tree, None);
+  assert!(matches!(result, Err(HuffmanError::CodeTooLong)));
+}
+
+// numbered lines of the same words, over a megabyte of them
+fn numbered_lines() -> Vec<u8> {
+  let mut text: Vec<u8> = Vec::new();
+  for n in 0..30_000 {
+    text.extend_from_slice(format!("line {n}: the quick brown fox jumps over the lazy dog\n").as_bytes());
+  }
+
+  return text;
+}
+
+// the BlockHeader of every block of compressed data with FLAG_BLOCKS
+fn block_headers(compressed: &[u8]) -> Vec<BlockHeader> {
+  let (_, mut position) = Header::from_bytes(compressed).unwrap();
+  let mut block_headers: Vec<BlockHeader> = Vec::new();
+  while let (Some(block_header), byte_count) = BlockHeader::from_bytes(&compressed[position..]).unwrap() {
+    position += byte_count + block_header.bits.div_ceil(8) as usize;
+    block_headers.push(block_header);
+  }
+
+  return block_headers;
+}
+
+#[test]
+fn round_trip_transformed() {
+  let bytes = numbered_lines();
+  let huffman = HuffmanCoding::compress(&bytes, &HuffmanTree::from_bytes(&bytes)).unwrap();
+  for checksum in [None, Some(ChecksumKind::Crc32), Some(ChecksumKind::XxHash32)] {
+    let compressed = HuffmanCoding::encode_transformed(bytes.iter().copied(), checksum).unwrap();
+    assert_ne!(compressed[5] & FLAG_BLOCKS, 0);
+    assert_ne!(compressed[5] & FLAG_EXTENDED, 0);
+    assert_eq!(compressed[6], SymbolKind::Bytes as u8);
+    assert_eq!(compressed[7], EXTENDED_FLAG_TRANSFORM);
+    assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), bytes);
+
+    let mut decompressed: Vec<u8> = Vec::new();
+    HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+    assert_eq!(decompressed, bytes);
+
+    // the lines differ in little more than their numbers, which order-0
+    // codes can't tell
+    assert!(compressed.len() < huffman.len() / 10);
+  }
+
+  // every block has its own codes and primary index
+  let compressed = HuffmanCoding::compress_transformed(&bytes).unwrap();
+  let headers = block_headers(&compressed);
+  assert_eq!(headers.len(), bytes.len().div_ceil(BWT_BLOCK_SIZE));
+  for (i, block_header) in headers.iter().enumerate() {
+    let length = (bytes.len() - i * BWT_BLOCK_SIZE).min(BWT_BLOCK_SIZE);
+    assert!(block_header.code_lengths.is_some());
+    assert_eq!(block_header.transform.unwrap().length, length as u64);
+  }
+
+  // the encoder holds a block at a time and comes out the same
+  let mut encoder = HuffmanEncoder::new(Vec::new());
+  encoder.compress_transformed(&mut Cursor::new(&bytes)).unwrap();
+  assert_eq!(encoder.into_inner(), compressed);
+
+  // what the transforms can't make smaller is stored
+  let compressed = HuffmanCoding::compress_transformed(&noise(5000)).unwrap();
+  assert!(block_headers(&compressed).iter().all(|block_header| block_header.stored));
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), noise(5000));
+}
+
+#[test]
+fn round_trip_transformed_symbol_kinds() {
+  // more than a block of chars of two bytes, split between whole chars
+  let text: String = (0..100_000).map(|n| format!("zeile {n}: grüße über öl\n")).collect();
+  assert!(text.len() > BWT_BLOCK_SIZE);
+  let compressed = HuffmanCoding::encode_transformed(text.chars(), Some(ChecksumKind::Crc32)).unwrap();
+  assert_eq!(compressed[6], SymbolKind::Chars as u8);
+  assert_eq!(HuffmanCoding::decode::<char>(&compressed).unwrap(), text.chars().collect::<Vec<char>>());
+  assert_eq!(HuffmanCoding::decompress(&compressed).unwrap(), text.as_bytes());
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  HuffmanDecoder::new(&compressed[..]).decompress(&mut decompressed).unwrap();
+  assert_eq!(decompressed, text.as_bytes());
+
+  let symbols: Vec<u16> = random_words(50_000).iter().map(|byte| *byte as u16 * 300).collect();
+  let compressed = HuffmanCoding::encode_transformed(symbols.clone(), Some(ChecksumKind::Crc32)).unwrap();
+  assert_eq!(compressed[6], SymbolKind::Custom as u8);
+  assert_eq!(HuffmanCoding::decode::<u16>(&compressed).unwrap(), symbols);
+
+  let symbols: Vec<u32> = (0..300_000).map(|n: u32| (n % 1000) * 70_000).collect();
+  let compressed = HuffmanCoding::encode_transformed(symbols.clone(), None).unwrap();
+  assert!(block_headers(&compressed).len() > 1);
+  assert_eq!(HuffmanCoding::decode::<u32>(&compressed).unwrap(), symbols);
+}
+
+#[test]
+fn damaged_transformed_data_is_refused() {
+  let bytes = random_words(5000);
+  let compressed = HuffmanCoding::compress_transformed(&bytes).unwrap();
+  assert!(block_headers(&compressed)[0].transform.is_some());
+
+  // the checksum is of the bytes that come out of the transforms
+  let mut damaged = compressed.clone();
+  let payload_byte = damaged.len() - 100;
+  damaged[payload_byte] ^= 0b0000_1000;
+  assert!(HuffmanCoding::decompress(&damaged).is_err());
+
+  // without one, damage may go unnoticed, but it never panics
+  let compressed = HuffmanCoding::encode_transformed(bytes.iter().copied(), None).unwrap();
+  for payload_byte in compressed.len() - 200..compressed.len() {
+    let mut damaged = compressed.clone();
+    damaged[payload_byte] ^= 0b0100_0000;
+    if let Ok(decompressed) = HuffmanCoding::decompress(&damaged) {
+      assert_ne!(decompressed, bytes);
+    }
+  }
+
+  for length in [20, compressed.len() - 1] {
+    let result = HuffmanCoding::decompress(&compressed[..length]);
+    assert!(matches!(result, Err(HuffmanError::TruncatedInput)));
+  }
+}
+
+// the header of transformed data with one block of the given `transform`,
+// whose symbols are all RUN_B with the 1 bit code 0
+fn crafted_transformed_data(transform: BlockTransform, bits: u64) -> Vec<u8> {
+  let header = Header {
+    blocks: true,
+    transform: true,
+    ..Header::new(SymbolKind::Bytes, None, Vec::new(), 0)
+  };
+
+  let block_header = BlockHeader {
+    code_lengths: Some(vec![CodeLengthEntry { value: 1, bits: 1 }]),
+    stored: false,
+    transform: Some(transform),
+    bits,
+  };
+
+  let mut crafted = header.as_bytes();
+  crafted.extend_from_slice(&block_header.as_bytes());
+  crafted.extend(std::iter::repeat_n(0, bits.div_ceil(8) as usize));
+  crafted.push(END_OF_BLOCKS);
+  return crafted;
+}
+
+#[test]
+fn crafted_transformed_blocks_are_refused() {
+  // a block can't claim more than BWT_BLOCK_SIZE bytes, or a primary index
+  // outside of it
+  for transform in [
+    BlockTransform { length: 1 << 62, primary_index: 1 },
+    BlockTransform { length: BWT_BLOCK_SIZE as u64 + 1, primary_index: 1 },
+    BlockTransform { length: 0, primary_index: 0 },
+    BlockTransform { length: 10, primary_index: 11 },
+  ] {
+    let crafted = crafted_transformed_data(transform, 8);
+    assert!(matches!(HuffmanCoding::decompress(&crafted), Err(HuffmanError::BadHeader(_))));
+
+    let mut decompressed: Vec<u8> = Vec::new();
+    let result = HuffmanDecoder::new(&crafted[..]).decompress(&mut decompressed);
+    assert!(matches!(result, Err(HuffmanError::BadHeader(_))));
+  }
+
+  // 61 RUN_B digits stand for a run of almost 2^62 ranks of 0, far more than
+  // the 10 bytes of the block, which is refused without making room for it
+  let crafted = crafted_transformed_data(BlockTransform { length: 10, primary_index: 1 }, 61);
+  assert!(matches!(HuffmanCoding::decompress(&crafted), Err(HuffmanError::InvalidTransform)));
+
+  let mut decompressed: Vec<u8> = Vec::new();
+  let result = HuffmanDecoder::new(&crafted[..]).decompress(&mut decompressed);
+  assert!(matches!(result, Err(HuffmanError::InvalidTransform)));
+
+  // and a run that falls short of the block doesn't make it up either
+  let crafted = crafted_transformed_data(BlockTransform { length: 10, primary_index: 1 }, 2);
+  assert!(matches!(HuffmanCoding::decompress(&crafted), Err(HuffmanError::InvalidTransform)));
+
+  // transformed blocks only come with the flag that says so
+  let mut crafted = crafted_transformed_data(BlockTransform { length: 6, primary_index: 1 }, 2);
+  let header_length = Header::from_bytes(&crafted).unwrap().1;
+  let plain_header = Header { blocks: true, ..Header::new(SymbolKind::Bytes, None, Vec::new(), 0) };
+  crafted.splice(..header_length, plain_header.as_bytes());
+  assert!(matches!(HuffmanCoding::decompress(&crafted), Err(HuffmanError::BadHeader(_))));
+}